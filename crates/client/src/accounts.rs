@@ -0,0 +1,25 @@
+use anchor_lang::{ AccountDeserialize, ZeroCopy };
+
+use crate::error::{ ClientError, Result };
+
+// Decodes a Borsh account such as `Beneficiary`, `Referral`, `ReferralCode`
+// or the purchase history and receipts.
+pub fn decode<T: AccountDeserialize>(
+  data: &[u8],
+) -> Result<T> {
+  let mut data = data;
+  Ok(T::try_deserialize(&mut data)?)
+}
+
+// Decodes a zero-copy account such as `Sale`, `Round` or `Shard`.
+pub fn decode_zero_copy<T: ZeroCopy>(
+  data: &[u8],
+) -> Result<T> {
+  let payload = data.strip_prefix(&T::DISCRIMINATOR[..]).ok_or(ClientError::Discriminator)?;
+  let size = std::mem::size_of::<T>();
+  if payload.len() < size {
+    return Err(ClientError::Truncated);
+  }
+
+  Ok(bytemuck::pod_read_unaligned(&payload[..size]))
+}
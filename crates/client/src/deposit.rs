@@ -0,0 +1,164 @@
+use anchor_lang::prelude::{ AccountMeta, Pubkey };
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::system_program;
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::token;
+use sale::accounts;
+use sale::auth::{ SOL_USD_PRICEFEED, USDC, USDT };
+use sale::instructions::referral::EMPTY_REFERRAL_KEY;
+
+use crate::instructions::{ self, with_remaining_accounts };
+use crate::pda;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DepositAsset {
+  Sol,
+  Usdc,
+  Usdt,
+}
+
+impl DepositAsset {
+  pub fn get_mint(
+    &self,
+  ) -> Option<Pubkey> {
+    match self {
+      DepositAsset::Sol => None,
+      DepositAsset::Usdc => Some(USDC.parse().expect("USDC is a valid pubkey")),
+      DepositAsset::Usdt => Some(USDT.parse().expect("USDT is a valid pubkey")),
+    }
+  }
+}
+
+// Everything needed to assemble a deposit besides the PDAs, which are
+// derived from the payer, round and referrer.
+pub struct DepositArgs {
+  pub payer: Pubkey,
+  pub treasury: Pubkey,
+  pub round_id: i16,
+  pub asset: DepositAsset,
+  pub amount: u64,
+  // `None` deposits without a referrer.
+  pub ref_key: Option<Pubkey>,
+  // Upline wallets above the referrer, nearest first: the keys returned by
+  // `rpc::fetch_uplines`.
+  pub uplines: Vec<Pubkey>,
+  // Deposits through a shard of the round instead of the round itself.
+  pub shard: Option<u8>,
+  // The purchase history's current count, to also write a receipt.
+  pub receipt_index: Option<u32>,
+}
+
+// Builds the matching `deposit*` instruction, with token accounts taken as
+// the associated accounts of the payer, treasury and referral PDAs.
+pub fn deposit(
+  args: &DepositArgs,
+) -> Instruction {
+  let ref_key = args.ref_key.unwrap_or(EMPTY_REFERRAL_KEY);
+  let (sale, _) = pda::sale();
+  let (round, _) = pda::round(args.round_id);
+  let (beneficiary, _) = pda::beneficiary(&args.payer);
+  let (referral, _) = pda::referral(&ref_key);
+  let (purchase_history, _) = pda::purchase_history(&args.payer, &round);
+  let purchase_receipt = args.receipt_index.map(|index| pda::purchase_receipt(&purchase_history, index).0);
+  let shard = args.shard.map(|index| pda::shard(args.round_id, index).0);
+  let mint = args.asset.get_mint();
+  let price_info = SOL_USD_PRICEFEED.parse().expect("SOL_USD_PRICEFEED is a valid pubkey");
+
+  let ix = match (mint, shard) {
+    (None, None) => instructions::deposit(accounts::Deposit {
+      sale,
+      payer: args.payer,
+      round,
+      beneficiary,
+      referral,
+      purchase_history,
+      purchase_receipt,
+      price_info,
+      treasury_info: args.treasury,
+      system_program: system_program::ID,
+    }, ref_key, args.amount),
+    (None, Some(shard)) => instructions::deposit_sharded(accounts::DepositSharded {
+      sale,
+      payer: args.payer,
+      round,
+      shard,
+      beneficiary,
+      referral,
+      purchase_history,
+      purchase_receipt,
+      price_info,
+      treasury_info: args.treasury,
+      system_program: system_program::ID,
+    }, ref_key, args.amount),
+    (Some(mint), None) => {
+      let beneficiary_ata = get_associated_token_address(&args.payer, &mint);
+      let treasury_ata = get_associated_token_address(&args.treasury, &mint);
+      let referral_pda_ata = get_associated_token_address(&referral, &mint);
+      match args.asset {
+        DepositAsset::Usdt => instructions::deposit_usdt(accounts::DepositUSDT {
+          sale,
+          payer: args.payer,
+          round,
+          beneficiary,
+          referral,
+          purchase_history,
+          purchase_receipt,
+          beneficiary_ata,
+          treasury_ata,
+          referral_pda_ata,
+          token_program: token::ID,
+          system_program: system_program::ID,
+        }, ref_key, args.amount),
+        _ => instructions::deposit_usdc(accounts::DepositUSDC {
+          sale,
+          payer: args.payer,
+          round,
+          beneficiary,
+          referral,
+          purchase_history,
+          purchase_receipt,
+          beneficiary_ata,
+          treasury_ata,
+          referral_pda_ata,
+          token_program: token::ID,
+          system_program: system_program::ID,
+        }, ref_key, args.amount),
+      }
+    }
+    (Some(mint), Some(shard)) => instructions::deposit_sharded_stable(accounts::DepositShardedStable {
+      sale,
+      payer: args.payer,
+      round,
+      shard,
+      beneficiary,
+      referral,
+      purchase_history,
+      purchase_receipt,
+      beneficiary_ata: get_associated_token_address(&args.payer, &mint),
+      treasury_ata: get_associated_token_address(&args.treasury, &mint),
+      referral_pda_ata: get_associated_token_address(&referral, &mint),
+      token_program: token::ID,
+      system_program: system_program::ID,
+    }, ref_key, args.amount),
+  };
+
+  with_remaining_accounts(ix, upline_accounts(&args.uplines, mint))
+}
+
+// The `remaining_accounts` expected by `get_uplines`: each upline's
+// writable referral PDA, followed by its token account for stable deposits.
+pub fn upline_accounts(
+  uplines: &[Pubkey],
+  mint: Option<Pubkey>,
+) -> Vec<AccountMeta> {
+  let mut metas = Vec::new();
+  for upline in uplines {
+    let (referral, _) = pda::referral(upline);
+    metas.push(AccountMeta::new(referral, false));
+    if let Some(mint) = mint {
+      metas.push(AccountMeta::new(get_associated_token_address(&referral, &mint), false));
+    }
+  }
+
+  metas
+}
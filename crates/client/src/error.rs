@@ -0,0 +1,47 @@
+use thiserror::Error;
+
+// Program and RPC errors are large, so they are boxed to keep `Result`
+// cheap to return.
+#[derive(Debug, Error)]
+pub enum ClientError {
+  #[error("account data shorter than its layout")]
+  Truncated,
+  #[error("account discriminator does not match")]
+  Discriminator,
+  #[error("account not found: {0}")]
+  AccountNotFound(anchor_lang::prelude::Pubkey),
+  #[error("{0}")]
+  Anchor(Box<anchor_lang::error::Error>),
+  #[error("invalid account data: {0}")]
+  Borsh(#[from] std::io::Error),
+  #[cfg(feature = "rpc")]
+  #[error("rpc request failed: {0}")]
+  Rpc(Box<solana_rpc_client_api::client_error::Error>),
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+impl From<anchor_lang::error::Error> for ClientError {
+  fn from(
+    err: anchor_lang::error::Error,
+  ) -> Self {
+    ClientError::Anchor(Box::new(err))
+  }
+}
+
+impl From<sale::errors::Sale> for ClientError {
+  fn from(
+    err: sale::errors::Sale,
+  ) -> Self {
+    ClientError::Anchor(Box::new(err.into()))
+  }
+}
+
+#[cfg(feature = "rpc")]
+impl From<solana_rpc_client_api::client_error::Error> for ClientError {
+  fn from(
+    err: solana_rpc_client_api::client_error::Error,
+  ) -> Self {
+    ClientError::Rpc(Box::new(err))
+  }
+}
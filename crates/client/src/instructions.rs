@@ -0,0 +1,107 @@
+use anchor_lang::prelude::{ AccountMeta, Pubkey };
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{ InstructionData, ToAccountMetas };
+use sale::state::round::{ DecayCurve, PriceMode };
+use sale::state::sale::{ RewardMode, RewardTier, MAX_UPLINE_LEVELS };
+use sale::{ accounts, instruction };
+
+pub fn build(
+  accounts: impl ToAccountMetas,
+  data: impl InstructionData,
+) -> Instruction {
+  Instruction {
+    program_id: sale::ID,
+    accounts: accounts.to_account_metas(None),
+    data: data.data(),
+  }
+}
+
+// Appends accounts the program reads from `remaining_accounts`, such as
+// upline referrals on deposits or shards on `settle_shards`.
+pub fn with_remaining_accounts(
+  mut ix: Instruction,
+  remaining_accounts: impl IntoIterator<Item = AccountMeta>,
+) -> Instruction {
+  ix.accounts.extend(remaining_accounts);
+  ix
+}
+
+// One builder per instruction in `lib.rs`, taking its account context and
+// arguments in declaration order.
+macro_rules! sale_instructions {
+  ($($name:ident($accounts:ident $(, $arg:ident: $ty:ty)*) => $data:ident;)*) => {
+    $(
+      pub fn $name(
+        accounts: accounts::$accounts,
+        $($arg: $ty,)*
+      ) -> Instruction {
+        build(accounts, instruction::$data { $($arg,)* })
+      }
+    )*
+  };
+}
+
+sale_instructions! {
+  initialize(InitSale) => Initialize;
+  set_sale_investment(SetSaleInvestment, max_investment: u64, min_investment: u64) => SetSaleInvestment;
+  set_sale_ref_reward(SetSaleReward, main_reward: u64, secondary_reward: u64) => SetSaleRefReward;
+  set_sale_ref_levels(SetSaleRefLevels, referral_depth: u8, upline_rewards: [u64; MAX_UPLINE_LEVELS]) => SetSaleRefLevels;
+  set_sale_ref_budget(SetSaleRefBudget, referrer_cap_usd: u64, budget_usd: u64, budget_tokens: u128) => SetSaleRefBudget;
+  set_sale_reward_mode(SetSaleRewardMode, reward_mode: RewardMode, cash_share: u64) => SetSaleRewardMode;
+  set_sale_buyer_bonus(SetSaleBuyerBonus, buyer_bonus: u64) => SetSaleBuyerBonus;
+  set_sale_referral_program(SetSaleReferralProgram, enabled: bool, reject_disabled_referral: bool) => SetSaleReferralProgram;
+  set_sale_ref_tiers(SetSaleRefTiers, tiers: Vec<RewardTier>) => SetSaleRefTiers;
+  set_sale_referral_rules(SetSaleReferralRules, bind_referrer: bool, min_referral_usd: u64) => SetSaleReferralRules;
+  set_sale_claim(SetSaleClaim, claim_start: i64, vesting_duration: i64) => SetSaleClaim;
+  open_sale(SetSaleOpened) => OpenSale;
+  close_sale(SetSaleClosed) => CloseSale;
+  close_sale_account(CloseSaleAccount) => CloseSaleAccount;
+  pause(SetSalePaused, scope: u8, reason: u8) => Pause;
+  unpause(SetSaleUnpaused, scope: u8) => Unpause;
+  deposit(Deposit, ref_key: Pubkey, amount: u64) => Deposit;
+  deposit_usdc(DepositUSDC, ref_key: Pubkey, amount: u64) => DepositUsdc;
+  deposit_usdt(DepositUSDT, ref_key: Pubkey, amount: u64) => DepositUsdt;
+  claim(Claim) => Claim;
+  close_beneficiary(CloseBeneficiary) => CloseBeneficiary;
+  init_round(InitRound, id: i16, price: u64, total_supply: u128) => InitRound;
+  set_round_price(SetRoundPrice, price: u64) => SetRoundPrice;
+  set_round_pricing(SetRoundPricing, price_mode: PriceMode, end_price: u64, price_steps: u16) => SetRoundPricing;
+  set_round_auction(SetRoundAuction, start_price: u64, floor_price: u64, start_time: i64, end_time: i64, decay_curve: DecayCurve, uniform_clearing: bool) => SetRoundAuction;
+  set_round_supply(SetRoundSupply, total_supply: u128) => SetRoundSupply;
+  schedule_round(SetRoundScheduled, start_time: i64) => ScheduleRound;
+  open_round(SetRoundOpened) => OpenRound;
+  close_round(SetRoundClosed) => CloseRound;
+  close_round_account(CloseRoundAccount) => CloseRoundAccount;
+  pause_round(SetRoundPaused) => PauseRound;
+  resume_round(SetRoundResumed) => ResumeRound;
+  cancel_round(SetRoundCancelled) => CancelRound;
+  settle_auction(SettleAuction, owner: Pubkey) => SettleAuction;
+  set_round_shards(SetRoundShards, shard_count: u8) => SetRoundShards;
+  init_shard(InitShard, round_id: i16, index: u8) => InitShard;
+  settle_shards(SettleShards) => SettleShards;
+  deposit_sharded(DepositSharded, ref_key: Pubkey, amount: u64) => DepositSharded;
+  deposit_sharded_stable(DepositShardedStable, ref_key: Pubkey, amount: u64) => DepositShardedStable;
+  init_referral(InitReferral, ref_key: Pubkey, main_reward: u64, secondary_reward: u64) => InitReferral;
+  register_referral(RegisterReferral, upline: Pubkey) => RegisterReferral;
+  register_referral_code(RegisterReferralCode, code: String) => RegisterReferralCode;
+  set_referral_reward(SetReferralReward, ref_key: Pubkey, main_reward: u64, secondary_reward: u64) => SetReferralReward;
+  set_referral_reward_mode(SetReferralRewardMode, ref_key: Pubkey, reward_mode: Option<RewardMode>) => SetReferralRewardMode;
+  set_referral_buyer_bonus(SetReferralBuyerBonus, ref_key: Pubkey, buyer_bonus: u64) => SetReferralBuyerBonus;
+  set_referral_upline(SetReferralUpline, ref_key: Pubkey, upline: Pubkey) => SetReferralUpline;
+  enable_referral(SetReferralEnabled, ref_key: Pubkey) => EnableReferral;
+  disable_referral(SetReferralDisabled, ref_key: Pubkey) => DisableReferral;
+  withdraw_ref(Withdraw) => WithdrawRef;
+  withdraw_ref_usdc(WithdrawUSDC) => WithdrawRefUsdc;
+  withdraw_ref_usdt(WithdrawUSDT) => WithdrawRefUsdt;
+  withdraw_ref_all(WithdrawAll) => WithdrawRefAll;
+  set_referral_payout(SetReferralPayout, payout_address: Pubkey) => SetReferralPayout;
+  withdraw_ref_to_payout(WithdrawToPayout, ref_key: Pubkey) => WithdrawRefToPayout;
+  clawback_referral(ClawbackReferral, ref_key: Pubkey) => ClawbackReferral;
+  close_referral(CloseReferral) => CloseReferral;
+  claim_ref_tokens(ClaimRefTokens) => ClaimRefTokens;
+  migrate_sale(MigrateAccount) => MigrateSale;
+  migrate_round(MigrateAccount) => MigrateRound;
+  migrate_referral(MigrateAccount) => MigrateReferral;
+  migrate_referral_code(MigrateAccount) => MigrateReferralCode;
+  migrate_beneficiary(MigrateAccount) => MigrateBeneficiary;
+}
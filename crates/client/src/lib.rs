@@ -0,0 +1,17 @@
+//! Client helpers for the sale program.
+//!
+//! [`instructions`] has a typed builder for every instruction in the
+//! program, [`deposit`] assembles deposits from a payer and a round id,
+//! [`pda`] derives the program's addresses, [`accounts`] and [`rpc`] decode
+//! and fetch its accounts, and [`quote`] previews what a deposit credits.
+
+pub mod accounts;
+pub mod deposit;
+pub mod error;
+pub mod instructions;
+pub mod pda;
+pub mod quote;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+
+pub use error::{ ClientError, Result };
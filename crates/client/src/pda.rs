@@ -0,0 +1,58 @@
+use anchor_lang::prelude::Pubkey;
+use sale::instructions::referral::{ REFERRAL_CODE_TAG, REFERRAL_TAG };
+use sale::instructions::round::ROUND_TAG;
+use sale::instructions::sale::{ BENEFICIARY_TAG, PURCHASE_HISTORY_TAG, PURCHASE_RECEIPT_TAG };
+use sale::instructions::shard::SHARD_TAG;
+
+// Mirrors the `seeds` constraints of the program's account contexts.
+
+pub fn sale() -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[], &sale::ID)
+}
+
+pub fn round(
+  id: i16,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[ROUND_TAG, b"_", &id.to_le_bytes()], &sale::ID)
+}
+
+pub fn beneficiary(
+  wallet: &Pubkey,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[BENEFICIARY_TAG, b"_", wallet.as_ref()], &sale::ID)
+}
+
+pub fn referral(
+  wallet: &Pubkey,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[REFERRAL_TAG, b"_", wallet.as_ref()], &sale::ID)
+}
+
+pub fn referral_code(
+  code: &str,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[REFERRAL_CODE_TAG, b"_", code.as_bytes()], &sale::ID)
+}
+
+pub fn shard(
+  round_id: i16,
+  index: u8,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[SHARD_TAG, b"_", &round_id.to_le_bytes(), b"_", &[index]], &sale::ID)
+}
+
+pub fn purchase_history(
+  wallet: &Pubkey,
+  round: &Pubkey,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[PURCHASE_HISTORY_TAG, b"_", wallet.as_ref(), b"_", round.as_ref()], &sale::ID)
+}
+
+// `index` is the history's purchase count before the deposit that writes
+// the receipt.
+pub fn purchase_receipt(
+  purchase_history: &Pubkey,
+  index: u32,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[PURCHASE_RECEIPT_TAG, b"_", purchase_history.as_ref(), b"_", &index.to_le_bytes()], &sale::ID)
+}
@@ -0,0 +1,389 @@
+use anchor_lang::prelude::{ AnchorDeserialize, Pubkey, Space };
+use sale::auth::{ PRECISION, STABLE_PRECISION };
+use sale::errors;
+use sale::instructions::referral::{ check_referral, get_upline_reward, resolve_referral, EMPTY_REFERRAL_KEY };
+use sale::instructions::sale::{ check_round, get_buyer_bonus, get_reward };
+use sale::instructions::shard::check_shard;
+use sale::math;
+use sale::state::beneficiary::Beneficiary;
+use sale::state::referral::Referral;
+use sale::state::round::Round;
+use sale::state::sale::{ Sale, PAUSE_DEPOSIT };
+use sale::state::shard::Shard;
+
+use crate::deposit::DepositAsset;
+use crate::error::Result;
+
+// The SOL price is read from the oracle on chain, so callers pass the price
+// they expect the deposit to see.
+#[derive(Clone, Copy, Debug)]
+pub enum Payment {
+  Sol { amount: u64, price: u128, expo: u32 },
+  Usdc(u64),
+  Usdt(u64),
+}
+
+impl Payment {
+  pub fn get_asset(
+    &self,
+  ) -> DepositAsset {
+    match self {
+      Payment::Sol { .. } => DepositAsset::Sol,
+      Payment::Usdc(_) => DepositAsset::Usdc,
+      Payment::Usdt(_) => DepositAsset::Usdt,
+    }
+  }
+
+  pub fn get_amount(
+    &self,
+  ) -> u64 {
+    match self {
+      Payment::Sol { amount, .. } => *amount,
+      Payment::Usdc(amount) | Payment::Usdt(amount) => *amount,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quote {
+  pub ref_key: Pubkey,
+  pub usd_amount: u128,
+  pub asset_price: u64,
+  pub token_amount: u128,
+  pub bonus_amount: u128,
+  pub credited_amount: u128,
+  // Paid to the referrer in the deposited asset.
+  pub referral_amount: u64,
+  pub referral_usd: u128,
+  pub referral_token_amount: u128,
+  pub upline_amount: u64,
+  // What reaches the treasury after referral payouts.
+  pub treasury_amount: u64,
+}
+
+// Fetched state a deposit reads. The beneficiary and referral are `None`
+// while their PDAs do not exist yet, and `uplines` is the chain returned by
+// `rpc::fetch_uplines`.
+pub struct DepositState<'a> {
+  pub sale: &'a Sale,
+  pub round: &'a Round,
+  // Set for deposits through a shard of the round.
+  pub shard: Option<&'a Shard>,
+  pub beneficiary: Option<&'a Beneficiary>,
+  pub referral: Option<&'a Referral>,
+  pub uplines: &'a [(Pubkey, Referral)],
+}
+
+// Mirrors the `deposit*` handlers against fetched state, failing with the
+// error the program would return. Rewards go through the program's own
+// `get_reward` and `get_upline_reward`.
+pub fn quote_deposit(
+  state: &DepositState,
+  payer: Pubkey,
+  ref_key: Option<Pubkey>,
+  payment: Payment,
+  now: i64,
+) -> Result<Quote> {
+  let mut sale = *state.sale;
+  let mut round = *state.round;
+  let mut beneficiary = match state.beneficiary {
+    Some(beneficiary) => beneficiary.clone(),
+    None => zeroed()?,
+  };
+  let mut referral = match state.referral {
+    Some(referral) => referral.clone(),
+    None => zeroed()?,
+  };
+
+  match state.shard {
+    Some(shard) => check_shard(&sale, &round, shard)?,
+    None => {
+      if !sale.is_open() {
+        return Err(errors::Sale::SaleNotOpened.into());
+      }
+
+      if sale.is_paused(PAUSE_DEPOSIT) {
+        return Err(errors::Sale::DepositsPaused.into());
+      }
+    }
+  }
+
+  let requested_key = ref_key.unwrap_or(EMPTY_REFERRAL_KEY);
+  let ref_key = resolve_referral(&sale, &referral, requested_key)?;
+
+  // Sharded deposits never activate the round; `check_shard` already
+  // required it open.
+  if state.shard.is_none() {
+    check_round(&mut sale, &mut round, now)?;
+  }
+
+  let amount = payment.get_amount();
+  let (usd_amount, asset_price) = match payment {
+    Payment::Sol { price, expo, .. } => (
+      math::mul_div_floor(u128::from(amount), price, math::pow10(expo)?)?,
+      math::to_u64(math::mul_div_floor(price, math::pow10(PRECISION)?, math::pow10(expo)?)?)?,
+    ),
+    Payment::Usdc(_) | Payment::Usdt(_) => (
+      math::mul(u128::from(amount), math::pow10(STABLE_PRECISION)?)?,
+      math::to_u64(math::pow10(PRECISION)?)?,
+    ),
+  };
+  let token_amount = round.quote(usd_amount, now)?;
+  let bonus_amount = get_buyer_bonus(&sale, ref_key, &referral, token_amount)?;
+  let credited_amount = math::add(token_amount, bonus_amount)?;
+
+  if sale.get_max_investment() < usd_amount {
+    return Err(errors::Sale::SaleMaxInvestmentExceeded.into());
+  }
+
+  if sale.get_min_investment() > usd_amount {
+    return Err(errors::Sale::SaleMinInvestmentNotReached.into());
+  }
+
+  match state.shard {
+    Some(shard) if credited_amount > shard.get_available() => return Err(errors::Sale::ShardCapacityExceeded.into()),
+    None if credited_amount > round.get_available_supply() => return Err(errors::Sale::RoundSupplyExceeded.into()),
+    _ => {}
+  }
+
+  check_referral(&sale, &mut beneficiary, payer, requested_key, ref_key, usd_amount)?;
+
+  let (referral_amount, referral_token_amount, referral_usd) = get_reward(&mut sale, ref_key, &mut referral, amount, usd_amount, token_amount)?;
+  let uplines = walk_uplines(&sale, &payer, &ref_key, &referral, |key| {
+    state.uplines
+      .iter()
+      .find(|(upline, _)| upline == key)
+      .map(|(_, referral)| referral.clone())
+      .ok_or_else(|| errors::Sale::ReferralUplineMissing.into())
+  })?;
+
+  let mut upline_amount = 0u128;
+  for (level, (_, mut upline)) in (2..).zip(uplines) {
+    if upline.is_disabled() {
+      break;
+    }
+
+    let (reward_amount, _, _) = get_upline_reward(&mut sale, &mut upline, level, amount, usd_amount, token_amount)?;
+    upline_amount = math::add(upline_amount, u128::from(reward_amount))?;
+  }
+  let reward_amount = math::add(u128::from(referral_amount), upline_amount)?;
+
+  Ok(Quote {
+    ref_key,
+    usd_amount,
+    asset_price,
+    token_amount,
+    bonus_amount,
+    credited_amount,
+    referral_amount,
+    referral_usd,
+    referral_token_amount,
+    upline_amount: math::to_u64(upline_amount)?,
+    treasury_amount: math::to_u64(math::sub(u128::from(amount), reward_amount)?)?,
+  })
+}
+
+// Walks the upline chain above `ref_key` with the stop rules of
+// `get_uplines`. A disabled upline ends the chain but is still returned,
+// since the program reads its account before stopping.
+pub fn walk_uplines<F>(
+  sale: &Sale,
+  payer: &Pubkey,
+  ref_key: &Pubkey,
+  referral: &Referral,
+  mut lookup: F,
+) -> Result<Vec<(Pubkey, Referral)>>
+where
+  F: FnMut(&Pubkey) -> Result<Referral>,
+{
+  let mut uplines: Vec<(Pubkey, Referral)> = Vec::new();
+  if *ref_key == EMPTY_REFERRAL_KEY || !sale.is_referral_program_enabled() {
+    return Ok(uplines);
+  }
+
+  let mut upline_key = referral.get_upline();
+  for _ in 2..=sale.get_referral_depth() {
+    if upline_key == Pubkey::default() || upline_key == EMPTY_REFERRAL_KEY {
+      break;
+    }
+
+    if upline_key == *ref_key || upline_key == *payer || uplines.iter().any(|(key, _)| *key == upline_key) {
+      break;
+    }
+
+    let upline = lookup(&upline_key)?;
+    let next_key = upline.get_upline();
+    let disabled = upline.is_disabled();
+    uplines.push((upline_key, upline));
+    if disabled {
+      break;
+    }
+
+    upline_key = next_key;
+  }
+
+  Ok(uplines)
+}
+
+// A PDA created by the deposit itself starts zeroed.
+fn zeroed<T: AnchorDeserialize + Space>() -> Result<T> {
+  let data = vec![0u8; T::INIT_SPACE];
+  Ok(T::deserialize(&mut data.as_slice())?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bytemuck::Zeroable;
+
+  const SOL: Payment = Payment::Sol { amount: 1_000_000_000, price: 14_400_000_000, expo: 8 };
+
+  struct Fixture {
+    sale: Sale,
+    round: Round,
+    referral: Referral,
+    uplines: Vec<(Pubkey, Referral)>,
+    payer: Pubkey,
+    ref_key: Pubkey,
+  }
+
+  impl Fixture {
+    // An open sale and round at 0.1 USD per token, three referral levels and
+    // a referrer with two uplines.
+    fn new() -> Fixture {
+      let mut sale = Sale::zeroed();
+      sale.init().unwrap();
+      sale.set_referral_levels(3, [20_000_000, 10_000_000]).unwrap();
+      sale.set_open().unwrap();
+      sale.set_round(1).unwrap();
+
+      let mut round = Round::zeroed();
+      round.init(1, 100_000_000, 1_000_000_000_000_000).unwrap();
+      round.set_open().unwrap();
+
+      let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+      Fixture {
+        sale,
+        round,
+        referral: registered(first),
+        uplines: vec![(first, registered(second)), (second, registered(Pubkey::default()))],
+        payer: Pubkey::new_unique(),
+        ref_key: Pubkey::new_unique(),
+      }
+    }
+
+    fn quote(
+      &self,
+      shard: Option<&Shard>,
+      beneficiary: Option<&Beneficiary>,
+    ) -> Result<Quote> {
+      let state = DepositState {
+        sale: &self.sale,
+        round: &self.round,
+        shard,
+        beneficiary,
+        referral: Some(&self.referral),
+        uplines: &self.uplines,
+      };
+      quote_deposit(&state, self.payer, Some(self.ref_key), SOL, 0)
+    }
+  }
+
+  fn registered(
+    upline: Pubkey,
+  ) -> Referral {
+    let mut referral: Referral = zeroed().unwrap();
+    referral.register(0, 0, upline).unwrap();
+    referral
+  }
+
+  fn assert_error(
+    result: Result<Quote>,
+    expected: errors::Sale,
+  ) {
+    match result {
+      Err(crate::ClientError::Anchor(err)) => assert_eq!(*err, expected.into()),
+      Err(err) => panic!("expected {expected:?}, got {err}"),
+      Ok(quote) => panic!("expected {expected:?}, got {quote:?}"),
+    }
+  }
+
+  #[test]
+  fn quotes_referrer_and_uplines() {
+    let fixture = Fixture::new();
+    let quote = fixture.quote(None, None).unwrap();
+
+    assert_eq!(quote, Quote {
+      ref_key: fixture.ref_key,
+      usd_amount: 144_000_000_000,
+      asset_price: 144_000_000_000,
+      token_amount: 1_440_000_000_000,
+      bonus_amount: 0,
+      credited_amount: 1_440_000_000_000,
+      referral_amount: 50_000_000,
+      referral_usd: 7_200_000_000,
+      referral_token_amount: 72_000_000_000,
+      upline_amount: 30_000_000,
+      treasury_amount: 920_000_000,
+    });
+  }
+
+  // The referrer draws on the budget before its uplines, as on chain.
+  #[test]
+  fn uplines_share_what_the_referrer_leaves_of_the_budget() {
+    let mut fixture = Fixture::new();
+    fixture.sale.set_referral_budget(0, 10_000_000_000, 0).unwrap();
+    let quote = fixture.quote(None, None).unwrap();
+
+    assert_eq!(quote.referral_usd, 7_200_000_000);
+    assert_eq!(quote.upline_amount, 19_444_444);
+  }
+
+  #[test]
+  fn disabled_upline_ends_the_chain() {
+    let mut fixture = Fixture::new();
+    fixture.uplines[0].1.disable().unwrap();
+    assert_eq!(fixture.quote(None, None).unwrap().upline_amount, 0);
+
+    let mut fixture = Fixture::new();
+    fixture.uplines[1].1.disable().unwrap();
+    assert_eq!(fixture.quote(None, None).unwrap().upline_amount, 20_000_000);
+  }
+
+  #[test]
+  fn missing_upline_fails_like_the_program() {
+    let mut fixture = Fixture::new();
+    fixture.uplines.pop();
+
+    assert_error(fixture.quote(None, None), errors::Sale::ReferralUplineMissing);
+  }
+
+  #[test]
+  fn bound_buyer_cannot_switch_referrer() {
+    let mut fixture = Fixture::new();
+    fixture.sale.set_referral_rules(true, 0).unwrap();
+    let mut beneficiary: Beneficiary = zeroed().unwrap();
+    beneficiary.set_referrer(Pubkey::new_unique()).unwrap();
+
+    assert_error(fixture.quote(None, Some(&beneficiary)), errors::Sale::ReferrerMismatch);
+
+    fixture.ref_key = fixture.payer;
+    assert_error(fixture.quote(None, None), errors::Sale::SelfReferral);
+  }
+
+  #[test]
+  fn sharded_quote_follows_shard_rules() {
+    let mut fixture = Fixture::new();
+    fixture.round.set_shard_count(1).unwrap();
+    let mut shard = Shard::zeroed();
+    shard.init(1, 0).unwrap();
+
+    assert_error(fixture.quote(Some(&shard), None), errors::Sale::ShardCapacityExceeded);
+
+    shard.set_capacity(1_440_000_000_000).unwrap();
+    assert_eq!(fixture.quote(Some(&shard), None).unwrap(), fixture.quote(None, None).unwrap());
+
+    fixture.sale.set_referral_budget(0, 10_000_000_000, 0).unwrap();
+    assert_error(fixture.quote(Some(&shard), None), errors::Sale::ShardingUnsupported);
+  }
+}
@@ -0,0 +1,91 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{ AccountDeserialize, ZeroCopy };
+use sale::state::beneficiary::Beneficiary;
+use sale::state::purchase::PurchaseHistory;
+use sale::state::referral::Referral;
+use sale::state::round::Round;
+use sale::state::sale::Sale;
+use solana_rpc_client::rpc_client::RpcClient;
+
+use crate::accounts::{ decode, decode_zero_copy };
+use crate::error::{ ClientError, Result };
+use crate::pda;
+use crate::quote::walk_uplines;
+
+// Returns `None` when the account does not exist.
+pub fn fetch_data(
+  client: &RpcClient,
+  address: &Pubkey,
+) -> Result<Option<Vec<u8>>> {
+  let account = client.get_account_with_commitment(address, client.commitment())?.value;
+  Ok(account.map(|account| account.data))
+}
+
+pub fn fetch<T: AccountDeserialize>(
+  client: &RpcClient,
+  address: &Pubkey,
+) -> Result<Option<T>> {
+  fetch_data(client, address)?.map(|data| decode(&data)).transpose()
+}
+
+pub fn fetch_zero_copy<T: ZeroCopy>(
+  client: &RpcClient,
+  address: &Pubkey,
+) -> Result<Option<T>> {
+  fetch_data(client, address)?.map(|data| decode_zero_copy(&data)).transpose()
+}
+
+pub fn fetch_sale(
+  client: &RpcClient,
+) -> Result<Sale> {
+  let (address, _) = pda::sale();
+  fetch_zero_copy(client, &address)?.ok_or(ClientError::AccountNotFound(address))
+}
+
+pub fn fetch_round(
+  client: &RpcClient,
+  id: i16,
+) -> Result<Round> {
+  let (address, _) = pda::round(id);
+  fetch_zero_copy(client, &address)?.ok_or(ClientError::AccountNotFound(address))
+}
+
+pub fn fetch_beneficiary(
+  client: &RpcClient,
+  wallet: &Pubkey,
+) -> Result<Option<Beneficiary>> {
+  fetch(client, &pda::beneficiary(wallet).0)
+}
+
+pub fn fetch_referral(
+  client: &RpcClient,
+  wallet: &Pubkey,
+) -> Result<Option<Referral>> {
+  fetch(client, &pda::referral(wallet).0)
+}
+
+pub fn fetch_purchase_history(
+  client: &RpcClient,
+  wallet: &Pubkey,
+  round_id: i16,
+) -> Result<Option<PurchaseHistory>> {
+  let (round, _) = pda::round(round_id);
+  fetch(client, &pda::purchase_history(wallet, &round).0)
+}
+
+// The upline chain above `ref_key`, nearest first. Pass the keys as
+// `DepositArgs::uplines` and the whole chain to `quote::quote_deposit`.
+pub fn fetch_uplines(
+  client: &RpcClient,
+  sale: &Sale,
+  payer: &Pubkey,
+  ref_key: &Pubkey,
+) -> Result<Vec<(Pubkey, Referral)>> {
+  let Some(referral) = fetch_referral(client, ref_key)? else {
+    return Ok(Vec::new());
+  };
+
+  walk_uplines(sale, payer, ref_key, &referral, |key| {
+    fetch_referral(client, key)?.ok_or(ClientError::AccountNotFound(pda::referral(key).0))
+  })
+}
@@ -0,0 +1,185 @@
+use anchor_lang::{ AnchorDeserialize, Discriminator };
+use sale::{ events, instruction };
+
+use crate::error::{ IndexerError, Result };
+
+fn check_version(
+  version: u8,
+) -> Result<()> {
+  if version != events::EVENT_VERSION {
+    return Err(IndexerError::UnsupportedVersion(version));
+  }
+
+  Ok(())
+}
+
+macro_rules! sale_events {
+  ($($variant:ident => $event:ident),* $(,)?) => {
+    pub enum SaleEvent {
+      $($variant(events::$event),)*
+    }
+
+    impl SaleEvent {
+      pub fn name(
+        &self,
+      ) -> &'static str {
+        match self {
+          $(SaleEvent::$variant(_) => stringify!($event),)*
+        }
+      }
+    }
+
+    // Decodes the payload of a `Program data:` log line. Returns `None` for
+    // discriminators this crate does not know about.
+    pub fn decode_event(
+      data: &[u8],
+    ) -> Result<Option<SaleEvent>> {
+      if data.len() < 8 {
+        return Err(IndexerError::Truncated);
+      }
+
+      let (discriminator, mut payload) = data.split_at(8);
+      $(
+        if discriminator == events::$event::DISCRIMINATOR {
+          let event = events::$event::deserialize(&mut payload)?;
+          check_version(event.version)?;
+          return Ok(Some(SaleEvent::$variant(event)));
+        }
+      )*
+
+      Ok(None)
+    }
+  };
+}
+
+macro_rules! sale_instructions {
+  ($($variant:ident),* $(,)?) => {
+    pub enum SaleInstruction {
+      $($variant(instruction::$variant),)*
+    }
+
+    impl SaleInstruction {
+      pub fn name(
+        &self,
+      ) -> &'static str {
+        match self {
+          $(SaleInstruction::$variant(_) => stringify!($variant),)*
+        }
+      }
+    }
+
+    // Decodes raw instruction data sent to the sale program. Returns `None`
+    // for discriminators this crate does not know about.
+    pub fn decode_instruction(
+      data: &[u8],
+    ) -> Result<Option<SaleInstruction>> {
+      if data.len() < 8 {
+        return Err(IndexerError::Truncated);
+      }
+
+      let (discriminator, mut payload) = data.split_at(8);
+      $(
+        if discriminator == instruction::$variant::DISCRIMINATOR {
+          let ix = instruction::$variant::deserialize(&mut payload)?;
+          return Ok(Some(SaleInstruction::$variant(ix)));
+        }
+      )*
+
+      Ok(None)
+    }
+  };
+}
+
+sale_events! {
+  DepositSol => DepositSolEvent,
+  DepositUsdt => DepositUsdtEvent,
+  DepositUsdc => DepositUsdcEvent,
+  WithdrawSol => WithdrawSolEvent,
+  WithdrawUsdt => WithdrawUsdtEvent,
+  WithdrawUsdc => WithdrawUsdcEvent,
+  WithdrawAll => WithdrawAllEvent,
+  SalePaused => SalePausedEvent,
+  SaleUnpaused => SaleUnpausedEvent,
+  RoundStateChanged => RoundStateChangedEvent,
+  ShardSettled => ShardSettledEvent,
+  AuctionSettled => AuctionSettledEvent,
+  ReferralUplineReward => ReferralUplineRewardEvent,
+  Claim => ClaimEvent,
+  ClaimRefTokens => ClaimRefTokensEvent,
+  ReferralRegistered => ReferralRegisteredEvent,
+  ReferralCodeRegistered => ReferralCodeRegisteredEvent,
+  ReferralTierChanged => ReferralTierChangedEvent,
+  ReferralPayoutChanged => ReferralPayoutChangedEvent,
+  ReferralClawback => ReferralClawbackEvent,
+  AccountMigrated => AccountMigratedEvent,
+  SaleConfigChanged => SaleConfigChangedEvent,
+  RoundConfigChanged => RoundConfigChangedEvent,
+  ReferralConfigChanged => ReferralConfigChangedEvent,
+  ShardInitialized => ShardInitializedEvent,
+  AccountClosed => AccountClosedEvent,
+}
+
+sale_instructions! {
+  Initialize,
+  SetSaleInvestment,
+  SetSaleRefReward,
+  SetSaleRefLevels,
+  SetSaleRefBudget,
+  SetSaleRewardMode,
+  SetSaleBuyerBonus,
+  SetSaleReferralProgram,
+  SetSaleRefTiers,
+  SetSaleReferralRules,
+  SetSaleClaim,
+  OpenSale,
+  CloseSale,
+  CloseSaleAccount,
+  Pause,
+  Unpause,
+  Deposit,
+  DepositUsdc,
+  DepositUsdt,
+  Claim,
+  CloseBeneficiary,
+  InitRound,
+  SetRoundPrice,
+  SetRoundPricing,
+  SetRoundAuction,
+  SetRoundSupply,
+  ScheduleRound,
+  OpenRound,
+  CloseRound,
+  CloseRoundAccount,
+  PauseRound,
+  ResumeRound,
+  CancelRound,
+  SettleAuction,
+  SetRoundShards,
+  InitShard,
+  SettleShards,
+  DepositSharded,
+  DepositShardedStable,
+  InitReferral,
+  RegisterReferral,
+  RegisterReferralCode,
+  SetReferralReward,
+  SetReferralRewardMode,
+  SetReferralBuyerBonus,
+  SetReferralUpline,
+  EnableReferral,
+  DisableReferral,
+  WithdrawRef,
+  WithdrawRefUsdc,
+  WithdrawRefUsdt,
+  WithdrawRefAll,
+  SetReferralPayout,
+  WithdrawRefToPayout,
+  ClawbackReferral,
+  CloseReferral,
+  ClaimRefTokens,
+  MigrateSale,
+  MigrateRound,
+  MigrateReferral,
+  MigrateReferralCode,
+  MigrateBeneficiary,
+}
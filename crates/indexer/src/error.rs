@@ -0,0 +1,23 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum IndexerError {
+  #[error("payload shorter than a discriminator")]
+  Truncated,
+  #[error("unsupported event version {0}")]
+  UnsupportedVersion(u8),
+  #[error("invalid base64 in program data: {0}")]
+  Base64(#[from] base64::DecodeError),
+  #[error("invalid base58 in instruction data: {0}")]
+  Base58(#[from] bs58::decode::Error),
+  #[error("invalid payload: {0}")]
+  Borsh(#[from] std::io::Error),
+  #[error("invalid transaction json: {0}")]
+  Json(#[from] serde_json::Error),
+  #[error("invalid pubkey {0}")]
+  Pubkey(String),
+  #[error("instruction references missing account index {0}")]
+  AccountIndex(usize),
+}
+
+pub type Result<T> = std::result::Result<T, IndexerError>;
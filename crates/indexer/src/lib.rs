@@ -0,0 +1,87 @@
+//! Rebuilds the sale program's state from its transaction logs.
+//!
+//! Transactions come in as `getTransaction` JSON (`"encoding": "json"`),
+//! are decoded into typed instructions and events, and the events are folded
+//! into an in-memory [`projection::Projection`].
+
+pub mod decode;
+pub mod error;
+pub mod projection;
+pub mod source;
+
+use anchor_lang::prelude::Pubkey;
+use std::collections::BTreeSet;
+
+use crate::error::Result;
+use crate::projection::Projection;
+use crate::source::{ parse_transactions, Transaction };
+
+// Transactions in `ambiguous_slots` shared a slot without distinct in-block
+// indices, so they were applied in input order, which may not be the order
+// they executed in.
+#[derive(Debug, Default, PartialEq)]
+pub struct Replay {
+  pub applied: usize,
+  pub ambiguous_slots: Vec<u64>,
+}
+
+pub struct Indexer {
+  program_id: Pubkey,
+  pub projection: Projection,
+}
+
+impl Indexer {
+  pub fn new(
+    program_id: Pubkey,
+  ) -> Indexer {
+    Indexer {
+      program_id,
+      projection: Projection::default(),
+    }
+  }
+
+  // Transactions are replayed in (slot, in-block index, input order), so the
+  // result does not depend on how they were split across files. Repeated
+  // signatures are dropped before ordering.
+  pub fn replay(
+    &mut self,
+    transactions: Vec<Transaction>,
+  ) -> Replay {
+    let mut signatures = BTreeSet::new();
+    let mut transactions: Vec<Transaction> = transactions
+      .into_iter()
+      .filter(|tx| signatures.insert(tx.signature.clone()))
+      .collect();
+    transactions.sort_by_key(|tx| (tx.slot, tx.index));
+
+    // Failed transactions emit nothing, so their position does not matter.
+    // Missing indices sort first, so a slot with one is caught by its first
+    // pair.
+    let effective: Vec<&Transaction> = transactions.iter().filter(|tx| !tx.failed).collect();
+    let mut ambiguous_slots: Vec<u64> = effective
+      .windows(2)
+      .filter(|pair| pair[0].slot == pair[1].slot && (pair[0].index.is_none() || pair[0].index == pair[1].index))
+      .map(|pair| pair[0].slot)
+      .collect();
+    ambiguous_slots.dedup();
+
+    Replay {
+      applied: transactions.iter().filter(|tx| self.projection.apply_transaction(tx)).count(),
+      ambiguous_slots,
+    }
+  }
+
+  pub fn replay_json(
+    &mut self,
+    json: &str,
+  ) -> Result<Replay> {
+    let transactions = parse_transactions(&self.program_id, json)?;
+    Ok(self.replay(transactions))
+  }
+}
+
+impl Default for Indexer {
+  fn default() -> Self {
+    Indexer::new(sale::ID)
+  }
+}
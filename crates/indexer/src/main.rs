@@ -0,0 +1,60 @@
+use std::process::ExitCode;
+
+use sale_indexer::source::parse_transactions;
+use sale_indexer::Indexer;
+
+// Replays every JSON file given on the command line and prints a summary of
+// the resulting projection.
+fn main() -> ExitCode {
+  let paths: Vec<String> = std::env::args().skip(1).collect();
+  if paths.is_empty() {
+    eprintln!("usage: sale-indexer <transactions.json>...");
+    return ExitCode::FAILURE;
+  }
+
+  let mut indexer = Indexer::default();
+  let mut transactions = Vec::new();
+  for path in paths.iter() {
+    let parsed = std::fs::read_to_string(path)
+      .map_err(|err| err.to_string())
+      .and_then(|json| parse_transactions(&sale::ID, &json).map_err(|err| err.to_string()));
+
+    match parsed {
+      Ok(mut parsed) => transactions.append(&mut parsed),
+      Err(err) => {
+        eprintln!("{path}: {err}");
+        return ExitCode::FAILURE;
+      }
+    }
+  }
+
+  let replay = indexer.replay(transactions);
+  let projection = &indexer.projection;
+
+  for slot in replay.ambiguous_slots.iter() {
+    eprintln!("warning: slot {slot} has transactions without distinct in-block indices; applied in input order");
+  }
+
+  println!("transactions applied: {}", replay.applied);
+  println!("last slot: {}", projection.last_slot);
+  println!("sale: total_sold={} raised_usd={} sol={} usdc={} usdt={} paused={}",
+    projection.sale.total_sold,
+    projection.sale.raised_usd,
+    projection.sale.raised.sol,
+    projection.sale.raised.usdc,
+    projection.sale.raised.usdt,
+    projection.sale.paused,
+  );
+  for (id, round) in projection.rounds.iter() {
+    println!("round {id}: state={:?} total_sold={} unsettled_sold={} deposits={}",
+      round.state,
+      round.total_sold,
+      round.unsettled_sold,
+      round.deposit_count,
+    );
+  }
+  println!("beneficiaries: {}", projection.beneficiaries.len());
+  println!("referrals: {}", projection.referrals.len());
+
+  ExitCode::SUCCESS
+}
@@ -0,0 +1,444 @@
+use anchor_lang::prelude::Pubkey;
+use sale::auth::{ USDC, USDT };
+use sale::events::AccountKind;
+use sale::instructions::referral::{ EMPTY_REFERRAL_KEY, REFERRAL_TAG };
+use sale::state::referral::ReferralConfig;
+use sale::state::round::{ RoundConfig, State as RoundState };
+use sale::state::sale::SaleConfig;
+use std::collections::{ BTreeMap, BTreeSet };
+
+use crate::decode::SaleEvent;
+use crate::source::Transaction;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Asset {
+  Sol,
+  Usdc,
+  Usdt,
+}
+
+impl Asset {
+  // Upline rewards carry the mint they were paid in, with the default key
+  // standing for SOL.
+  fn from_mint(
+    mint: &Pubkey,
+  ) -> Option<Asset> {
+    if *mint == Pubkey::default() {
+      return Some(Asset::Sol);
+    }
+
+    if USDC.parse::<Pubkey>() == Ok(*mint) {
+      return Some(Asset::Usdc);
+    }
+
+    if USDT.parse::<Pubkey>() == Ok(*mint) {
+      return Some(Asset::Usdt);
+    }
+
+    None
+  }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AssetAmounts {
+  pub sol: u128,
+  pub usdc: u128,
+  pub usdt: u128,
+}
+
+impl AssetAmounts {
+  fn add(
+    &mut self,
+    asset: Asset,
+    amount: u64,
+  ) {
+    let slot = self.get_mut(asset);
+    *slot = slot.saturating_add(u128::from(amount));
+  }
+
+  fn sub(
+    &mut self,
+    asset: Asset,
+    amount: u64,
+  ) {
+    let slot = self.get_mut(asset);
+    *slot = slot.saturating_sub(u128::from(amount));
+  }
+
+  fn get_mut(
+    &mut self,
+    asset: Asset,
+  ) -> &mut u128 {
+    match asset {
+      Asset::Sol => &mut self.sol,
+      Asset::Usdc => &mut self.usdc,
+      Asset::Usdt => &mut self.usdt,
+    }
+  }
+}
+
+#[derive(Clone, Default)]
+pub struct SaleView {
+  pub config: Option<SaleConfig>,
+  pub paused: u8,
+  pub pause_reason: u8,
+  pub total_sold: u128,
+  pub spent_usd: u128,
+  pub spent_tokens: u128,
+  pub raised: AssetAmounts,
+  pub raised_usd: u128,
+  pub closed: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct RoundView {
+  pub config: Option<RoundConfig>,
+  pub state: Option<RoundState>,
+  pub clearing_price: u64,
+  pub total_sold: u128,
+  // Sold through shards but not yet rolled up by `settle_shards`.
+  pub unsettled_sold: u128,
+  pub settled_usd: u128,
+  pub shards: BTreeMap<u8, u128>,
+  pub raised_usd: u128,
+  pub deposit_count: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct BeneficiaryView {
+  pub token_amount: u128,
+  pub bonus_amount: u128,
+  pub claimed_amount: u128,
+  pub usd_amount: u128,
+  pub paid: AssetAmounts,
+  pub referrer: Option<Pubkey>,
+  pub purchase_count: u64,
+  pub first_purchase_at: i64,
+  pub last_purchase_at: i64,
+}
+
+#[derive(Clone, Default)]
+pub struct ReferralView {
+  pub account: Pubkey,
+  pub config: Option<ReferralConfig>,
+  pub registered: bool,
+  pub upline: Pubkey,
+  pub balance: AssetAmounts,
+  pub earned: AssetAmounts,
+  pub token_reward_amount: u128,
+  pub token_claimed_amount: u128,
+  pub referred_usd: u128,
+  pub referred_count: u32,
+  pub tier: u8,
+  pub payout_address: Pubkey,
+  pub codes: BTreeSet<String>,
+}
+
+struct Deposit {
+  asset: Asset,
+  round: i16,
+  beneficiary: Pubkey,
+  referral: Pubkey,
+  amount: u64,
+  usd_amount: u128,
+  token_amount: u128,
+  bonus_amount: u128,
+  referral_amount: u64,
+  referral_usd: u128,
+  referral_token_amount: u128,
+  shard: Option<u8>,
+  timestamp: i64,
+}
+
+// State rebuilt purely from events. Every map is ordered, so replaying the
+// same transactions in the same order always yields the same projection.
+#[derive(Default)]
+pub struct Projection {
+  pub sale: SaleView,
+  pub rounds: BTreeMap<i16, RoundView>,
+  pub beneficiaries: BTreeMap<Pubkey, BeneficiaryView>,
+  pub referrals: BTreeMap<Pubkey, ReferralView>,
+  pub referral_codes: BTreeMap<String, Pubkey>,
+  pub account_versions: BTreeMap<Pubkey, u8>,
+  pub closed_accounts: BTreeMap<Pubkey, AccountKind>,
+  pub last_slot: u64,
+  seen: BTreeSet<String>,
+}
+
+impl Projection {
+  // Failed transactions emit nothing and repeated signatures are ignored, so
+  // overlapping input files can be replayed safely.
+  pub fn apply_transaction(
+    &mut self,
+    tx: &Transaction,
+  ) -> bool {
+    if tx.failed || !self.seen.insert(tx.signature.clone()) {
+      return false;
+    }
+
+    for event in tx.events.iter() {
+      self.apply(event);
+    }
+    self.last_slot = u64::max(self.last_slot, tx.slot);
+
+    true
+  }
+
+  pub fn apply(
+    &mut self,
+    event: &SaleEvent,
+  ) {
+    match event {
+      SaleEvent::DepositSol(e) => self.apply_deposit(Deposit {
+        asset: Asset::Sol,
+        round: e.round,
+        beneficiary: e.beneficiary,
+        referral: e.referral,
+        amount: e.sol_amount,
+        usd_amount: e.usd_amount,
+        token_amount: e.token_amount,
+        bonus_amount: e.bonus_amount,
+        referral_amount: e.referral_amount,
+        referral_usd: e.referral_usd,
+        referral_token_amount: e.referral_token_amount,
+        shard: e.shard,
+        timestamp: e.timestamp,
+      }),
+      SaleEvent::DepositUsdc(e) => self.apply_deposit(Deposit {
+        asset: Asset::Usdc,
+        round: e.round,
+        beneficiary: e.beneficiary,
+        referral: e.referral,
+        amount: e.usdc_amount,
+        usd_amount: e.usd_amount,
+        token_amount: e.token_amount,
+        bonus_amount: e.bonus_amount,
+        referral_amount: e.referral_amount,
+        referral_usd: e.referral_usd,
+        referral_token_amount: e.referral_token_amount,
+        shard: e.shard,
+        timestamp: e.timestamp,
+      }),
+      SaleEvent::DepositUsdt(e) => self.apply_deposit(Deposit {
+        asset: Asset::Usdt,
+        round: e.round,
+        beneficiary: e.beneficiary,
+        referral: e.referral,
+        amount: e.usdt_amount,
+        usd_amount: e.usd_amount,
+        token_amount: e.token_amount,
+        bonus_amount: e.bonus_amount,
+        referral_amount: e.referral_amount,
+        referral_usd: e.referral_usd,
+        referral_token_amount: e.referral_token_amount,
+        shard: e.shard,
+        timestamp: e.timestamp,
+      }),
+      SaleEvent::ReferralUplineReward(e) => {
+        if e.shard.is_none() {
+          self.sale.spent_usd += e.reward_usd;
+          self.sale.spent_tokens += e.token_amount;
+        }
+
+        let referral = self.referral(e.referral);
+        referral.token_reward_amount += e.token_amount;
+        if let Some(asset) = Asset::from_mint(&e.mint) {
+          referral.balance.add(asset, e.amount);
+          referral.earned.add(asset, e.amount);
+        }
+      }
+      SaleEvent::WithdrawSol(e) => self.referral(e.referral).balance.sub(Asset::Sol, e.sol_amount),
+      SaleEvent::WithdrawUsdc(e) => self.referral(e.referral).balance.sub(Asset::Usdc, e.usdc_amount),
+      SaleEvent::WithdrawUsdt(e) => self.referral(e.referral).balance.sub(Asset::Usdt, e.usdt_amount),
+      SaleEvent::WithdrawAll(e) => {
+        let referral = self.referral(e.referral);
+        referral.balance.sub(Asset::Sol, e.sol_amount);
+        referral.balance.sub(Asset::Usdc, e.usdc_amount);
+        referral.balance.sub(Asset::Usdt, e.usdt_amount);
+      }
+      SaleEvent::ReferralClawback(e) => {
+        let referral = self.referral(e.referral);
+        referral.balance.sub(Asset::Sol, e.sol_amount);
+        referral.balance.sub(Asset::Usdc, e.usdc_amount);
+        referral.balance.sub(Asset::Usdt, e.usdt_amount);
+        referral.token_reward_amount = referral.token_reward_amount.saturating_sub(e.token_amount);
+      }
+      SaleEvent::Claim(e) => self.beneficiary(e.beneficiary).claimed_amount = e.claimed_amount,
+      SaleEvent::ClaimRefTokens(e) => self.referral(e.referral).token_claimed_amount = e.claimed_amount,
+      SaleEvent::ReferralRegistered(e) => {
+        let referral = self.referral(e.referral);
+        referral.registered = true;
+        referral.upline = e.upline;
+      }
+      SaleEvent::ReferralCodeRegistered(e) => {
+        self.referral(e.referral).codes.insert(e.code.clone());
+        self.referral_codes.insert(e.code.clone(), e.referral);
+      }
+      SaleEvent::ReferralTierChanged(e) => {
+        let referral = self.referral(e.referral);
+        referral.tier = e.to_tier;
+        referral.referred_usd = e.referred_usd;
+        referral.referred_count = e.referred_count;
+      }
+      SaleEvent::ReferralPayoutChanged(e) => self.referral(e.referral).payout_address = e.payout_address,
+      SaleEvent::ReferralConfigChanged(e) => self.referral(e.referral).set_config(&e.config),
+      SaleEvent::SalePaused(e) => {
+        self.sale.paused = e.paused;
+        self.sale.pause_reason = e.reason;
+      }
+      SaleEvent::SaleUnpaused(e) => {
+        self.sale.paused = e.paused;
+        if e.paused == 0 {
+          self.sale.pause_reason = 0;
+        }
+      }
+      SaleEvent::SaleConfigChanged(e) => {
+        self.sale.paused = e.config.paused;
+        self.sale.pause_reason = e.config.pause_reason;
+        self.sale.total_sold = e.config.total_sold;
+        self.sale.spent_usd = e.config.spent_usd;
+        self.sale.spent_tokens = e.config.spent_tokens;
+        self.sale.config = Some(e.config.clone());
+      }
+      SaleEvent::RoundConfigChanged(e) => {
+        let round = self.round(e.round);
+        round.state = Some(e.config.state);
+        round.clearing_price = e.config.clearing_price;
+        round.total_sold = e.config.total_sold;
+        round.settled_usd = e.config.settled_usd;
+        round.config = Some(e.config.clone());
+      }
+      SaleEvent::RoundStateChanged(e) => {
+        let round = self.round(e.round);
+        round.state = Some(e.to);
+        round.clearing_price = e.clearing_price;
+      }
+      SaleEvent::AuctionSettled(e) => {
+        // The fill is swapped for what it cleared at. The program drops the
+        // fills from the round on close, so the round only matches it once
+        // every buyer is settled.
+        let beneficiary = self.beneficiary(e.beneficiary);
+        beneficiary.token_amount = beneficiary.token_amount - e.bought_token_amount + e.token_amount;
+        let round = self.round(e.round);
+        round.total_sold = round.total_sold - e.bought_token_amount + e.token_amount;
+        round.settled_usd += e.settled_usd;
+        self.sale.total_sold = self.sale.total_sold - e.bought_token_amount + e.token_amount;
+      }
+      SaleEvent::ShardInitialized(e) => {
+        self.round(e.round).shards.entry(e.shard).or_insert(0);
+      }
+      SaleEvent::ShardSettled(e) => {
+        let round = self.round(e.round);
+        round.total_sold += e.sold_amount;
+        round.unsettled_sold = round.unsettled_sold.saturating_sub(e.sold_amount);
+        round.shards.insert(e.shard, e.capacity);
+        self.sale.total_sold += e.sold_amount;
+        self.sale.spent_usd += e.spent_usd;
+        self.sale.spent_tokens += e.spent_tokens;
+      }
+      SaleEvent::AccountMigrated(e) => {
+        self.account_versions.insert(e.account, e.to_version);
+      }
+      SaleEvent::AccountClosed(e) => {
+        if e.kind == AccountKind::Sale {
+          self.sale.closed = true;
+        }
+        self.closed_accounts.insert(e.account, e.kind);
+      }
+    }
+  }
+
+  fn apply_deposit(
+    &mut self,
+    deposit: Deposit,
+  ) {
+    let credited_amount = deposit.token_amount + deposit.bonus_amount;
+    let has_referral = deposit.referral != EMPTY_REFERRAL_KEY;
+
+    let beneficiary = self.beneficiary(deposit.beneficiary);
+    let new_buyer = has_referral && beneficiary.referrer.is_none();
+    if beneficiary.purchase_count == 0 {
+      beneficiary.first_purchase_at = deposit.timestamp;
+    }
+    if new_buyer {
+      beneficiary.referrer = Some(deposit.referral);
+    }
+    beneficiary.token_amount += credited_amount;
+    beneficiary.bonus_amount += deposit.bonus_amount;
+    beneficiary.usd_amount += deposit.usd_amount;
+    beneficiary.paid.add(deposit.asset, deposit.amount);
+    beneficiary.purchase_count += 1;
+    beneficiary.last_purchase_at = deposit.timestamp;
+
+    // Sharded deposits reach the sale and round totals when their shard is
+    // settled.
+    let round = self.round(deposit.round);
+    round.deposit_count += 1;
+    round.raised_usd += deposit.usd_amount;
+    match deposit.shard {
+      Some(_) => round.unsettled_sold += credited_amount,
+      None => round.total_sold += credited_amount,
+    }
+
+    self.sale.raised.add(deposit.asset, deposit.amount);
+    self.sale.raised_usd += deposit.usd_amount;
+    if deposit.shard.is_none() {
+      self.sale.total_sold += credited_amount;
+      self.sale.spent_usd += deposit.referral_usd;
+      self.sale.spent_tokens += deposit.referral_token_amount;
+    }
+
+    if has_referral {
+      let referral = self.referral(deposit.referral);
+      referral.balance.add(deposit.asset, deposit.referral_amount);
+      referral.earned.add(deposit.asset, deposit.referral_amount);
+      referral.token_reward_amount += deposit.referral_token_amount;
+      referral.referred_usd += deposit.usd_amount;
+      if new_buyer {
+        referral.referred_count += 1;
+      }
+    }
+  }
+
+  fn round(
+    &mut self,
+    id: i16,
+  ) -> &mut RoundView {
+    self.rounds.entry(id).or_default()
+  }
+
+  fn beneficiary(
+    &mut self,
+    wallet: Pubkey,
+  ) -> &mut BeneficiaryView {
+    self.beneficiaries.entry(wallet).or_default()
+  }
+
+  // Events name the wallet, so its PDA is derived the first time the wallet
+  // shows up.
+  fn referral(
+    &mut self,
+    wallet: Pubkey,
+  ) -> &mut ReferralView {
+    self.referrals.entry(wallet).or_insert_with(|| {
+      let (account, _) = Pubkey::find_program_address(&[REFERRAL_TAG, b"_", wallet.as_ref()], &sale::ID);
+      ReferralView {
+        account,
+        ..ReferralView::default()
+      }
+    })
+  }
+}
+
+impl ReferralView {
+  fn set_config(
+    &mut self,
+    config: &ReferralConfig,
+  ) {
+    self.registered = self.registered || config.initialized;
+    self.upline = config.upline;
+    self.tier = config.tier;
+    self.payout_address = config.payout_address;
+    self.config = Some(config.clone());
+  }
+}
@@ -0,0 +1,151 @@
+use anchor_lang::prelude::Pubkey;
+use base64::{ engine::general_purpose::STANDARD, Engine };
+use serde::Deserialize;
+use std::str::FromStr;
+
+use crate::decode::{ decode_event, decode_instruction, SaleEvent, SaleInstruction };
+use crate::error::{ IndexerError, Result };
+
+// The subset of a `getTransaction` response (with `"encoding": "json"`) the
+// indexer needs.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcTransaction {
+  pub slot: u64,
+  // Not part of `getTransaction`; exporters that know where the transaction
+  // sits in its block add it so same-slot transactions replay in order.
+  #[serde(default)]
+  pub index: Option<u32>,
+  pub block_time: Option<i64>,
+  pub meta: Option<RpcMeta>,
+  pub transaction: RpcTransactionBody,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcMeta {
+  pub err: Option<serde_json::Value>,
+  pub log_messages: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcTransactionBody {
+  pub signatures: Vec<String>,
+  pub message: RpcMessage,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcMessage {
+  pub account_keys: Vec<String>,
+  pub instructions: Vec<RpcInstruction>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcInstruction {
+  pub program_id_index: usize,
+  pub data: String,
+}
+
+pub struct Transaction {
+  pub signature: String,
+  pub slot: u64,
+  pub index: Option<u32>,
+  pub block_time: Option<i64>,
+  pub failed: bool,
+  pub instructions: Vec<SaleInstruction>,
+  pub events: Vec<SaleEvent>,
+}
+
+impl Transaction {
+  pub fn from_rpc(
+    program_id: &Pubkey,
+    rpc: &RpcTransaction,
+  ) -> Result<Transaction> {
+    let keys = rpc.transaction.message.account_keys
+      .iter()
+      .map(|key| Pubkey::from_str(key).map_err(|_| IndexerError::Pubkey(key.clone())))
+      .collect::<Result<Vec<Pubkey>>>()?;
+
+    let mut instructions = Vec::new();
+    for ix in rpc.transaction.message.instructions.iter() {
+      let program = keys.get(ix.program_id_index).ok_or(IndexerError::AccountIndex(ix.program_id_index))?;
+      if program != program_id {
+        continue;
+      }
+
+      if let Some(decoded) = decode_instruction(&bs58::decode(&ix.data).into_vec()?)? {
+        instructions.push(decoded);
+      }
+    }
+
+    let (failed, logs) = match &rpc.meta {
+      Some(meta) => (meta.err.is_some(), meta.log_messages.clone().unwrap_or_default()),
+      None => (false, Vec::new()),
+    };
+
+    Ok(Transaction {
+      signature: rpc.transaction.signatures.first().cloned().unwrap_or_default(),
+      slot: rpc.slot,
+      index: rpc.index,
+      block_time: rpc.block_time,
+      failed,
+      instructions,
+      events: parse_logs(program_id, &logs)?,
+    })
+  }
+}
+
+// Reads a file holding either one transaction or an array of them.
+pub fn parse_transactions(
+  program_id: &Pubkey,
+  json: &str,
+) -> Result<Vec<Transaction>> {
+  let value: serde_json::Value = serde_json::from_str(json)?;
+  let rpcs: Vec<RpcTransaction> = match value {
+    serde_json::Value::Array(_) => serde_json::from_value(value)?,
+    _ => vec![serde_json::from_value(value)?],
+  };
+
+  rpcs.iter().map(|rpc| Transaction::from_rpc(program_id, rpc)).collect()
+}
+
+// Collects the `Program data:` lines logged while the sale program is the
+// innermost running program, so events from CPI'd programs are ignored.
+pub fn parse_logs(
+  program_id: &Pubkey,
+  logs: &[String],
+) -> Result<Vec<SaleEvent>> {
+  let program = program_id.to_string();
+  let mut stack: Vec<&str> = Vec::new();
+  let mut events = Vec::new();
+
+  for log in logs {
+    if let Some(data) = log.strip_prefix("Program data: ") {
+      if stack.last() == Some(&program.as_str()) {
+        if let Some(event) = decode_event(&STANDARD.decode(data)?)? {
+          events.push(event);
+        }
+      }
+      continue;
+    }
+
+    let Some(rest) = log.strip_prefix("Program ") else {
+      continue;
+    };
+
+    let mut words = rest.split_whitespace();
+    match (words.next(), words.next()) {
+      (Some(id), Some("invoke")) => stack.push(id),
+      (Some(id), Some(status))
+        if (status == "success" || status.starts_with("failed")) && stack.last() == Some(&id) => {
+        stack.pop();
+      }
+      _ => {}
+    }
+  }
+
+  Ok(events)
+}
@@ -0,0 +1,117 @@
+use anchor_lang::prelude::Pubkey;
+use sale::instructions::referral::{ EMPTY_REFERRAL_KEY, REFERRAL_TAG };
+use sale::state::round::State as RoundState;
+use sale_indexer::projection::Projection;
+use sale_indexer::source::{ parse_transactions, Transaction };
+use sale_indexer::{ Indexer, Replay };
+
+const BUYER: Pubkey = Pubkey::new_from_array([1; 32]);
+const REFERRER: Pubkey = Pubkey::new_from_array([2; 32]);
+const UPLINE: Pubkey = Pubkey::new_from_array([3; 32]);
+const OTHER_BUYER: Pubkey = Pubkey::new_from_array([4; 32]);
+
+const FIXTURES: [&str; 4] = ["round.json", "referral.json", "deposit.json", "claim.json"];
+
+fn fixture(
+  name: &str,
+) -> Vec<Transaction> {
+  let path = format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"));
+  let json = std::fs::read_to_string(&path).unwrap();
+  parse_transactions(&sale::ID, &json).unwrap()
+}
+
+fn replay(
+  names: &[&str],
+) -> (Indexer, Replay) {
+  let mut indexer = Indexer::default();
+  let transactions = names.iter().flat_map(|name| fixture(name)).collect();
+  let replay = indexer.replay(transactions);
+  (indexer, replay)
+}
+
+fn assert_projection(
+  projection: &Projection,
+) {
+  let sale = &projection.sale;
+  assert_eq!(sale.raised.sol, 1_000_000_000);
+  assert_eq!(sale.raised.usdc, 10_000_000);
+  assert_eq!(sale.raised_usd, 154_000_000);
+  assert_eq!(sale.total_sold, 1_545_000_000);
+  assert_eq!(sale.spent_usd, 10_080_000);
+
+  let round = &projection.rounds[&1];
+  assert_eq!(round.state, Some(RoundState::Closed));
+  assert_eq!(round.total_sold, 500 + 1_545_000_000);
+  assert_eq!(round.raised_usd, 154_000_000);
+  assert_eq!(round.deposit_count, 2);
+
+  let buyer = &projection.beneficiaries[&BUYER];
+  assert_eq!(buyer.token_amount, 1_440_000_000);
+  assert_eq!(buyer.claimed_amount, 1_000_000_000);
+  assert_eq!(buyer.paid.sol, 1_000_000_000);
+  assert_eq!(buyer.referrer, Some(REFERRER));
+  assert_eq!(buyer.purchase_count, 1);
+
+  let other = &projection.beneficiaries[&OTHER_BUYER];
+  assert_eq!(other.token_amount, 105_000_000);
+  assert_eq!(other.bonus_amount, 5_000_000);
+  assert_eq!(other.paid.usdc, 10_000_000);
+  assert_eq!(other.referrer, None);
+
+  let referral = &projection.referrals[&REFERRER];
+  let (account, _) = Pubkey::find_program_address(&[REFERRAL_TAG, b"_", REFERRER.as_ref()], &sale::ID);
+  assert_eq!(referral.account, account);
+  assert!(referral.registered);
+  assert_eq!(referral.upline, UPLINE);
+  assert!(referral.config.as_ref().unwrap().enabled);
+  assert_eq!(referral.earned.sol, 50_000_000);
+  assert_eq!(referral.balance.sol, 0);
+  assert_eq!(referral.referred_usd, 144_000_000);
+  assert_eq!(referral.referred_count, 1);
+
+  let upline = &projection.referrals[&UPLINE];
+  assert_eq!(upline.balance.sol, 20_000_000);
+  assert_eq!(upline.earned.sol, 20_000_000);
+  assert!(!projection.referrals.contains_key(&EMPTY_REFERRAL_KEY));
+
+  assert_eq!(projection.last_slot, 300);
+}
+
+#[test]
+fn replays_fixtures() {
+  let (indexer, replay) = replay(&FIXTURES);
+
+  assert_eq!(replay, Replay { applied: 9, ambiguous_slots: Vec::new() });
+  assert_projection(&indexer.projection);
+}
+
+#[test]
+fn replay_does_not_depend_on_input_order() {
+  let mut names = FIXTURES;
+  names.reverse();
+  let (indexer, replay) = replay(&names);
+
+  assert!(replay.ambiguous_slots.is_empty());
+  assert_projection(&indexer.projection);
+}
+
+#[test]
+fn skips_failed_and_repeated_transactions() {
+  let mut indexer = Indexer::default();
+  let transactions = fixture("deposit.json");
+  assert_eq!(transactions.len(), 4);
+  assert!(transactions[2].failed);
+  assert_eq!(transactions[0].instructions[0].name(), "Deposit");
+
+  assert_eq!(indexer.replay(transactions).applied, 2);
+  assert_eq!(indexer.replay(fixture("deposit.json")).applied, 0);
+  assert_eq!(indexer.projection.beneficiaries[&OTHER_BUYER].paid.sol, 0);
+}
+
+#[test]
+fn reports_slots_without_indices() {
+  let (indexer, replay) = replay(&["unindexed.json"]);
+
+  assert_eq!(replay, Replay { applied: 2, ambiguous_slots: vec![400] });
+  assert_eq!(indexer.projection.beneficiaries[&OTHER_BUYER].claimed_amount, 10_000_000);
+}
@@ -1,8 +1,13 @@
 use anchor_lang::prelude::*;
 
+#[cfg(not(feature = "bench"))]
 pub const TREASURY: &str = "<>";
+// Known treasury for the compute unit benchmark in `tests/deposit-compute.ts`.
+#[cfg(feature = "bench")]
+pub const TREASURY: &str = "86s2R7MYkrrvyYLf4zh34WUNbnKGpyrkxdWs6KVouTKt";
 pub const SOL_USD_PRICEFEED: &str = "J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix";
 
+pub const PRECISION: u32 = 9;
 pub const STABLE_PRECISION: u32 = 3;
 pub const USDT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
 pub const USDC: &str = "Gh9ZwEmdLJ8DscKNTkTqPbNwLNNBjuSzaG9Vp2KGtKJr";
@@ -10,5 +15,5 @@ pub const USDC: &str = "Gh9ZwEmdLJ8DscKNTkTqPbNwLNNBjuSzaG9Vp2KGtKJr";
 const ADMINS: &[&str] = &["3tHXr7UqSpiHJsJjpPSxs823HdPysKuREaayzWFh6vxD"];
 
 pub fn only_admin(address: Pubkey) -> bool {
-  return  ADMINS.contains(&address.to_string().as_str());
+  ADMINS.contains(&address.to_string().as_str())
 }
\ No newline at end of file
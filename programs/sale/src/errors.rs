@@ -42,4 +42,190 @@ pub enum Sale {
   PriceIsDown,
   #[msg("Referral no funds")]
   ReferralNoFunds,
-}
\ No newline at end of file
+  #[msg("Invalid pause scope")]
+  InvalidPauseScope,
+  #[msg("Deposits are paused")]
+  DepositsPaused,
+  #[msg("Referral withdrawals are paused")]
+  WithdrawalsPaused,
+  #[msg("Claims are paused")]
+  ClaimsPaused,
+  #[msg("Round state transition not allowed")]
+  RoundInvalidTransition,
+  #[msg("Round cancelled")]
+  RoundCancelled,
+  #[msg("Invalid round pricing")]
+  RoundInvalidPricing,
+  #[msg("Previous auction purchase not settled")]
+  AuctionNotSettled,
+  #[msg("Auction clearing price not set")]
+  AuctionNotCleared,
+  #[msg("Nothing to settle for this auction")]
+  AuctionNothingToSettle,
+  #[msg("Math overflow")]
+  MathOverflow,
+  #[msg("Transfer failed")]
+  TransferFailed,
+  #[msg("Invalid token mint")]
+  InvalidMint,
+  #[msg("Invalid token account owner")]
+  InvalidOwner,
+  #[msg("Sale referral levels invalid")]
+  SaleRefLevelsInvalid,
+  #[msg("Referral cannot be its own upline")]
+  ReferralSelfUpline,
+  #[msg("Referral upline account missing")]
+  ReferralUplineMissing,
+  #[msg("Referral upline account mismatch")]
+  ReferralUplineMismatch,
+  #[msg("Sale claim configuration invalid")]
+  SaleClaimInvalid,
+  #[msg("Claim not started")]
+  ClaimNotStarted,
+  #[msg("Nothing to claim")]
+  NothingToClaim,
+  #[msg("Referral already registered")]
+  ReferralAlreadyRegistered,
+  #[msg("Referral not registered")]
+  ReferralNotRegistered,
+  #[msg("Invalid referral code")]
+  InvalidReferralCode,
+  #[msg("Self-referral is not allowed")]
+  SelfReferral,
+  #[msg("Beneficiary is bound to another referrer")]
+  ReferrerMismatch,
+  #[msg("Purchase too small for a referral")]
+  ReferralPurchaseTooSmall,
+  #[msg("Sale reward tiers invalid")]
+  SaleRewardTiersInvalid,
+  #[msg("Sale reward mode invalid")]
+  SaleRewardModeInvalid,
+  #[msg("Buyer bonus invalid")]
+  BuyerBonusInvalid,
+  #[msg("Remaining accounts must come in pairs")]
+  InvalidAccountPair,
+  #[msg("Referral payout address not set")]
+  PayoutAddressNotSet,
+  #[msg("Invalid payout address")]
+  InvalidPayoutAddress,
+  #[msg("Referral disabled")]
+  ReferralDisabled,
+  #[msg("Invalid referral key")]
+  InvalidReferralKey,
+  #[msg("Beneficiary has unclaimed tokens")]
+  BeneficiaryNotClaimed,
+  #[msg("Referral has unwithdrawn rewards")]
+  ReferralNotEmpty,
+  #[msg("Round not finalised")]
+  RoundNotFinalised,
+  #[msg("Sale not finalised")]
+  SaleNotFinalised,
+  #[msg("Invalid account")]
+  InvalidAccount,
+  #[msg("Account already migrated")]
+  AccountAlreadyMigrated,
+  #[msg("Invalid shard")]
+  ShardInvalid,
+  #[msg("Shard capacity exceeded")]
+  ShardCapacityExceeded,
+  #[msg("Round cannot be sharded")]
+  ShardingUnsupported,
+  #[msg("Round schedule conflicts with its auction start")]
+  RoundScheduleConflict,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use anchor_lang::solana_program::program_pack::Pack;
+  use anchor_spl::token::spl_token::state::{ Account as SplAccount, AccountState };
+  use anchor_spl::token::{ self, Token };
+  use crate::auth::{ USDC, USDT };
+  use crate::instructions::referral::{ withdraw_balances, REFERRAL_TAG };
+  use crate::state::referral::Referral;
+
+  fn leak<T>(value: T) -> &'static mut T {
+    Box::leak(Box::new(value))
+  }
+
+  fn account_info(
+    key: Pubkey,
+    owner: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+    executable: bool,
+  ) -> AccountInfo<'static> {
+    AccountInfo::new(leak(key), false, true, leak(lamports), Box::leak(data.into_boxed_slice()), leak(owner), executable, 0)
+  }
+
+  fn token_account(
+    mint: Pubkey,
+    owner: Pubkey,
+  ) -> AccountInfo<'static> {
+    let mut data = vec![0u8; SplAccount::LEN];
+    SplAccount { mint, owner, state: AccountState::Initialized, ..SplAccount::default() }.pack_into_slice(&mut data);
+    account_info(Pubkey::new_unique(), token::ID, 1, data, false)
+  }
+
+  fn withdraw(
+    sol_reward_amount: u64,
+    lamports: u64,
+    token_accounts: impl FnOnce(Pubkey, Pubkey) -> Vec<AccountInfo<'static>>,
+  ) -> Result<(u64, u64, u64)> {
+    let ref_key = Pubkey::new_unique();
+    let (referral_key, bump) = Pubkey::find_program_address(&[REFERRAL_TAG, b"_", ref_key.as_ref()], &crate::ID);
+
+    let mut referral = Referral::deserialize(&mut &vec![0u8; Referral::INIT_SPACE][..])?;
+    referral.set_sol_reward_amount(sol_reward_amount)?;
+    let mut data = Vec::new();
+    referral.try_serialize(&mut data)?;
+
+    let referral_info = leak(account_info(referral_key, crate::ID, lamports, data, false));
+    let mut referral = Account::<Referral>::try_from(referral_info)?;
+    let recipient = account_info(Pubkey::new_unique(), Pubkey::default(), 0, Vec::new(), false);
+    let program_info = leak(account_info(token::ID, Pubkey::default(), 1, Vec::new(), true));
+    let program = Program::<Token>::try_from(&*program_info)?;
+    let remaining_accounts = Box::leak(token_accounts(referral_key, recipient.key()).into_boxed_slice());
+
+    withdraw_balances(&mut referral, ref_key, bump, &recipient, &program, remaining_accounts)
+  }
+
+  #[test]
+  fn codes_are_stable() {
+    assert_eq!(u32::from(Sale::MathOverflow), 6030);
+    assert_eq!(u32::from(Sale::TransferFailed), 6031);
+    assert_eq!(u32::from(Sale::InvalidMint), 6032);
+    assert_eq!(u32::from(Sale::InvalidOwner), 6033);
+    assert_eq!(Sale::TransferFailed.to_string(), "Transfer failed");
+    assert_eq!(Sale::InvalidMint.to_string(), "Invalid token mint");
+    assert_eq!(Sale::InvalidOwner.to_string(), "Invalid token account owner");
+  }
+
+  #[test]
+  fn transfer_failed() {
+    assert!(withdraw(10, 100, |_, _| Vec::new()).is_ok());
+    assert_eq!(withdraw(100, 10, |_, _| Vec::new()).unwrap_err(), Sale::TransferFailed.into());
+  }
+
+  #[test]
+  fn invalid_mint() {
+    let usdc = USDC.parse().unwrap();
+    let usdt = USDT.parse().unwrap();
+    let result = withdraw(0, 1, |referral, recipient| vec![token_account(usdc, referral), token_account(usdt, recipient)]);
+    assert_eq!(result.unwrap_err(), Sale::InvalidMint.into());
+
+    let mint = Pubkey::new_unique();
+    let result = withdraw(0, 1, |referral, recipient| vec![token_account(mint, referral), token_account(mint, recipient)]);
+    assert_eq!(result.unwrap_err(), Sale::InvalidMint.into());
+  }
+
+  #[test]
+  fn invalid_owner() {
+    let usdc = USDC.parse().unwrap();
+    let result = withdraw(0, 1, |_, recipient| vec![token_account(usdc, Pubkey::new_unique()), token_account(usdc, recipient)]);
+    assert_eq!(result.unwrap_err(), Sale::InvalidOwner.into());
+
+    let result = withdraw(0, 1, |referral, _| vec![token_account(usdc, referral), token_account(usdc, Pubkey::new_unique())]);
+    assert_eq!(result.unwrap_err(), Sale::InvalidOwner.into());
+  }
+}
@@ -1,46 +1,335 @@
 use anchor_lang::prelude::*;
+use crate::state::round::{ State as RoundState, RoundConfig };
+use crate::state::sale::SaleConfig;
+use crate::state::referral::ReferralConfig;
+
+// Bumped whenever a field is added to or removed from any event below, so
+// indexers can pick the matching decoder.
+pub const EVENT_VERSION: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+pub enum SaleAction {
+  Initialized,
+  InvestmentChanged,
+  RewardChanged,
+  ReferralLevelsChanged,
+  ReferralBudgetChanged,
+  RewardModeChanged,
+  BuyerBonusChanged,
+  ReferralProgramChanged,
+  RewardTiersChanged,
+  ReferralRulesChanged,
+  ClaimChanged,
+  Opened,
+  Closed,
+  RoundActivated,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+pub enum RoundAction {
+  Initialized,
+  PriceChanged,
+  PricingChanged,
+  AuctionChanged,
+  SupplyChanged,
+  ShardsChanged,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+pub enum ReferralAction {
+  Initialized,
+  RewardChanged,
+  RewardModeChanged,
+  BuyerBonusChanged,
+  UplineChanged,
+  Enabled,
+  Disabled,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+pub enum AccountKind {
+  Sale,
+  Round,
+  Beneficiary,
+  Referral,
+}
 
 #[event]
 pub struct DepositSolEvent {
+  pub version: u8,
   pub round: i16,
   pub beneficiary: Pubkey,
   pub referral: Pubkey,
   pub sol_amount: u64,
+  pub usd_amount: u128,
+  pub asset_price: u64,
   pub token_amount: u128,
+  pub bonus_amount: u128,
+  pub avg_price: u64,
+  pub referral_amount: u64,
+  pub referral_usd: u128,
+  pub referral_token_amount: u128,
+  pub upline_amount: u64,
+  pub shard: Option<u8>,
+  pub timestamp: i64,
 }
 
 #[event]
 pub struct DepositUsdtEvent {
+  pub version: u8,
   pub round: i16,
   pub beneficiary: Pubkey,
   pub referral: Pubkey,
   pub usdt_amount: u64,
+  pub usd_amount: u128,
+  pub asset_price: u64,
   pub token_amount: u128,
+  pub bonus_amount: u128,
+  pub avg_price: u64,
+  pub referral_amount: u64,
+  pub referral_usd: u128,
+  pub referral_token_amount: u128,
+  pub upline_amount: u64,
+  pub shard: Option<u8>,
+  pub timestamp: i64,
 }
 
 #[event]
 pub struct DepositUsdcEvent {
+  pub version: u8,
   pub round: i16,
   pub beneficiary: Pubkey,
   pub referral: Pubkey,
   pub usdc_amount: u64,
+  pub usd_amount: u128,
+  pub asset_price: u64,
   pub token_amount: u128,
+  pub bonus_amount: u128,
+  pub avg_price: u64,
+  pub referral_amount: u64,
+  pub referral_usd: u128,
+  pub referral_token_amount: u128,
+  pub upline_amount: u64,
+  pub shard: Option<u8>,
+  pub timestamp: i64,
 }
 
 #[event]
 pub struct WithdrawSolEvent {
+  pub version: u8,
   pub referral: Pubkey,
   pub sol_amount: u64,
+  pub timestamp: i64,
 }
 
 #[event]
 pub struct WithdrawUsdtEvent {
+  pub version: u8,
   pub referral: Pubkey,
   pub usdt_amount: u64,
+  pub timestamp: i64,
 }
 
 #[event]
 pub struct WithdrawUsdcEvent {
+  pub version: u8,
+  pub referral: Pubkey,
+  pub usdc_amount: u64,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawAllEvent {
+  pub version: u8,
+  pub referral: Pubkey,
+  pub recipient: Pubkey,
+  pub sol_amount: u64,
+  pub usdc_amount: u64,
+  pub usdt_amount: u64,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct SalePausedEvent {
+  pub version: u8,
+  pub scope: u8,
+  pub paused: u8,
+  pub reason: u8,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct SaleUnpausedEvent {
+  pub version: u8,
+  pub scope: u8,
+  pub paused: u8,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct RoundStateChangedEvent {
+  pub version: u8,
+  pub round: i16,
+  pub from: RoundState,
+  pub to: RoundState,
+  pub clearing_price: u64,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct ShardSettledEvent {
+  pub version: u8,
+  pub round: i16,
+  pub shard: u8,
+  pub sold_amount: u128,
+  pub released: u128,
+  pub capacity: u128,
+  pub spent_usd: u128,
+  pub spent_tokens: u128,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionSettledEvent {
+  pub version: u8,
+  pub round: i16,
+  pub beneficiary: Pubkey,
+  pub clearing_price: u64,
+  pub bought_token_amount: u128,
+  pub token_amount: u128,
+  pub settled_usd: u128,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralUplineRewardEvent {
+  pub version: u8,
+  pub round: i16,
+  pub beneficiary: Pubkey,
+  pub referral: Pubkey,
+  pub level: u8,
+  pub mint: Pubkey,
+  pub amount: u64,
+  pub token_amount: u128,
+  pub reward_usd: u128,
+  pub shard: Option<u8>,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct ClaimEvent {
+  pub version: u8,
+  pub beneficiary: Pubkey,
+  pub token_amount: u64,
+  pub claimed_amount: u128,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct ClaimRefTokensEvent {
+  pub version: u8,
+  pub referral: Pubkey,
+  pub token_amount: u64,
+  pub claimed_amount: u128,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralRegisteredEvent {
+  pub version: u8,
+  pub referral: Pubkey,
+  pub upline: Pubkey,
+  pub main_reward: u64,
+  pub secondary_reward: u64,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralCodeRegisteredEvent {
+  pub version: u8,
+  pub referral: Pubkey,
+  pub code: String,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralTierChangedEvent {
+  pub version: u8,
+  pub referral: Pubkey,
+  pub from_tier: u8,
+  pub to_tier: u8,
+  pub referred_usd: u128,
+  pub referred_count: u32,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralPayoutChangedEvent {
+  pub version: u8,
+  pub referral: Pubkey,
+  pub payout_address: Pubkey,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralClawbackEvent {
+  pub version: u8,
   pub referral: Pubkey,
+  pub sol_amount: u64,
   pub usdc_amount: u64,
+  pub usdt_amount: u64,
+  pub token_amount: u128,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct AccountMigratedEvent {
+  pub version: u8,
+  pub account: Pubkey,
+  pub from_version: u8,
+  pub to_version: u8,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct SaleConfigChangedEvent {
+  pub version: u8,
+  pub action: SaleAction,
+  pub config: SaleConfig,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct RoundConfigChangedEvent {
+  pub version: u8,
+  pub round: i16,
+  pub action: RoundAction,
+  pub config: RoundConfig,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralConfigChangedEvent {
+  pub version: u8,
+  pub referral: Pubkey,
+  pub action: ReferralAction,
+  pub config: ReferralConfig,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct ShardInitializedEvent {
+  pub version: u8,
+  pub round: i16,
+  pub shard: u8,
+  pub timestamp: i64,
+}
+
+#[event]
+pub struct AccountClosedEvent {
+  pub version: u8,
+  pub account: Pubkey,
+  pub kind: AccountKind,
+  pub recipient: Pubkey,
+  pub timestamp: i64,
 }
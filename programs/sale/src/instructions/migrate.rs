@@ -0,0 +1,286 @@
+use anchor_lang::{
+  prelude::*,
+  system_program::{ self, Transfer },
+  Discriminator,
+};
+
+use bytemuck::Zeroable;
+use std::mem::size_of;
+
+use crate::errors;
+use crate::events;
+use crate::state::LEGACY_ACCOUNT_SPACE;
+use crate::state::sale::Sale;
+use crate::state::round::Round;
+use crate::state::legacy::{ LegacySale, LegacyRound };
+use crate::state::referral::Referral;
+use crate::state::referral_code::ReferralCode;
+use crate::state::beneficiary::Beneficiary;
+
+// `Sale` and `Round` moved from Borsh to zero-copy layouts, which are told
+// apart from the legacy ones by their exact size.
+const _: () = assert!(size_of::<Sale>() != LegacySale::INIT_SPACE && 8 + size_of::<Sale>() != LEGACY_ACCOUNT_SPACE);
+const _: () = assert!(size_of::<Round>() != LegacyRound::INIT_SPACE && 8 + size_of::<Round>() != LEGACY_ACCOUNT_SPACE);
+
+pub fn migrate_sale(
+  ctx: Context<MigrateAccount>,
+) -> Result<()> {
+  let info = &ctx.accounts.account;
+  check_account(info, Sale::DISCRIMINATOR)?;
+  if info.data_len() == 8 + size_of::<Sale>() {
+    return err!(errors::Sale::AccountAlreadyMigrated);
+  }
+
+  let legacy = LegacySale::deserialize(&mut &read_legacy(info, LegacySale::INIT_SPACE)?[..])?;
+  resize_account(info, 8 + size_of::<Sale>(), &ctx.accounts.payer, &ctx.accounts.system_program)?;
+
+  let mut data = info.try_borrow_mut_data()?;
+  let sale: &mut Sale = bytemuck::from_bytes_mut(&mut data[8..8 + size_of::<Sale>()]);
+  *sale = Sale::zeroed();
+  let from_version = sale.migrate(&legacy)?;
+  let to_version = sale.get_version();
+  drop(data);
+
+  emit_migrated(info.key(), from_version, to_version)
+}
+
+pub fn migrate_round(
+  ctx: Context<MigrateAccount>,
+) -> Result<()> {
+  let info = &ctx.accounts.account;
+  check_account(info, Round::DISCRIMINATOR)?;
+  if info.data_len() == 8 + size_of::<Round>() {
+    return err!(errors::Sale::AccountAlreadyMigrated);
+  }
+
+  let legacy = LegacyRound::deserialize(&mut &read_legacy(info, LegacyRound::INIT_SPACE)?[..])?;
+  resize_account(info, 8 + size_of::<Round>(), &ctx.accounts.payer, &ctx.accounts.system_program)?;
+
+  let mut data = info.try_borrow_mut_data()?;
+  let round: &mut Round = bytemuck::from_bytes_mut(&mut data[8..8 + size_of::<Round>()]);
+  *round = Round::zeroed();
+  let from_version = round.migrate(&legacy)?;
+  let to_version = round.get_version();
+  drop(data);
+
+  emit_migrated(info.key(), from_version, to_version)
+}
+
+pub fn migrate_referral(
+  ctx: Context<MigrateAccount>,
+) -> Result<()> {
+  let info = &ctx.accounts.account;
+  realloc_account(info, Referral::DISCRIMINATOR, 8 + Referral::INIT_SPACE, &ctx.accounts.payer, &ctx.accounts.system_program)?;
+
+  let mut referral = Referral::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+  let from_version = referral.set_version()?;
+  referral.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+  emit_migrated(info.key(), from_version, referral.get_version())
+}
+
+pub fn migrate_referral_code(
+  ctx: Context<MigrateAccount>,
+) -> Result<()> {
+  let info = &ctx.accounts.account;
+  realloc_account(info, ReferralCode::DISCRIMINATOR, 8 + ReferralCode::INIT_SPACE, &ctx.accounts.payer, &ctx.accounts.system_program)?;
+
+  let mut referral_code = ReferralCode::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+  let from_version = referral_code.set_version()?;
+  referral_code.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+  emit_migrated(info.key(), from_version, referral_code.get_version())
+}
+
+pub fn migrate_beneficiary(
+  ctx: Context<MigrateAccount>,
+) -> Result<()> {
+  let info = &ctx.accounts.account;
+  realloc_account(info, Beneficiary::DISCRIMINATOR, 8 + Beneficiary::INIT_SPACE, &ctx.accounts.payer, &ctx.accounts.system_program)?;
+
+  let mut beneficiary = Beneficiary::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+  let from_version = beneficiary.set_version()?;
+  beneficiary.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+  emit_migrated(info.key(), from_version, beneficiary.get_version())
+}
+
+// Grows an account written with an older, shorter layout to the current
+// size. New bytes are zeroed, which every appended field reads as its
+// default. Accounts already large enough (the original 680-byte
+// allocations) are left as they are.
+fn realloc_account<'info>(
+  info: &AccountInfo<'info>,
+  discriminator: [u8; 8],
+  space: usize,
+  payer: &Signer<'info>,
+  system_program: &Program<'info, System>,
+) -> Result<()> {
+  check_account(info, discriminator)?;
+  if info.data_len() >= space {
+    return Ok(());
+  }
+
+  resize_account(info, space, payer, system_program)
+}
+
+pub fn check_account(
+  info: &AccountInfo,
+  discriminator: [u8; 8],
+) -> Result<()> {
+  if info.owner != &crate::ID {
+    return err!(errors::Sale::InvalidOwner);
+  }
+
+  if info.try_borrow_data()?.get(..8) != Some(&discriminator[..]) {
+    return err!(errors::Sale::InvalidAccount);
+  }
+
+  Ok(())
+}
+
+// Account data after the discriminator, zero-padded to `space` so layouts
+// that predate appended fields read them as defaults.
+fn read_legacy(
+  info: &AccountInfo,
+  space: usize,
+) -> Result<Vec<u8>> {
+  let mut buffer = info.try_borrow_data()?[8..].to_vec();
+  if buffer.len() < space {
+    buffer.resize(space, 0);
+  }
+
+  Ok(buffer)
+}
+
+fn resize_account<'info>(
+  info: &AccountInfo<'info>,
+  space: usize,
+  payer: &Signer<'info>,
+  system_program: &Program<'info, System>,
+) -> Result<()> {
+  let minimum_balance = Rent::get()?.minimum_balance(space);
+  let rent = minimum_balance.saturating_sub(info.lamports());
+  if rent > 0 {
+    let cpi_accounts = Transfer {
+      from: payer.to_account_info(),
+      to: info.clone(),
+    };
+    system_program::transfer(CpiContext::new(system_program.to_account_info(), cpi_accounts), rent)?;
+  }
+
+  info.realloc(space, true)?;
+
+  refund_rent(info, payer, minimum_balance)
+}
+
+// A shrunk account holds more rent than its new size needs; the excess
+// goes back to the payer.
+fn refund_rent(
+  info: &AccountInfo,
+  payer: &AccountInfo,
+  minimum_balance: u64,
+) -> Result<()> {
+  let excess = info.lamports().saturating_sub(minimum_balance);
+  if excess > 0 {
+    info.sub_lamports(excess)?;
+    payer.add_lamports(excess)?;
+  }
+
+  Ok(())
+}
+
+fn emit_migrated(
+  account: Pubkey,
+  from_version: u8,
+  to_version: u8,
+) -> Result<()> {
+  emit!(events::AccountMigratedEvent {
+    version: events::EVENT_VERSION,
+    account,
+    from_version,
+    to_version,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MigrateAccount<'info> {
+  /// CHECK : Owner and discriminator are checked before the account is reallocated
+  #[account(mut)]
+  pub account: AccountInfo<'info>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+  pub system_program: Program<'info, System>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn leak<T>(value: T) -> &'static mut T {
+    Box::leak(Box::new(value))
+  }
+
+  fn account_info(
+    owner: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+  ) -> AccountInfo<'static> {
+    AccountInfo::new(leak(Pubkey::new_unique()), false, true, leak(lamports), Box::leak(data.into_boxed_slice()), leak(owner), false, 0)
+  }
+
+  #[test]
+  fn refunds_rent_of_shrunk_account() {
+    let info = account_info(crate::ID, 1_000, Vec::new());
+    let payer = account_info(Pubkey::default(), 50, Vec::new());
+
+    refund_rent(&info, &payer, 600).unwrap();
+    assert_eq!((info.lamports(), payer.lamports()), (600, 450));
+
+    refund_rent(&info, &payer, 800).unwrap();
+    assert_eq!((info.lamports(), payer.lamports()), (600, 450));
+  }
+
+  #[test]
+  fn reads_legacy_account_data() {
+    let mut data = Round::DISCRIMINATOR.to_vec();
+    data.extend([7u8; 16]);
+    let info = account_info(crate::ID, 0, data);
+
+    check_account(&info, Round::DISCRIMINATOR).unwrap();
+    assert_eq!(check_account(&info, Sale::DISCRIMINATOR).unwrap_err(), errors::Sale::InvalidAccount.into());
+
+    let buffer = read_legacy(&info, 32).unwrap();
+    assert_eq!(buffer[..16], [7u8; 16]);
+    assert_eq!(buffer[16..], [0u8; 16]);
+  }
+
+  #[test]
+  fn rejects_foreign_accounts() {
+    let info = account_info(Pubkey::new_unique(), 0, Round::DISCRIMINATOR.to_vec());
+    assert_eq!(check_account(&info, Round::DISCRIMINATOR).unwrap_err(), errors::Sale::InvalidOwner.into());
+  }
+
+  #[test]
+  fn legacy_referral_keeps_its_fields() {
+    let mut referral = Referral::deserialize(&mut &vec![0u8; Referral::INIT_SPACE][..]).unwrap();
+    referral.set_reward(1, 2, 1_000_000_000).unwrap();
+    referral.set_sol_reward_amount(3).unwrap();
+    referral.set_upline(Pubkey::new_unique()).unwrap();
+    let mut data = Vec::new();
+    referral.try_serialize(&mut data).unwrap();
+    data.resize(LEGACY_ACCOUNT_SPACE, 0);
+
+    let mut migrated = Referral::try_deserialize(&mut &data[..]).unwrap();
+    assert_eq!(migrated.set_version().unwrap(), 1);
+    assert_eq!(migrated.get_reward(), referral.get_reward());
+    assert_eq!(migrated.get_sol_reward_amount(), referral.get_sol_reward_amount());
+    assert_eq!(migrated.get_upline(), referral.get_upline());
+    // Off legacy referrals are the implicit ones, never registered.
+    assert!(!migrated.is_disabled());
+    assert!(!migrated.is_initialized());
+  }
+}
@@ -1,6 +1,10 @@
 pub use sale::*;
 pub use round::*;
 pub use referral::*;
+pub use migrate::*;
+pub use shard::*;
 pub mod sale;
 pub mod round;
-pub mod referral;
\ No newline at end of file
+pub mod referral;
+pub mod migrate;
+pub mod shard;
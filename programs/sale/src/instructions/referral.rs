@@ -1,41 +1,166 @@
-use anchor_lang::prelude::*;
+use anchor_lang::{ prelude::*, Discriminator };
 use anchor_spl::token::{self, Token, TokenAccount, Transfer as SplTransfer};
-use crate::auth::{ USDC, USDT };
+use std::mem::size_of;
+use crate::auth::{ TREASURY, USDC, USDT };
 
 use crate::events;
 use crate::errors;
 use crate::state::referral::*;
+use crate::state::referral_code::*;
+use crate::state::sale::{ Sale, RewardMode, PAUSE_WITHDRAW, PAUSE_CLAIM };
+use crate::state::beneficiary::Beneficiary;
+use crate::instructions::sale::{ emit_account_closed, spend_reward };
+use crate::instructions::migrate::check_account;
+use crate::auth::PRECISION;
+use crate::math;
 
 pub fn initialize_referral(
   ctx: Context<InitReferral>,
+  ref_key: Pubkey,
   main_reward: u64,
   secondary_reward: u64,
 ) -> Result<()> {
+  check_ref_key(ref_key)?;
+
+  let sale = &ctx.accounts.sale.load()?;
+  let referral = &mut ctx.accounts.referral;
+  referral.init(main_reward, secondary_reward, sale.get_max_main_reward()?)?;
+
+  emit_referral_config(ref_key, referral, events::ReferralAction::Initialized)
+}
+
+pub fn register_referral(
+  ctx: Context<RegisterReferral>,
+  upline: Pubkey,
+) -> Result<()> {
+  let payer = &ctx.accounts.payer;
+  let sale = &mut ctx.accounts.sale.load_mut()?;
   let referral = &mut ctx.accounts.referral;
-  referral.init(main_reward, secondary_reward)
+
+  if payer.key() == upline {
+    return err!(errors::Sale::ReferralSelfUpline);
+  }
+
+  let (main_reward, secondary_reward) = sale.get_reward();
+  referral.register(main_reward, secondary_reward, upline)?;
+
+  emit!(events::ReferralRegisteredEvent {
+    version: events::EVENT_VERSION,
+    referral: payer.key(),
+    upline,
+    main_reward,
+    secondary_reward,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+pub fn register_referral_code(
+  ctx: Context<RegisterReferralCode>,
+  code: String,
+) -> Result<()> {
+  let payer = &ctx.accounts.payer;
+  let referral_code = &mut ctx.accounts.referral_code;
+
+  ReferralCode::validate(&code)?;
+
+  if !ctx.accounts.referral.is_initialized() {
+    return err!(errors::Sale::ReferralNotRegistered);
+  }
+
+  referral_code.init(payer.key())?;
+
+  emit!(events::ReferralCodeRegisteredEvent {
+    version: events::EVENT_VERSION,
+    referral: payer.key(),
+    code,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
 }
 
 pub fn set_referral_reward(
   ctx: Context<SetReferralReward>,
+  ref_key: Pubkey,
   main_reward: u64,
   secondary_reward: u64,
 ) -> Result<()> {
+  check_ref_key(ref_key)?;
+
+  let sale = &ctx.accounts.sale.load()?;
+  let referral = &mut ctx.accounts.referral;
+  referral.set_reward(main_reward, secondary_reward, sale.get_max_main_reward()?)?;
+
+  emit_referral_config(ref_key, referral, events::ReferralAction::RewardChanged)
+}
+
+pub fn set_referral_reward_mode(
+  ctx: Context<SetReferralRewardMode>,
+  ref_key: Pubkey,
+  reward_mode: Option<RewardMode>,
+) -> Result<()> {
+  check_ref_key(ref_key)?;
+
+  let referral = &mut ctx.accounts.referral;
+  referral.set_reward_mode(reward_mode)?;
+
+  emit_referral_config(ref_key, referral, events::ReferralAction::RewardModeChanged)
+}
+
+pub fn set_referral_buyer_bonus(
+  ctx: Context<SetReferralBuyerBonus>,
+  ref_key: Pubkey,
+  buyer_bonus: u64,
+) -> Result<()> {
+  check_ref_key(ref_key)?;
+
+  let referral = &mut ctx.accounts.referral;
+  referral.set_buyer_bonus(buyer_bonus)?;
+
+  emit_referral_config(ref_key, referral, events::ReferralAction::BuyerBonusChanged)
+}
+
+pub fn set_referral_upline(
+  ctx: Context<SetReferralUpline>,
+  ref_key: Pubkey,
+  upline: Pubkey,
+) -> Result<()> {
+  check_ref_key(ref_key)?;
+
+  if ref_key == upline {
+    return err!(errors::Sale::ReferralSelfUpline);
+  }
+
   let referral = &mut ctx.accounts.referral;
-  referral.set_reward(main_reward, secondary_reward)
+  referral.set_upline(upline)?;
+
+  emit_referral_config(ref_key, referral, events::ReferralAction::UplineChanged)
 }
 
 pub fn enable_referral(
   ctx: Context<SetReferralEnabled>,
+  ref_key: Pubkey,
 ) -> Result<()> {
+  check_ref_key(ref_key)?;
+
   let referral = &mut ctx.accounts.referral;
-  referral.enable()
+  referral.enable()?;
+
+  emit_referral_config(ref_key, referral, events::ReferralAction::Enabled)
 }
 
 pub fn disable_referral(
   ctx: Context<SetReferralDisabled>,
+  ref_key: Pubkey,
 ) -> Result<()> {
+  check_ref_key(ref_key)?;
+
   let referral = &mut ctx.accounts.referral;
-  referral.disable()
+  referral.disable()?;
+
+  emit_referral_config(ref_key, referral, events::ReferralAction::Disabled)
 }
 
 pub fn withdraw(
@@ -43,19 +168,25 @@ pub fn withdraw(
 ) -> Result<()> {
   let payer = &mut ctx.accounts.payer;
   let referral = &mut ctx.accounts.referral;
+
+  check_withdraw_paused(&ctx.accounts.sale)?;
   
   let sol_reward = referral.get_sol_reward_amount();
-  if sol_reward > 0 {
-    referral.reset_sol_reward_amount().unwrap();
+  if sol_reward == 0 {
+    return err!(errors::Sale::ReferralNoFunds);
+  }
 
-    referral.sub_lamports(sol_reward).unwrap();
-    payer.add_lamports(sol_reward).unwrap();
+  referral.reset_sol_reward_amount()?;
 
-    emit!(events::WithdrawSolEvent {
-      referral: payer.key(),
-      sol_amount: sol_reward,
-    });
-  }
+  referral.sub_lamports(sol_reward).map_err(|_| error!(errors::Sale::TransferFailed))?;
+  payer.add_lamports(sol_reward).map_err(|_| error!(errors::Sale::TransferFailed))?;
+
+  emit!(events::WithdrawSolEvent {
+    version: events::EVENT_VERSION,
+    referral: payer.key(),
+    sol_amount: sol_reward,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
 
   Ok(())
 }
@@ -65,6 +196,8 @@ pub fn withdraw_usdc(
 ) -> Result<()> {
   let payer = &mut ctx.accounts.payer;
   let referral = &mut ctx.accounts.referral;
+
+  check_withdraw_paused(&ctx.accounts.sale)?;
   
   let referral_ata = &ctx.accounts.referral_ata;
   let referral_pda_ata = &ctx.accounts.referral_pda_ata;
@@ -75,12 +208,12 @@ pub fn withdraw_usdc(
     return err!(errors::Sale::ReferralNoFunds);
   }
 
-  referral.reset_usdc_reward_amount().unwrap();
+  referral.reset_usdc_reward_amount()?;
 
   let payer_key = payer.key();
   let bump = &[ctx.bumps.referral];
   let seeds: &[&[u8]] = &[REFERRAL_TAG, b"_", payer_key.as_ref(), bump];
-  let signer_seeds = &[&seeds[..]];
+  let signer_seeds = &[seeds];
 
   let cpi_accounts = SplTransfer {
     from: referral_pda_ata.to_account_info(),
@@ -88,11 +221,13 @@ pub fn withdraw_usdc(
     authority: referral.to_account_info(),
   };
   let ctx = CpiContext::new_with_signer(program.to_account_info(), cpi_accounts, signer_seeds);
-  token::transfer(ctx, amount).unwrap();
+  token::transfer(ctx, amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
 
   emit!(events::WithdrawUsdcEvent {
+    version: events::EVENT_VERSION,
     referral: payer.key(),
     usdc_amount: amount,
+    timestamp: Clock::get()?.unix_timestamp,
   });
 
   Ok(())
@@ -103,6 +238,8 @@ pub fn withdraw_usdt(
 ) -> Result<()> {
   let payer = &mut ctx.accounts.payer;
   let referral = &mut ctx.accounts.referral;
+
+  check_withdraw_paused(&ctx.accounts.sale)?;
   
   let referral_ata = &ctx.accounts.referral_ata;
   let referral_pda_ata = &ctx.accounts.referral_pda_ata;
@@ -113,12 +250,12 @@ pub fn withdraw_usdt(
     return err!(errors::Sale::ReferralNoFunds);
   }
 
-  referral.reset_usdt_reward_amount().unwrap();
+  referral.reset_usdt_reward_amount()?;
 
   let payer_key = payer.key();
   let bump = &[ctx.bumps.referral];
   let seeds: &[&[u8]] = &[REFERRAL_TAG, b"_", payer_key.as_ref(), bump];
-  let signer_seeds = &[&seeds[..]];
+  let signer_seeds = &[seeds];
 
   let cpi_accounts = SplTransfer {
     from: referral_pda_ata.to_account_info(),
@@ -126,26 +263,578 @@ pub fn withdraw_usdt(
     authority: referral.to_account_info(),
   };
   let ctx = CpiContext::new_with_signer(program.to_account_info(), cpi_accounts, signer_seeds);
-  token::transfer(ctx, amount).unwrap();
+  token::transfer(ctx, amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
 
   emit!(events::WithdrawUsdtEvent {
+    version: events::EVENT_VERSION,
     referral: payer.key(),
     usdt_amount: amount,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+pub fn withdraw_all<'info>(
+  ctx: Context<'_, '_, 'info, 'info, WithdrawAll<'info>>,
+) -> Result<()> {
+  let payer = &ctx.accounts.payer;
+  let referral = &mut ctx.accounts.referral;
+
+  check_withdraw_paused(&ctx.accounts.sale)?;
+
+  let (sol_amount, usdc_amount, usdt_amount) = withdraw_balances(
+    referral,
+    payer.key(),
+    ctx.bumps.referral,
+    &payer.to_account_info(),
+    &ctx.accounts.token_program,
+    ctx.remaining_accounts,
+  )?;
+
+  if sol_amount == 0 && usdc_amount == 0 && usdt_amount == 0 {
+    return err!(errors::Sale::ReferralNoFunds);
+  }
+
+  emit!(events::WithdrawAllEvent {
+    version: events::EVENT_VERSION,
+    referral: payer.key(),
+    recipient: payer.key(),
+    sol_amount,
+    usdc_amount,
+    usdt_amount,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+// Referral balances are held by the referral itself, so they stay
+// withdrawable after the sale account is closed.
+fn check_withdraw_paused(
+  sale: &AccountInfo,
+) -> Result<()> {
+  if sale.data_is_empty() {
+    return Ok(());
+  }
+
+  check_account(sale, Sale::DISCRIMINATOR)?;
+  let data = sale.try_borrow_data()?;
+  if data.len() != 8 + size_of::<Sale>() {
+    return err!(errors::Sale::InvalidAccount);
+  }
+
+  let sale: &Sale = bytemuck::from_bytes(&data[8..]);
+  if sale.is_paused(PAUSE_WITHDRAW) {
+    return err!(errors::Sale::WithdrawalsPaused);
+  }
+
+  Ok(())
+}
+
+pub fn set_referral_payout(
+  ctx: Context<SetReferralPayout>,
+  payout_address: Pubkey,
+) -> Result<()> {
+  let referral = &mut ctx.accounts.referral;
+  referral.set_payout_address(payout_address)?;
+
+  emit!(events::ReferralPayoutChangedEvent {
+    version: events::EVENT_VERSION,
+    referral: ctx.accounts.payer.key(),
+    payout_address,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+// Permissionless: anyone may crank a referrer's balances out to the payout
+// address the referrer configured.
+pub fn withdraw_to_payout<'info>(
+  ctx: Context<'_, '_, 'info, 'info, WithdrawToPayout<'info>>,
+  ref_key: Pubkey,
+) -> Result<()> {
+  let referral = &mut ctx.accounts.referral;
+  let payout = &ctx.accounts.payout;
+
+  check_withdraw_paused(&ctx.accounts.sale)?;
+
+  if referral.get_payout_address() == Pubkey::default() {
+    return err!(errors::Sale::PayoutAddressNotSet);
+  }
+
+  let (sol_amount, usdc_amount, usdt_amount) = withdraw_balances(
+    referral,
+    ref_key,
+    ctx.bumps.referral,
+    &payout.to_account_info(),
+    &ctx.accounts.token_program,
+    ctx.remaining_accounts,
+  )?;
+
+  if sol_amount == 0 && usdc_amount == 0 && usdt_amount == 0 {
+    return err!(errors::Sale::ReferralNoFunds);
+  }
+
+  emit!(events::WithdrawAllEvent {
+    version: events::EVENT_VERSION,
+    referral: ref_key,
+    recipient: payout.key(),
+    sol_amount,
+    usdc_amount,
+    usdt_amount,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+// Moves a fraudulent referrer's unwithdrawn balances to the treasury,
+// forfeits unclaimed token rewards and disables the referral.
+pub fn clawback_referral<'info>(
+  ctx: Context<'_, '_, 'info, 'info, ClawbackReferral<'info>>,
+  ref_key: Pubkey,
+) -> Result<()> {
+  let referral = &mut ctx.accounts.referral;
+  let treasury_info = &ctx.accounts.treasury_info;
+
+  let (sol_amount, usdc_amount, usdt_amount) = withdraw_balances(
+    referral,
+    ref_key,
+    ctx.bumps.referral,
+    treasury_info,
+    &ctx.accounts.token_program,
+    ctx.remaining_accounts,
+  )?;
+  let token_amount = referral.forfeit_token_reward_amount()?;
+  ctx.accounts.sale.load_mut()?.set_claimed_tokens(token_amount)?;
+  referral.disable()?;
+
+  emit!(events::ReferralClawbackEvent {
+    version: events::EVENT_VERSION,
+    referral: ref_key,
+    sol_amount,
+    usdc_amount,
+    usdt_amount,
+    token_amount,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+// Deposits without a referrer are booked against `EMPTY_REFERRAL_KEY`, which
+// is never a real referrer and cannot be configured as one.
+pub fn check_ref_key(
+  ref_key: Pubkey,
+) -> Result<()> {
+  if ref_key == EMPTY_REFERRAL_KEY {
+    return err!(errors::Sale::InvalidReferralKey);
+  }
+
+  Ok(())
+}
+
+pub fn emit_referral_config(
+  ref_key: Pubkey,
+  referral: &Referral,
+  action: events::ReferralAction,
+) -> Result<()> {
+  emit!(events::ReferralConfigChangedEvent {
+    version: events::EVENT_VERSION,
+    referral: ref_key,
+    action,
+    config: referral.get_config(),
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+// Returns the referral key a deposit is credited to: the empty key when the
+// referral program is switched off or the referral was disabled, unless the
+// sale is configured to reject such deposits outright.
+pub fn resolve_referral(
+  sale: &Sale,
+  referral: &Referral,
+  ref_key: Pubkey,
+) -> Result<Pubkey> {
+  if ref_key == EMPTY_REFERRAL_KEY || !sale.is_referral_program_enabled() {
+    return Ok(EMPTY_REFERRAL_KEY);
+  }
+
+  if referral.is_disabled() {
+    if sale.is_rejecting_disabled_referral() {
+      return err!(errors::Sale::ReferralDisabled);
+    }
+
+    return Ok(EMPTY_REFERRAL_KEY);
+  }
+
+  Ok(ref_key)
+}
+
+// Drains the SOL balance to `recipient` and, for every
+// (referral_pda_ata, recipient_ata) pair passed in remaining accounts, the
+// matching stable balance.
+pub(crate) fn withdraw_balances<'info>(
+  referral: &mut Account<'info, Referral>,
+  ref_key: Pubkey,
+  bump: u8,
+  recipient: &AccountInfo<'info>,
+  program: &Program<'info, Token>,
+  remaining_accounts: &'info [AccountInfo<'info>],
+) -> Result<(u64, u64, u64)> {
+  let sol_amount = referral.get_sol_reward_amount();
+  if sol_amount > 0 {
+    referral.reset_sol_reward_amount()?;
+
+    referral.sub_lamports(sol_amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
+    recipient.add_lamports(sol_amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
+  }
+
+  let bump = &[bump];
+  let seeds: &[&[u8]] = &[REFERRAL_TAG, b"_", ref_key.as_ref(), bump];
+  let signer_seeds = &[seeds];
+
+  let mut usdc_amount = 0;
+  let mut usdt_amount = 0;
+  let mut accounts = remaining_accounts.iter();
+  while let Some(pda_ata_info) = accounts.next() {
+    let ata_info = accounts.next().ok_or_else(|| error!(errors::Sale::InvalidAccountPair))?;
+    let referral_pda_ata = Account::<TokenAccount>::try_from(pda_ata_info)?;
+    let recipient_ata = Account::<TokenAccount>::try_from(ata_info)?;
+
+    if referral_pda_ata.owner != referral.key() || recipient_ata.owner != recipient.key() {
+      return err!(errors::Sale::InvalidOwner);
+    }
+
+    if referral_pda_ata.mint != recipient_ata.mint {
+      return err!(errors::Sale::InvalidMint);
+    }
+
+    let amount = if USDC.parse::<Pubkey>() == Ok(recipient_ata.mint) {
+      let amount = referral.get_usdc_reward_amount();
+      referral.reset_usdc_reward_amount()?;
+      usdc_amount = math::to_u64(math::add(u128::from(usdc_amount), u128::from(amount))?)?;
+      amount
+    } else if USDT.parse::<Pubkey>() == Ok(recipient_ata.mint) {
+      let amount = referral.get_usdt_reward_amount();
+      referral.reset_usdt_reward_amount()?;
+      usdt_amount = math::to_u64(math::add(u128::from(usdt_amount), u128::from(amount))?)?;
+      amount
+    } else {
+      return err!(errors::Sale::InvalidMint);
+    };
+
+    if amount == 0 {
+      continue;
+    }
+
+    let cpi_accounts = SplTransfer {
+      from: pda_ata_info.clone(),
+      to: ata_info.clone(),
+      authority: referral.to_account_info(),
+    };
+    let ctx = CpiContext::new_with_signer(program.to_account_info(), cpi_accounts, signer_seeds);
+    token::transfer(ctx, amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
+  }
+
+  Ok((sol_amount, usdc_amount, usdt_amount))
+}
+
+// A referral recreated while the sale runs would start over with a fresh
+// reward cap, tier volume and enabled flag, so it can only be closed once the
+// sale is over, and never while disabled.
+pub fn check_referral_closable(
+  sale: &Sale,
+  referral: &Referral,
+) -> Result<()> {
+  if !sale.is_closed() {
+    return err!(errors::Sale::SaleNotFinalised);
+  }
+
+  if referral.is_disabled() {
+    return err!(errors::Sale::ReferralDisabled);
+  }
+
+  if !referral.is_empty() {
+    return err!(errors::Sale::ReferralNotEmpty);
+  }
+
+  Ok(())
+}
+
+pub fn close_referral(
+  ctx: Context<CloseReferral>,
+) -> Result<()> {
+  let sale = &ctx.accounts.sale.load()?;
+  check_referral_closable(sale, &ctx.accounts.referral)?;
+
+  emit_account_closed(ctx.accounts.referral.key(), events::AccountKind::Referral, ctx.accounts.payer.key())
+}
+
+pub fn claim_ref_tokens(
+  ctx: Context<ClaimRefTokens>,
+) -> Result<()> {
+  let payer = &ctx.accounts.payer;
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  let referral = &mut ctx.accounts.referral;
+  let vault = &ctx.accounts.vault;
+  let referral_token_ata = &ctx.accounts.referral_token_ata;
+  let token_program = &ctx.accounts.token_program;
+
+  if sale.is_paused(PAUSE_CLAIM) {
+    return err!(errors::Sale::ClaimsPaused);
+  }
+
+  let now = Clock::get()?.unix_timestamp;
+  let vested_amount = sale.get_vested_amount(referral.get_token_reward_amount(), now)?;
+  let amount = math::sub(vested_amount, referral.get_token_claimed_amount())?;
+  if amount == 0 {
+    return err!(errors::Sale::NothingToClaim);
+  }
+
+  referral.set_token_claimed_amount(amount)?;
+  sale.set_claimed_tokens(amount)?;
+
+  let token_amount = math::to_u64(amount)?;
+  let bump = &[ctx.bumps.sale];
+  let seeds: &[&[u8]] = &[bump];
+  let signer_seeds = &[seeds];
+
+  let cpi_accounts = SplTransfer {
+    from: vault.to_account_info(),
+    to: referral_token_ata.to_account_info(),
+    authority: ctx.accounts.sale.to_account_info(),
+  };
+  let ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer_seeds);
+  token::transfer(ctx, token_amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
+
+  emit!(events::ClaimRefTokensEvent {
+    version: events::EVENT_VERSION,
+    referral: payer.key(),
+    token_amount,
+    claimed_amount: referral.get_token_claimed_amount(),
+    timestamp: now,
   });
 
   Ok(())
 }
 
+// Rejects self-referral, enforces the sale's referral rules and binds the
+// beneficiary to the first referrer it used. Returns whether the
+// beneficiary was bound by this deposit. The binding is checked against the
+// key the buyer passed, since `resolve_referral` may have dropped it.
+// Only the first referrer is recorded, so when referrers are not bound a
+// buyer who later switches is never counted as new by the next one.
+pub fn check_referral(
+  sale: &Sale,
+  beneficiary: &mut Beneficiary,
+  payer_key: Pubkey,
+  requested_key: Pubkey,
+  ref_key: Pubkey,
+  usd_amount: u128,
+) -> Result<bool> {
+  let bound_key = beneficiary.get_referrer();
+  if sale.is_referrer_bound() && bound_key != Pubkey::default() && bound_key != requested_key {
+    return err!(errors::Sale::ReferrerMismatch);
+  }
+
+  if ref_key == EMPTY_REFERRAL_KEY {
+    return Ok(false);
+  }
+
+  if ref_key == payer_key {
+    return err!(errors::Sale::SelfReferral);
+  }
+
+  if usd_amount < sale.get_min_referral_usd() {
+    return err!(errors::Sale::ReferralPurchaseTooSmall);
+  }
+
+  beneficiary.set_referrer(ref_key)
+}
+
+pub fn set_referral_volume(
+  sale: &Sale,
+  referral: &mut Referral,
+  ref_key: Pubkey,
+  usd_amount: u128,
+  new_buyer: bool,
+) -> Result<()> {
+  referral.set_referred(usd_amount, new_buyer)?;
+
+  let (referred_usd, referred_count) = referral.get_referred();
+  if let Some((tier, _)) = sale.get_tier(referred_usd) {
+    let previous = referral.set_tier(tier)?;
+    if tier > previous {
+      emit!(events::ReferralTierChangedEvent {
+        version: events::EVENT_VERSION,
+        referral: ref_key,
+        from_tier: previous,
+        to_tier: tier,
+        referred_usd,
+        referred_count,
+        timestamp: Clock::get()?.unix_timestamp,
+      });
+    }
+  }
+
+  Ok(())
+}
+
+pub struct Upline<'info> {
+  pub key: Pubkey,
+  pub level: u8,
+  pub referral: Account<'info, Referral>,
+  pub referral_ata: Option<Account<'info, TokenAccount>>,
+  pub reward_amount: u64,
+  pub token_reward_amount: u128,
+  pub reward_usd: u128,
+}
+
+// Walks the upline chain of `referral` up to the sale's referral depth,
+// stopping at the first disabled upline. Each level expects its referral
+// PDA in `remaining_accounts`, followed by that PDA's token account when
+// paying out in `mint`.
+pub fn get_uplines<'info>(
+  sale: &Sale,
+  payer_key: Pubkey,
+  ref_key: Pubkey,
+  referral: &Referral,
+  remaining_accounts: &'info [AccountInfo<'info>],
+  mint: Option<Pubkey>,
+) -> Result<Vec<Upline<'info>>> {
+  let mut uplines = Vec::new();
+  if ref_key == EMPTY_REFERRAL_KEY || !sale.is_referral_program_enabled() {
+    return Ok(uplines);
+  }
+
+  let mut accounts = remaining_accounts.iter();
+  let mut upline_key = referral.get_upline();
+
+  for level in 2..=sale.get_referral_depth() {
+    if upline_key == Pubkey::default() || upline_key == EMPTY_REFERRAL_KEY {
+      break;
+    }
+
+    // A cycle in the chain would pay the same referral twice, and the
+    // buyer never earns from their own purchase.
+    if upline_key == ref_key || upline_key == payer_key || uplines.iter().any(|upline: &Upline| upline.key == upline_key) {
+      break;
+    }
+
+    let referral_info = accounts.next().ok_or_else(|| error!(errors::Sale::ReferralUplineMissing))?;
+    let (referral_key, _) = Pubkey::find_program_address(
+      &[REFERRAL_TAG, b"_", upline_key.as_ref()],
+      &crate::ID,
+    );
+    if referral_info.key() != referral_key || !referral_info.is_writable {
+      return err!(errors::Sale::ReferralUplineMismatch);
+    }
+    let upline_referral = Account::<Referral>::try_from(referral_info)?;
+    if upline_referral.is_disabled() {
+      break;
+    }
+
+    let referral_ata = match mint {
+      Some(mint) => {
+        let ata_info = accounts.next().ok_or_else(|| error!(errors::Sale::ReferralUplineMissing))?;
+        let ata = Account::<TokenAccount>::try_from(ata_info)?;
+        if ata.mint != mint {
+          return err!(errors::Sale::InvalidMint);
+        }
+        if ata.owner != referral_key {
+          return err!(errors::Sale::InvalidOwner);
+        }
+        Some(ata)
+      }
+      None => None,
+    };
+
+    let next_key = upline_referral.get_upline();
+    uplines.push(Upline {
+      key: upline_key,
+      level,
+      referral: upline_referral,
+      referral_ata,
+      reward_amount: 0,
+      token_reward_amount: 0,
+      reward_usd: 0,
+    });
+    upline_key = next_key;
+  }
+
+  Ok(uplines)
+}
+
+// An upline's cut at `level`, split by its reward mode and clamped and
+// booked like the direct referrer's in `get_reward`.
+pub fn get_upline_reward(
+  sale: &mut Sale,
+  referral: &mut Referral,
+  level: u8,
+  amount: u64,
+  usd_amount: u128,
+  token_amount: u128,
+) -> Result<(u64, u128, u128)> {
+  let one = math::pow10(PRECISION)?;
+  let upline_reward = u128::from(sale.get_upline_reward(level));
+  let cash_share = u128::from(sale.get_cash_share(referral.get_reward_mode()));
+  let cash_reward = math::mul_div_floor(upline_reward, cash_share, one)?;
+  let token_upline_reward = math::sub(upline_reward, cash_reward)?;
+
+  let reward_amount = math::to_u64(math::mul_div_floor(u128::from(amount), cash_reward, one)?)?;
+  let token_reward_amount = math::mul_div_floor(token_amount, token_upline_reward, one)?;
+  let reward_usd = math::mul_div_floor(usd_amount, cash_reward, one)?;
+
+  spend_reward(sale, referral, reward_amount, reward_usd, token_reward_amount)
+}
+
+// Runs after `get_reward`, so the direct referrer draws on the budget first.
+pub fn set_upline_rewards(
+  sale: &mut Sale,
+  uplines: &mut [Upline],
+  amount: u64,
+  usd_amount: u128,
+  token_amount: u128,
+) -> Result<()> {
+  for upline in uplines.iter_mut() {
+    let (reward_amount, token_reward_amount, reward_usd) = get_upline_reward(sale, &mut upline.referral, upline.level, amount, usd_amount, token_amount)?;
+    upline.reward_amount = reward_amount;
+    upline.token_reward_amount = token_reward_amount;
+    upline.reward_usd = reward_usd;
+  }
+
+  Ok(())
+}
+
+pub fn get_upline_reward_amount(
+  uplines: &[Upline],
+) -> Result<u64> {
+  let mut total = 0u128;
+  for upline in uplines {
+    total = math::add(total, u128::from(upline.reward_amount))?;
+  }
+
+  math::to_u64(total)
+}
+
 pub const REFERRAL_TAG: &[u8] = b"REFERRAL";
-pub const EMPTY_REFERRAL_KEY: &str  = "4B8zY1AsDUhv1s5Ftvh8QLvXJm9En2M1bNvrWinYoLDv";
+pub const REFERRAL_CODE_TAG: &[u8] = b"REFERRAL_CODE";
+pub const EMPTY_REFERRAL_KEY: Pubkey = anchor_lang::solana_program::pubkey!("4B8zY1AsDUhv1s5Ftvh8QLvXJm9En2M1bNvrWinYoLDv");
 
 #[derive(Accounts)]
 #[instruction(ref_key: Pubkey)]
 pub struct InitReferral<'info> {
+  #[account(seeds = [], bump)]
+  pub sale: AccountLoader<'info, Sale>,
   #[account(
     init,
     payer = payer,
-    space = 680,
+    space = 8 + Referral::INIT_SPACE,
     seeds = [
       REFERRAL_TAG,
       b"_",
@@ -160,24 +849,154 @@ pub struct InitReferral<'info> {
 }
 
 #[derive(Accounts)]
+#[instruction(upline: Pubkey)]
+pub struct RegisterReferral<'info> {
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(
+    init_if_needed,
+    payer = payer,
+    space = 8 + Referral::INIT_SPACE,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      payer.key().as_ref()
+    ],
+    bump
+  )]
+  pub referral: Account<'info, Referral>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+  pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(code: String)]
+pub struct RegisterReferralCode<'info> {
+  #[account(
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      payer.key().as_ref()
+    ],
+    bump
+  )]
+  pub referral: Account<'info, Referral>,
+  #[account(
+    init,
+    payer = payer,
+    space = 8 + ReferralCode::INIT_SPACE,
+    seeds = [
+      REFERRAL_CODE_TAG,
+      b"_",
+      code.as_bytes()
+    ],
+    bump,
+  )]
+  pub referral_code: Account<'info, ReferralCode>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+  pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(ref_key: Pubkey)]
 pub struct SetReferralReward<'info> {
+  #[account(seeds = [], bump)]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(
+    mut,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      ref_key.key().as_ref()
+    ],
+    bump
+  )]
+  pub referral: Account<'info, Referral>,
   #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(ref_key: Pubkey)]
+pub struct SetReferralRewardMode<'info> {
+  #[account(
+    mut,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      ref_key.key().as_ref()
+    ],
+    bump
+  )]
   pub referral: Account<'info, Referral>,
   #[account(mut)]
   pub payer: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct SetReferralEnabled<'info> {
+#[instruction(ref_key: Pubkey)]
+pub struct SetReferralBuyerBonus<'info> {
+  #[account(
+    mut,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      ref_key.key().as_ref()
+    ],
+    bump
+  )]
+  pub referral: Account<'info, Referral>,
   #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(ref_key: Pubkey, upline: Pubkey)]
+pub struct SetReferralUpline<'info> {
+  #[account(
+    mut,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      ref_key.key().as_ref()
+    ],
+    bump
+  )]
   pub referral: Account<'info, Referral>,
   #[account(mut)]
   pub payer: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct SetReferralDisabled<'info> {
+#[instruction(ref_key: Pubkey)]
+pub struct SetReferralEnabled<'info> {
+  #[account(
+    mut,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      ref_key.key().as_ref()
+    ],
+    bump
+  )]
+  pub referral: Account<'info, Referral>,
   #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(ref_key: Pubkey)]
+pub struct SetReferralDisabled<'info> {
+  #[account(
+    mut,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      ref_key.key().as_ref()
+    ],
+    bump
+  )]
   pub referral: Account<'info, Referral>,
   #[account(mut)]
   pub payer: Signer<'info>,
@@ -195,6 +1014,12 @@ pub struct Withdraw<'info> {
     bump
   )]
   pub referral: Account<'info, Referral>,
+  /// CHECK : The sale PDA, only read for its pause flags while it exists
+  #[account(
+    seeds = [],
+    bump,
+  )]
+  pub sale: AccountInfo<'info>,
   #[account(mut)]
   pub payer: Signer<'info>,
 }
@@ -213,16 +1038,22 @@ pub struct WithdrawUSDC<'info> {
   pub referral: Account<'info, Referral>,
   #[account(
     mut,
-    constraint = referral_ata.mint == USDC.parse::<Pubkey>().unwrap(),
-    constraint = referral_ata.owner == payer.key(),
+    constraint = USDC.parse::<Pubkey>() == Ok(referral_ata.mint) @ errors::Sale::InvalidMint,
+    constraint = referral_ata.owner == payer.key() @ errors::Sale::InvalidOwner,
   )]
   pub referral_ata: Account<'info, TokenAccount>,
   #[account(
     mut,
-    constraint = referral_pda_ata.mint == USDC.parse::<Pubkey>().unwrap(),
-    constraint = referral_pda_ata.owner == referral.key(),
+    constraint = USDC.parse::<Pubkey>() == Ok(referral_pda_ata.mint) @ errors::Sale::InvalidMint,
+    constraint = referral_pda_ata.owner == referral.key() @ errors::Sale::InvalidOwner,
   )]
   pub referral_pda_ata: Account<'info, TokenAccount>,
+  /// CHECK : The sale PDA, only read for its pause flags while it exists
+  #[account(
+    seeds = [],
+    bump,
+  )]
+  pub sale: AccountInfo<'info>,
   pub token_program: Program<'info, Token>,
   #[account(mut)]
   pub payer: Signer<'info>,
@@ -242,17 +1073,336 @@ pub struct WithdrawUSDT<'info> {
   pub referral: Account<'info, Referral>,
   #[account(
     mut,
-    constraint = referral_ata.mint == USDT.parse::<Pubkey>().unwrap(),
-    constraint = referral_ata.owner == payer.key(),
+    constraint = USDT.parse::<Pubkey>() == Ok(referral_ata.mint) @ errors::Sale::InvalidMint,
+    constraint = referral_ata.owner == payer.key() @ errors::Sale::InvalidOwner,
   )]
   pub referral_ata: Account<'info, TokenAccount>,
   #[account(
     mut,
-    constraint = referral_pda_ata.mint == USDT.parse::<Pubkey>().unwrap(),
-    constraint = referral_pda_ata.owner == referral.key(),
+    constraint = USDT.parse::<Pubkey>() == Ok(referral_pda_ata.mint) @ errors::Sale::InvalidMint,
+    constraint = referral_pda_ata.owner == referral.key() @ errors::Sale::InvalidOwner,
   )]
   pub referral_pda_ata: Account<'info, TokenAccount>,
+  /// CHECK : The sale PDA, only read for its pause flags while it exists
+  #[account(
+    seeds = [],
+    bump,
+  )]
+  pub sale: AccountInfo<'info>,
+  pub token_program: Program<'info, Token>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawAll<'info> {
+  #[account(
+    mut,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      payer.key().as_ref()
+    ],
+    bump
+  )]
+  pub referral: Account<'info, Referral>,
+  /// CHECK : The sale PDA, only read for its pause flags while it exists
+  #[account(
+    seeds = [],
+    bump,
+  )]
+  pub sale: AccountInfo<'info>,
   pub token_program: Program<'info, Token>,
   #[account(mut)]
   pub payer: Signer<'info>,
-}
\ No newline at end of file
+}
+
+#[derive(Accounts)]
+pub struct SetReferralPayout<'info> {
+  #[account(
+    mut,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      payer.key().as_ref()
+    ],
+    bump
+  )]
+  pub referral: Account<'info, Referral>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(ref_key: Pubkey)]
+pub struct WithdrawToPayout<'info> {
+  #[account(
+    mut,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      ref_key.as_ref()
+    ],
+    bump
+  )]
+  pub referral: Account<'info, Referral>,
+  /// CHECK : Checked against the payout address stored on the referral
+  #[account(
+    mut,
+    address = referral.get_payout_address() @ errors::Sale::InvalidPayoutAddress,
+  )]
+  pub payout: AccountInfo<'info>,
+  /// CHECK : The sale PDA, only read for its pause flags while it exists
+  #[account(
+    seeds = [],
+    bump,
+  )]
+  pub sale: AccountInfo<'info>,
+  pub token_program: Program<'info, Token>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(ref_key: Pubkey)]
+pub struct ClawbackReferral<'info> {
+  #[account(
+    mut,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      ref_key.as_ref()
+    ],
+    bump
+  )]
+  pub referral: Account<'info, Referral>,
+  /// CHECK : Checked against the Pubkey of the treasury
+  #[account(
+    mut,
+    constraint = TREASURY.parse::<Pubkey>() == Ok(treasury_info.key()) @ errors::Sale::WrongTreasury,
+  )]
+  pub treasury_info: AccountInfo<'info>,
+  #[account(
+    mut,
+    seeds = [],
+    bump,
+  )]
+  pub sale: AccountLoader<'info, Sale>,
+  pub token_program: Program<'info, Token>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseReferral<'info> {
+  #[account(
+    mut,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      payer.key().as_ref()
+    ],
+    bump,
+    close = payer,
+  )]
+  pub referral: Account<'info, Referral>,
+  #[account(
+    seeds = [],
+    bump,
+  )]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRefTokens<'info> {
+  #[account(
+    mut,
+    seeds = [],
+    bump,
+  )]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(
+    mut,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      payer.key().as_ref()
+    ],
+    bump
+  )]
+  pub referral: Account<'info, Referral>,
+  #[account(
+    mut,
+    constraint = vault.mint == sale.load()?.get_token_mint() @ errors::Sale::InvalidMint,
+    constraint = vault.owner == sale.key() @ errors::Sale::InvalidOwner,
+  )]
+  pub vault: Account<'info, TokenAccount>,
+  #[account(
+    mut,
+    constraint = referral_token_ata.mint == sale.load()?.get_token_mint() @ errors::Sale::InvalidMint,
+    constraint = referral_token_ata.owner == payer.key() @ errors::Sale::InvalidOwner,
+  )]
+  pub referral_token_ata: Account<'info, TokenAccount>,
+  pub token_program: Program<'info, Token>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bytemuck::Zeroable;
+
+  fn zeroed<T: AnchorDeserialize + Space>() -> T {
+    T::deserialize(&mut &vec![0u8; T::INIT_SPACE][..]).unwrap()
+  }
+
+  fn bound_beneficiary(
+    referrer: Pubkey,
+  ) -> Beneficiary {
+    let mut beneficiary: Beneficiary = zeroed();
+    beneficiary.set_referrer(referrer).unwrap();
+    beneficiary
+  }
+
+  #[test]
+  fn bound_buyer_can_deposit_when_referral_is_resolved_away() {
+    let mut sale = Sale::zeroed();
+    sale.set_referral_rules(true, 0).unwrap();
+    sale.set_referral_program(false, false).unwrap();
+    let referrer = Pubkey::new_unique();
+    let mut beneficiary = bound_beneficiary(referrer);
+
+    let referral: Referral = zeroed();
+    let ref_key = resolve_referral(&sale, &referral, referrer).unwrap();
+    assert_eq!(ref_key, EMPTY_REFERRAL_KEY);
+
+    let new_buyer = check_referral(&sale, &mut beneficiary, Pubkey::new_unique(), referrer, ref_key, 0).unwrap();
+    assert!(!new_buyer);
+  }
+
+  #[test]
+  fn bound_buyer_cannot_switch_referrer() {
+    let mut sale = Sale::zeroed();
+    sale.set_referral_rules(true, 0).unwrap();
+    let mut beneficiary = bound_beneficiary(Pubkey::new_unique());
+
+    let other = Pubkey::new_unique();
+    let result = check_referral(&sale, &mut beneficiary, Pubkey::new_unique(), other, other, 0);
+    assert_eq!(result.unwrap_err(), errors::Sale::ReferrerMismatch.into());
+
+    let result = check_referral(&sale, &mut beneficiary, Pubkey::new_unique(), EMPTY_REFERRAL_KEY, EMPTY_REFERRAL_KEY, 0);
+    assert_eq!(result.unwrap_err(), errors::Sale::ReferrerMismatch.into());
+  }
+
+  #[test]
+  fn buyer_is_counted_by_first_referrer_only() {
+    let sale = Sale::zeroed();
+    let payer = Pubkey::new_unique();
+    let mut beneficiary: Beneficiary = zeroed();
+    let mut first: Referral = zeroed();
+    let mut second: Referral = zeroed();
+
+    for (referral, ref_key) in [(&mut first, Pubkey::new_unique()), (&mut second, Pubkey::new_unique())] {
+      for _ in 0..2 {
+        let new_buyer = check_referral(&sale, &mut beneficiary, payer, ref_key, ref_key, 100).unwrap();
+        set_referral_volume(&sale, referral, ref_key, 100, new_buyer).unwrap();
+      }
+    }
+
+    assert_eq!(first.get_referred(), (200, 1));
+    assert_eq!(second.get_referred(), (200, 0));
+  }
+
+  #[test]
+  fn referral_is_not_reset_by_close_and_recreate() {
+    let mut sale = Sale::zeroed();
+    sale.set_open().unwrap();
+    let mut referral: Referral = zeroed();
+    referral.set_reward_usd(100).unwrap();
+
+    assert_eq!(check_referral_closable(&sale, &referral).unwrap_err(), errors::Sale::SaleNotFinalised.into());
+
+    sale.set_close().unwrap();
+    referral.disable().unwrap();
+    assert_eq!(check_referral_closable(&sale, &referral).unwrap_err(), errors::Sale::ReferralDisabled.into());
+
+    referral.enable().unwrap();
+    check_referral_closable(&sale, &referral).unwrap();
+  }
+
+  #[test]
+  fn legacy_referral_that_is_off_is_not_registered() {
+    let mut sale = Sale::zeroed();
+    sale.set_referral_program(true, true).unwrap();
+    let referrer = Pubkey::new_unique();
+
+    let mut referral: Referral = zeroed();
+    assert!(!referral.is_initialized());
+    assert_eq!(resolve_referral(&sale, &referral, referrer).unwrap(), referrer);
+
+    referral.disable().unwrap();
+    assert_eq!(resolve_referral(&sale, &referral, referrer).unwrap_err(), errors::Sale::ReferralDisabled.into());
+  }
+
+  #[test]
+  fn placeholder_referral_is_not_configurable() {
+    assert_eq!(check_ref_key(EMPTY_REFERRAL_KEY).unwrap_err(), errors::Sale::InvalidReferralKey.into());
+    check_ref_key(Pubkey::new_unique()).unwrap();
+  }
+
+  #[test]
+  fn upline_reward_is_clamped_to_referrer_cap_and_budget() {
+    let mut sale = Sale::zeroed();
+    sale.set_referral_levels(3, [20_000_000, 10_000_000]).unwrap();
+    sale.set_referral_budget(150, 250, 0).unwrap();
+
+    let mut upline: Referral = zeroed();
+    let reward = get_upline_reward(&mut sale, &mut upline, 2, 10_000, 10_000, 0).unwrap();
+    assert_eq!(reward, (150, 0, 150));
+    assert_eq!(upline.get_reward_usd(), 150);
+
+    let reward = get_upline_reward(&mut sale, &mut upline, 2, 10_000, 10_000, 0).unwrap();
+    assert_eq!(reward, (0, 0, 0));
+
+    let mut other: Referral = zeroed();
+    let reward = get_upline_reward(&mut sale, &mut other, 3, 10_000, 10_000, 0).unwrap();
+    assert_eq!(reward, (100, 0, 100));
+    let reward = get_upline_reward(&mut sale, &mut other, 3, 10_000, 10_000, 0).unwrap();
+    assert_eq!(reward, (0, 0, 0));
+    assert_eq!(sale.get_referral_spent(), (250, 0));
+  }
+
+  #[test]
+  fn upline_reward_follows_reward_mode() {
+    let mut sale = Sale::zeroed();
+    sale.set_referral_levels(2, [20_000_000, 0]).unwrap();
+    sale.set_reward_mode(RewardMode::Split, 250_000_000).unwrap();
+
+    let mut upline: Referral = zeroed();
+    let reward = get_upline_reward(&mut sale, &mut upline, 2, 10_000, 10_000, 40_000).unwrap();
+    assert_eq!(reward, (50, 600, 50));
+
+    upline.set_reward_mode(Some(RewardMode::Tokens)).unwrap();
+    let reward = get_upline_reward(&mut sale, &mut upline, 2, 10_000, 10_000, 40_000).unwrap();
+    assert_eq!(reward, (0, 800, 0));
+    assert_eq!(sale.get_referral_spent(), (50, 1_400));
+  }
+
+  #[test]
+  fn referral_main_reward_leaves_room_for_uplines() {
+    let mut sale = Sale::zeroed();
+    sale.set_referral_levels(2, [300_000_000, 0]).unwrap();
+    let max_main_reward = sale.get_max_main_reward().unwrap();
+
+    let mut referral: Referral = zeroed();
+    assert_eq!(referral.init(700_000_001, 0, max_main_reward).unwrap_err(), errors::Sale::SaleMainRefRewardTooLarge.into());
+    referral.init(700_000_000, 0, max_main_reward).unwrap();
+
+    assert_eq!(referral.set_reward(700_000_001, 0, max_main_reward).unwrap_err(), errors::Sale::SaleMainRefRewardTooLarge.into());
+    referral.set_reward(500_000_000, 0, max_main_reward).unwrap();
+    assert_eq!(referral.get_reward(), (500_000_000, 0));
+  }
+}
@@ -1,5 +1,9 @@
 use anchor_lang::prelude::*;
-use crate::state::round::Round;
+use crate::events;
+use crate::errors;
+use crate::state::round::{ Round, State, PriceMode, DecayCurve };
+use crate::state::beneficiary::Beneficiary;
+use crate::instructions::sale::{ BENEFICIARY_TAG, emit_sale_config, emit_account_closed };
 use crate::state::sale::Sale;
 
 pub fn initialize_round(
@@ -8,41 +12,188 @@ pub fn initialize_round(
   price: u64,
   total_supply: u128,
 ) -> Result<()> {
-  let round = &mut ctx.accounts.round;
-  round.init(id, price, total_supply)
+  let round = &mut ctx.accounts.round.load_init()?;
+  round.init(id, price, total_supply)?;
+
+  emit_round_config(round, events::RoundAction::Initialized)
 }
 
 pub fn set_round_price(
   ctx: Context<SetRoundPrice>,
   price: u64,
 ) -> Result<()> {
-  let round = &mut ctx.accounts.round;
-  round.set_price(price)
+  let round = &mut ctx.accounts.round.load_mut()?;
+  round.set_price(price)?;
+
+  emit_round_config(round, events::RoundAction::PriceChanged)
+}
+
+pub fn set_round_pricing(
+  ctx: Context<SetRoundPricing>,
+  price_mode: PriceMode,
+  end_price: u64,
+  price_steps: u16,
+) -> Result<()> {
+  let round = &mut ctx.accounts.round.load_mut()?;
+  round.set_pricing(price_mode, end_price, price_steps)?;
+
+  emit_round_config(round, events::RoundAction::PricingChanged)
+}
+
+pub fn set_round_auction(
+  ctx: Context<SetRoundAuction>,
+  start_price: u64,
+  floor_price: u64,
+  start_time: i64,
+  end_time: i64,
+  decay_curve: DecayCurve,
+  uniform_clearing: bool,
+) -> Result<()> {
+  let round = &mut ctx.accounts.round.load_mut()?;
+  round.set_auction(start_price, floor_price, start_time, end_time, decay_curve, uniform_clearing)?;
+
+  emit_round_config(round, events::RoundAction::AuctionChanged)
 }
 
 pub fn set_round_supply(
   ctx: Context<SetRoundSupply>,
   total_supply: u128
 ) -> Result<()> {
-  let round = &mut ctx.accounts.round;
-  round.set_total_supply(total_supply)
+  let round = &mut ctx.accounts.round.load_mut()?;
+  round.set_total_supply(total_supply)?;
+
+  emit_round_config(round, events::RoundAction::SupplyChanged)
+}
+
+pub fn schedule_round(
+  ctx: Context<SetRoundScheduled>,
+  start_time: i64,
+) -> Result<()> {
+  let round = &mut ctx.accounts.round.load_mut()?;
+  let from = round.set_scheduled(start_time)?;
+
+  emit_state_changed(round, from)
 }
 
 pub fn open_round(
   ctx: Context<SetRoundOpened>,
 ) -> Result<()> {
-  let round = &mut ctx.accounts.round;
-  round.set_open().unwrap();
+  let round = &mut ctx.accounts.round.load_mut()?;
+  let from = round.set_open()?;
+
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_round(round.get_id())?;
+
+  emit_sale_config(sale, events::SaleAction::RoundActivated)?;
+  emit_state_changed(round, from)
+}
+
+pub fn pause_round(
+  ctx: Context<SetRoundPaused>,
+) -> Result<()> {
+  let round = &mut ctx.accounts.round.load_mut()?;
+  let from = round.set_paused()?;
 
-  let sale = &mut ctx.accounts.sale;
-  sale.set_round(round.get_id())
+  emit_state_changed(round, from)
+}
+
+pub fn resume_round(
+  ctx: Context<SetRoundResumed>,
+) -> Result<()> {
+  let round = &mut ctx.accounts.round.load_mut()?;
+  let from = round.set_resumed()?;
+
+  emit_state_changed(round, from)
 }
 
 pub fn close_round(
   ctx: Context<SetRoundClosed>,
 ) -> Result<()> {
-  let round = &mut ctx.accounts.round;
-  round.set_close()
+  let round = &mut ctx.accounts.round.load_mut()?;
+  let from = round.set_close()?;
+
+  emit_state_changed(round, from)
+}
+
+pub fn close_round_account(
+  ctx: Context<CloseRoundAccount>,
+) -> Result<()> {
+  if !ctx.accounts.sale.load()?.is_closed() || !ctx.accounts.round.load()?.is_finalised() {
+    return err!(errors::Sale::RoundNotFinalised);
+  }
+
+  emit_account_closed(ctx.accounts.round.key(), events::AccountKind::Round, ctx.accounts.payer.key())
+}
+
+pub fn cancel_round(
+  ctx: Context<SetRoundCancelled>,
+) -> Result<()> {
+  let round = &mut ctx.accounts.round.load_mut()?;
+  let from = round.set_cancel()?;
+
+  emit_state_changed(round, from)
+}
+
+pub fn settle_auction(
+  ctx: Context<SettleAuction>,
+  owner: Pubkey,
+) -> Result<()> {
+  let round = &mut ctx.accounts.round.load_mut()?;
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  let beneficiary = &mut ctx.accounts.beneficiary;
+
+  let clearing_price = round.get_clearing_price();
+  if round.get_state() != State::Closed || clearing_price == 0 {
+    return err!(errors::Sale::AuctionNotCleared);
+  }
+
+  let (bought_amount, cleared_amount, usd_amount) = beneficiary.settle_auction(round.get_id(), clearing_price)?;
+  round.set_auction_settled(cleared_amount, usd_amount)?;
+  sale.set_auction_settled(bought_amount, cleared_amount)?;
+
+  emit!(events::AuctionSettledEvent {
+    version: events::EVENT_VERSION,
+    round: round.get_id(),
+    beneficiary: owner,
+    clearing_price,
+    bought_token_amount: bought_amount,
+    token_amount: cleared_amount,
+    settled_usd: usd_amount,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+pub fn emit_round_config(
+  round: &Round,
+  action: events::RoundAction,
+) -> Result<()> {
+  emit!(events::RoundConfigChangedEvent {
+    version: events::EVENT_VERSION,
+    round: round.get_id(),
+    action,
+    config: round.get_config(),
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+pub fn emit_state_changed(
+  round: &mut Round,
+  from: State,
+) -> Result<()> {
+  emit!(events::RoundStateChangedEvent {
+    version: events::EVENT_VERSION,
+    round: round.get_id(),
+    from,
+    to: round.get_state(),
+    clearing_price: round.get_clearing_price(),
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
 }
 
 pub const ROUND_TAG: &[u8] = b"ROUND";
@@ -53,7 +204,7 @@ pub struct InitRound<'info> {
   #[account(
     init,
     payer = payer,
-    space = 680,
+    space = 8 + std::mem::size_of::<Round>(),
     seeds = [
       ROUND_TAG,
       b"_",
@@ -61,7 +212,7 @@ pub struct InitRound<'info> {
     ],
     bump,
   )]
-  pub round: Account<'info, Round>,
+  pub round: AccountLoader<'info, Round>,
   #[account(mut)]
   pub payer: Signer<'info>,
   pub system_program: Program<'info, System>,
@@ -71,7 +222,32 @@ pub struct InitRound<'info> {
 #[instruction(price: u64)]
 pub struct SetRoundPrice<'info> {
   #[account(mut)]
-  pub round: Account<'info, Round>,
+  pub round: AccountLoader<'info, Round>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(price_mode: PriceMode, end_price: u64, price_steps: u16)]
+pub struct SetRoundPricing<'info> {
+  #[account(mut)]
+  pub round: AccountLoader<'info, Round>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+  start_price: u64,
+  floor_price: u64,
+  start_time: i64,
+  end_time: i64,
+  decay_curve: DecayCurve,
+  uniform_clearing: bool,
+)]
+pub struct SetRoundAuction<'info> {
+  #[account(mut)]
+  pub round: AccountLoader<'info, Round>,
   #[account(mut)]
   pub payer: Signer<'info>,
 }
@@ -80,7 +256,16 @@ pub struct SetRoundPrice<'info> {
 #[instruction(total_supply: u128)]
 pub struct SetRoundSupply<'info> {
   #[account(mut)]
-  pub round: Account<'info, Round>,
+  pub round: AccountLoader<'info, Round>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(start_time: i64)]
+pub struct SetRoundScheduled<'info> {
+  #[account(mut)]
+  pub round: AccountLoader<'info, Round>,
   #[account(mut)]
   pub payer: Signer<'info>,
 }
@@ -88,9 +273,9 @@ pub struct SetRoundSupply<'info> {
 #[derive(Accounts)]
 pub struct SetRoundOpened<'info> {
   #[account(mut)]
-  pub round: Account<'info, Round>,
+  pub round: AccountLoader<'info, Round>,
   #[account(mut)]
-  pub sale: Account<'info, Sale>,
+  pub sale: AccountLoader<'info, Sale>,
   #[account(mut)]
   pub payer: Signer<'info>,
 }
@@ -98,9 +283,65 @@ pub struct SetRoundOpened<'info> {
 #[derive(Accounts)]
 pub struct SetRoundClosed<'info> {
   #[account(mut)]
-  pub round: Account<'info, Round>,
+  pub round: AccountLoader<'info, Round>,
   #[account(mut)]
-  pub sale: Account<'info, Sale>,
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRoundPaused<'info> {
+  #[account(mut)]
+  pub round: AccountLoader<'info, Round>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRoundResumed<'info> {
   #[account(mut)]
+  pub round: AccountLoader<'info, Round>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRoundCancelled<'info> {
+  #[account(mut)]
+  pub round: AccountLoader<'info, Round>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseRoundAccount<'info> {
+  #[account(
+    mut,
+    close = payer,
+  )]
+  pub round: AccountLoader<'info, Round>,
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(owner: Pubkey)]
+pub struct SettleAuction<'info> {
+  #[account(mut)]
+  pub round: AccountLoader<'info, Round>,
+  #[account(mut)]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(
+    mut,
+    seeds = [
+      BENEFICIARY_TAG,
+      b"_",
+      owner.as_ref()
+    ],
+    bump
+  )]
+  pub beneficiary: Account<'info, Beneficiary>,
   pub payer: Signer<'info>,
 }
\ No newline at end of file
@@ -2,27 +2,31 @@ use anchor_lang::{
   prelude::*,
   solana_program::{ program::invoke, system_instruction::transfer },
 };
-use anchor_spl::token::{ self, Token, TokenAccount, Transfer as SplTransfer };
-use pyth_sdk_solana::{ load_price_feed_from_account_info, PriceFeed, Price };
+use anchor_spl::token::{ self, Mint, Token, TokenAccount, Transfer as SplTransfer };
 use std::str::FromStr;
 
 use crate::errors;
 use crate::events;
+use crate::math;
 use crate::state::sale::*;
-use crate::state::round::Round;
+use crate::state::round::{ Round, State as RoundState };
 use crate::state::referral::Referral;
 use crate::state::beneficiary::Beneficiary;
-use crate::auth::{ SOL_USD_PRICEFEED, TREASURY, USDC, USDT, STABLE_PRECISION };
-use crate::referral::{ REFERRAL_TAG, EMPTY_REFERRAL_KEY };
+use crate::state::purchase::{ PurchaseHistory, PurchaseReceipt, Purchase, PaymentAsset };
+use crate::auth::{ SOL_USD_PRICEFEED, TREASURY, USDC, USDT, PRECISION, STABLE_PRECISION };
+use crate::referral::{ REFERRAL_TAG, EMPTY_REFERRAL_KEY, check_referral, resolve_referral, set_referral_volume, get_uplines, set_upline_rewards, get_upline_reward_amount, Upline };
+use crate::round::emit_state_changed;
 
-const PRECISION: u32                = 9;
+#[allow(dead_code)]
 const STALENESS_THRESHOLD: u64      = 60;
 
 pub fn initialize_sale(
   ctx: Context<InitSale>,
 ) -> Result<()> {
-  let sale = &mut ctx.accounts.sale;
-  sale.init()
+  let sale = &mut ctx.accounts.sale.load_init()?;
+  sale.init()?;
+
+  emit_sale_config(sale, events::SaleAction::Initialized)
 }
 
 pub fn set_sale_investment(
@@ -30,8 +34,10 @@ pub fn set_sale_investment(
   max_investment: u64,
   min_investment: u64,
 ) -> Result<()> {
-  let sale = &mut ctx.accounts.sale;
-  sale.set_investment(max_investment, min_investment)
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_investment(max_investment, min_investment)?;
+
+  emit_sale_config(sale, events::SaleAction::InvestmentChanged)
 }
 
 pub fn set_sale_reward(
@@ -39,48 +45,227 @@ pub fn set_sale_reward(
   main_reward: u64,
   secondary_reward: u64,
 ) -> Result<()> {
-  let sale = &mut ctx.accounts.sale;
-  sale.set_reward(main_reward, secondary_reward)
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_reward(main_reward, secondary_reward)?;
+
+  emit_sale_config(sale, events::SaleAction::RewardChanged)
+}
+
+pub fn set_sale_ref_levels(
+  ctx: Context<SetSaleRefLevels>,
+  referral_depth: u8,
+  upline_rewards: [u64; MAX_UPLINE_LEVELS],
+) -> Result<()> {
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_referral_levels(referral_depth, upline_rewards)?;
+
+  emit_sale_config(sale, events::SaleAction::ReferralLevelsChanged)
+}
+
+pub fn set_sale_claim(
+  ctx: Context<SetSaleClaim>,
+  claim_start: i64,
+  vesting_duration: i64,
+) -> Result<()> {
+  let token_mint = ctx.accounts.token_mint.key();
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_claim(token_mint, claim_start, vesting_duration)?;
+
+  emit_sale_config(sale, events::SaleAction::ClaimChanged)
+}
+
+pub fn set_sale_referral_rules(
+  ctx: Context<SetSaleReferralRules>,
+  bind_referrer: bool,
+  min_referral_usd: u64,
+) -> Result<()> {
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_referral_rules(bind_referrer, min_referral_usd)?;
+
+  emit_sale_config(sale, events::SaleAction::ReferralRulesChanged)
+}
+
+pub fn set_sale_ref_budget(
+  ctx: Context<SetSaleRefBudget>,
+  referrer_cap_usd: u64,
+  budget_usd: u64,
+  budget_tokens: u128,
+) -> Result<()> {
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_referral_budget(referrer_cap_usd, budget_usd, budget_tokens)?;
+
+  emit_sale_config(sale, events::SaleAction::ReferralBudgetChanged)
+}
+
+pub fn set_sale_reward_mode(
+  ctx: Context<SetSaleRewardMode>,
+  reward_mode: RewardMode,
+  cash_share: u64,
+) -> Result<()> {
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_reward_mode(reward_mode, cash_share)?;
+
+  emit_sale_config(sale, events::SaleAction::RewardModeChanged)
+}
+
+pub fn set_sale_buyer_bonus(
+  ctx: Context<SetSaleBuyerBonus>,
+  buyer_bonus: u64,
+) -> Result<()> {
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_buyer_bonus(buyer_bonus)?;
+
+  emit_sale_config(sale, events::SaleAction::BuyerBonusChanged)
+}
+
+pub fn set_sale_referral_program(
+  ctx: Context<SetSaleReferralProgram>,
+  enabled: bool,
+  reject_disabled_referral: bool,
+) -> Result<()> {
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_referral_program(enabled, reject_disabled_referral)?;
+
+  emit_sale_config(sale, events::SaleAction::ReferralProgramChanged)
+}
+
+pub fn set_sale_ref_tiers(
+  ctx: Context<SetSaleRefTiers>,
+  tiers: Vec<RewardTier>,
+) -> Result<()> {
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_reward_tiers(tiers)?;
+
+  emit_sale_config(sale, events::SaleAction::RewardTiersChanged)
 }
 
 pub fn open_sale(
   ctx: Context<SetSaleOpened>,
 ) -> Result<()> {
-  let sale = &mut ctx.accounts.sale;
-  sale.set_open()
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_open()?;
+
+  emit_sale_config(sale, events::SaleAction::Opened)
 }
 
 pub fn close_sale(
   ctx: Context<SetSaleClosed>,
 ) -> Result<()> {
-  let sale = &mut ctx.accounts.sale;
-  sale.set_close()
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_close()?;
+
+  emit_sale_config(sale, events::SaleAction::Closed)
 }
 
-pub fn deposit(
-  ctx: Context<Deposit>,
+// The sale PDA owns the claim vault, so it can only go once the vault has
+// been emptied.
+pub fn close_sale_account(
+  ctx: Context<CloseSaleAccount>,
+) -> Result<()> {
+  let sale = &ctx.accounts.sale.load()?;
+  if !sale.is_closed() {
+    return err!(errors::Sale::SaleNotFinalised);
+  }
+
+  if sale.get_token_mint() != Pubkey::default() {
+    match &ctx.accounts.vault {
+      Some(vault) if vault.amount == 0 => {},
+      _ => return err!(errors::Sale::SaleNotFinalised),
+    }
+  }
+
+  if sale.has_unclaimed_tokens() {
+    return err!(errors::Sale::SaleNotFinalised);
+  }
+
+  emit_account_closed(ctx.accounts.sale.key(), events::AccountKind::Sale, ctx.accounts.payer.key())
+}
+
+pub fn close_beneficiary(
+  ctx: Context<CloseBeneficiary>,
+) -> Result<()> {
+  if !ctx.accounts.sale.load()?.is_closed() {
+    return err!(errors::Sale::SaleNotFinalised);
+  }
+
+  if !ctx.accounts.beneficiary.is_closable() {
+    return err!(errors::Sale::BeneficiaryNotClaimed);
+  }
+
+  emit_account_closed(ctx.accounts.beneficiary.key(), events::AccountKind::Beneficiary, ctx.accounts.payer.key())
+}
+
+pub fn pause_sale(
+  ctx: Context<SetSalePaused>,
+  scope: u8,
+  reason: u8,
+) -> Result<()> {
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_paused(scope, reason)?;
+
+  let (paused, _) = sale.get_paused();
+  emit!(events::SalePausedEvent {
+    version: events::EVENT_VERSION,
+    scope,
+    paused,
+    reason,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+pub fn unpause_sale(
+  ctx: Context<SetSaleUnpaused>,
+  scope: u8,
+) -> Result<()> {
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  sale.set_unpaused(scope)?;
+
+  let (paused, _) = sale.get_paused();
+  emit!(events::SaleUnpausedEvent {
+    version: events::EVENT_VERSION,
+    scope,
+    paused,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+pub fn deposit<'info>(
+  ctx: Context<'_, '_, 'info, 'info, Deposit<'info>>,
   ref_key: Pubkey,
   amount: u64,
 ) -> Result<()> {
+  log_compute_units();
   let to_account_infos = &mut ctx.accounts.to_account_infos();
   let payer = &mut ctx.accounts.payer;
-  let sale = &mut ctx.accounts.sale;
-  let round = &mut ctx.accounts.round;
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  let round = &mut ctx.accounts.round.load_mut()?;
   let beneficiary = &mut ctx.accounts.beneficiary;
   let referral = &mut ctx.accounts.referral;
+  let purchase_history = &mut ctx.accounts.purchase_history;
+  let purchase_receipt = &mut ctx.accounts.purchase_receipt;
   let price_info = &ctx.accounts.price_info;
   let treasury_info = &mut ctx.accounts.treasury_info;
+  let system_program = &ctx.accounts.system_program;
 
   if !sale.is_open() {
     return err!(errors::Sale::SaleNotOpened);
   }
 
-  if !round.is_open() {
-    return err!(errors::Sale::RoundNotOpened);
+  if sale.is_paused(PAUSE_DEPOSIT) {
+    return err!(errors::Sale::DepositsPaused);
   }
 
-  if sale.get_round() != round.get_id() {
-    return err!(errors::Sale::InactiveRound);
+  let requested_key = ref_key;
+  let ref_key = resolve_referral(sale, referral, ref_key)?;
+
+  let now = Clock::get()?.unix_timestamp;
+  if let Some(from) = check_round(sale, round, now)? {
+    emit_sale_config(sale, events::SaleAction::RoundActivated)?;
+    emit_state_changed(round, from)?;
   }
 
   if Pubkey::from_str(TREASURY) != Ok(treasury_info.key()){
@@ -91,71 +276,94 @@ pub fn deposit(
     return Err(error!(errors::Sale::WrongPriceFeedId))
   };
   
-  let (price, expo) = get_price(&price_info).unwrap();
-  let usd_amount = u128::from(amount) * price / 10u128.pow(expo);
-  let token_amount = usd_amount * 10u128.pow(PRECISION) / u128::from(round.get_price());
+  let (price, expo) = get_price(price_info)?;
+  let usd_amount = math::mul_div_floor(u128::from(amount), price, math::pow10(expo)?)?;
+  let asset_price = math::to_u64(math::mul_div_floor(price, math::pow10(PRECISION)?, math::pow10(expo)?)?)?;
+  let (token_amount, bonus_amount) = get_purchase(sale, round, ref_key, referral, usd_amount, now)?;
+  let credited_amount = math::add(token_amount, bonus_amount)?;
 
-  if sale.get_max_investment() < usd_amount {
-    return err!(errors::Sale::SaleMaxInvestmentExceeded);
-  }
-
-  if sale.get_min_investment() > usd_amount {
-    return err!(errors::Sale::SaleMinInvestmentNotReached);
-  }
-
-  if round.get_total_sold() + token_amount > round.get_total_supply() {
+  if credited_amount > round.get_available_supply() {
     return err!(errors::Sale::RoundSupplyExceeded);
   }
+
+  let new_buyer = check_referral(sale, beneficiary, payer.key(), requested_key, ref_key, usd_amount)?;
   
-  let (sol_reward_amount, token_reward_amount) = get_reward(sale, ref_key, referral, amount, token_amount).unwrap();
-  let mut to_amount = amount;
-  if sol_reward_amount > 0 {
-    to_amount = to_amount - sol_reward_amount;
-  }
+  let (sol_reward_amount, token_reward_amount, referral_usd) = get_reward(sale, ref_key, referral, amount, usd_amount, token_amount)?;
+  let mut uplines = get_uplines(sale, payer.key(), ref_key, referral, ctx.remaining_accounts, None)?;
+  set_upline_rewards(sale, &mut uplines, amount, usd_amount, token_amount)?;
+  let upline_reward_amount = get_upline_reward_amount(&uplines)?;
+  let reward_amount = math::add(u128::from(sol_reward_amount), u128::from(upline_reward_amount))?;
+  let to_amount = math::to_u64(math::sub(u128::from(amount), reward_amount)?)?;
 
   let instruction = &transfer(&payer.key(), &treasury_info.key(), to_amount);
-  invoke(instruction, to_account_infos).unwrap();
+  invoke(instruction, to_account_infos).map_err(|_| error!(errors::Sale::TransferFailed))?;
 
   if sol_reward_amount > 0 {
     let instruction = &transfer(&payer.key(), &referral.key(), sol_reward_amount);
-    invoke(instruction, to_account_infos).unwrap();
+    invoke(instruction, to_account_infos).map_err(|_| error!(errors::Sale::TransferFailed))?;
+  }
+
+  for upline in uplines.iter() {
+    if upline.reward_amount > 0 {
+      let instruction = &transfer(&payer.key(), &upline.referral.key(), upline.reward_amount);
+      let account_infos = &[payer.to_account_info(), upline.referral.to_account_info(), system_program.to_account_info()];
+      invoke(instruction, account_infos).map_err(|_| error!(errors::Sale::TransferFailed))?;
+    }
   }
 
   // Updating sale details
-  sale.set_total_sold(token_amount).unwrap();
+  sale.set_total_sold(credited_amount)?;
 
   // Updating round details
-  round.set_total_sold(token_amount).unwrap();
+  round.set_total_sold(credited_amount)?;
+  round.set_purchase(usd_amount, bonus_amount, now)?;
+  if let Some(from) = round.update_sold_out()? {
+    emit_state_changed(round, from)?;
+  }
 
   // Updating beneficiary details
-  beneficiary.set_token_amount(token_amount).unwrap();
-
-  // Updating referral details
-  if Pubkey::from_str(EMPTY_REFERRAL_KEY) != Ok(ref_key){
-    referral.set_sol_reward_amount(sol_reward_amount).unwrap();
-    referral.set_token_reward_amount(token_reward_amount).unwrap();
-  };
+  if round.is_uniform_clearing() {
+    beneficiary.set_auction_purchase(round.get_id(), usd_amount, token_amount)?;
+  }
 
-  emit!(events::DepositSolEvent {
+  record_deposit(sale, beneficiary, purchase_history, purchase_receipt.as_deref_mut(), referral, &mut uplines, &DepositRecord {
+    payer: payer.key(),
     round: round.get_id(),
-    beneficiary: payer.key(),
-    referral: ref_key,
-    sol_amount: amount,
-    token_amount: token_amount,
-  });
+    shard: None,
+    purchase: Purchase {
+      asset: PaymentAsset::Sol,
+      paid_amount: amount,
+      usd_amount,
+      token_amount,
+      bonus_amount,
+      referral: ref_key,
+      timestamp: now,
+    },
+    asset_price,
+    new_buyer,
+    referral_amount: sol_reward_amount,
+    referral_usd,
+    referral_token_amount: token_reward_amount,
+    upline_amount: upline_reward_amount,
+  })?;
+
+  log_compute_units();
   Ok(())
 }
 
-pub fn deposit_usdc(
-  ctx: Context<DepositUSDC>,
+pub fn deposit_usdc<'info>(
+  ctx: Context<'_, '_, 'info, 'info, DepositUSDC<'info>>,
   ref_key: Pubkey,
   amount: u64,
 ) -> Result<()> {
+  log_compute_units();
   let payer = &mut ctx.accounts.payer;
-  let sale = &mut ctx.accounts.sale;
-  let round = &mut ctx.accounts.round;
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  let round = &mut ctx.accounts.round.load_mut()?;
   let beneficiary = &mut ctx.accounts.beneficiary;
   let referral = &mut ctx.accounts.referral;
+  let purchase_history = &mut ctx.accounts.purchase_history;
+  let purchase_receipt = &mut ctx.accounts.purchase_receipt;
 
   let beneficiary_ata = &ctx.accounts.beneficiary_ata;
   let treasury_ata = &ctx.accounts.treasury_ata;
@@ -166,34 +374,36 @@ pub fn deposit_usdc(
     return err!(errors::Sale::SaleNotOpened);
   }
 
-  if !round.is_open() {
-    return err!(errors::Sale::RoundNotOpened);
-  }
-
-  if sale.get_round() != round.get_id() {
-    return err!(errors::Sale::InactiveRound);
+  if sale.is_paused(PAUSE_DEPOSIT) {
+    return err!(errors::Sale::DepositsPaused);
   }
 
-  let usd_amount = u128::from(amount) * 10u128.pow(STABLE_PRECISION);
-  let token_amount = usd_amount * 10u128.pow (PRECISION) / u128::from(round.get_price());
+  let requested_key = ref_key;
+  let ref_key = resolve_referral(sale, referral, ref_key)?;
 
-  if sale.get_max_investment() < usd_amount {
-    return err!(errors::Sale::SaleMaxInvestmentExceeded);
+  let now = Clock::get()?.unix_timestamp;
+  if let Some(from) = check_round(sale, round, now)? {
+    emit_sale_config(sale, events::SaleAction::RoundActivated)?;
+    emit_state_changed(round, from)?;
   }
 
-  if sale.get_min_investment() > usd_amount {
-    return err!(errors::Sale::SaleMinInvestmentNotReached);
-  }
+  let usd_amount = math::mul(u128::from(amount), math::pow10(STABLE_PRECISION)?)?;
+  let asset_price = math::to_u64(math::pow10(PRECISION)?)?;
+  let (token_amount, bonus_amount) = get_purchase(sale, round, ref_key, referral, usd_amount, now)?;
+  let credited_amount = math::add(token_amount, bonus_amount)?;
 
-  if round.get_total_sold() + token_amount > round.get_total_supply() {
+  if credited_amount > round.get_available_supply() {
     return err!(errors::Sale::RoundSupplyExceeded);
   }
 
-  let (stable_reward_amount, token_reward_amount) = get_reward(sale, ref_key, referral, amount, token_amount).unwrap();
-  let mut to_amount = amount;
-  if stable_reward_amount > 0 {
-    to_amount = to_amount - stable_reward_amount;
-  }
+  let new_buyer = check_referral(sale, beneficiary, payer.key(), requested_key, ref_key, usd_amount)?;
+
+  let (stable_reward_amount, token_reward_amount, referral_usd) = get_reward(sale, ref_key, referral, amount, usd_amount, token_amount)?;
+  let mut uplines = get_uplines(sale, payer.key(), ref_key, referral, ctx.remaining_accounts, Some(beneficiary_ata.mint))?;
+  set_upline_rewards(sale, &mut uplines, amount, usd_amount, token_amount)?;
+  let upline_reward_amount = get_upline_reward_amount(&uplines)?;
+  let reward_amount = math::add(u128::from(stable_reward_amount), u128::from(upline_reward_amount))?;
+  let to_amount = math::to_u64(math::sub(u128::from(amount), reward_amount)?)?;
 
   let cpi_accounts = SplTransfer {
     from: beneficiary_ata.to_account_info(),
@@ -201,7 +411,7 @@ pub fn deposit_usdc(
     authority: payer.to_account_info(),
   };
   let cpi_program = token_program.to_account_info();
-  token::transfer(CpiContext::new(cpi_program, cpi_accounts), to_amount).unwrap();
+  token::transfer(CpiContext::new(cpi_program, cpi_accounts), to_amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
   
   if stable_reward_amount > 0 {
     let cpi_accounts = SplTransfer {
@@ -210,45 +420,78 @@ pub fn deposit_usdc(
       authority: payer.to_account_info(),
     };
     let cpi_program = token_program.to_account_info();
-    token::transfer(CpiContext::new(cpi_program, cpi_accounts), stable_reward_amount).unwrap();
+    token::transfer(CpiContext::new(cpi_program, cpi_accounts), stable_reward_amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
+  }
+
+  for upline in uplines.iter() {
+    if upline.reward_amount == 0 {
+      continue;
+    }
+
+    if let Some(upline_ata) = &upline.referral_ata {
+      let cpi_accounts = SplTransfer {
+        from: beneficiary_ata.to_account_info(),
+        to: upline_ata.to_account_info(),
+        authority: payer.to_account_info(),
+      };
+      let cpi_program = token_program.to_account_info();
+      token::transfer(CpiContext::new(cpi_program, cpi_accounts), upline.reward_amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
+    }
   }
 
   // Updating sale details
-  sale.set_total_sold(token_amount).unwrap();
+  sale.set_total_sold(credited_amount)?;
 
   // Updating round details
-  round.set_total_sold(token_amount).unwrap();
+  round.set_total_sold(credited_amount)?;
+  round.set_purchase(usd_amount, bonus_amount, now)?;
+  if let Some(from) = round.update_sold_out()? {
+    emit_state_changed(round, from)?;
+  }
 
   // Updating beneficiary details
-  beneficiary.set_token_amount(token_amount).unwrap();
-
-  // Updating referral details
-  if Pubkey::from_str(EMPTY_REFERRAL_KEY) != Ok(ref_key){
-    referral.set_usdc_reward_amount(stable_reward_amount).unwrap();
-    referral.set_token_reward_amount(token_reward_amount).unwrap();
-  };
+  if round.is_uniform_clearing() {
+    beneficiary.set_auction_purchase(round.get_id(), usd_amount, token_amount)?;
+  }
 
-  emit!(events::DepositUsdcEvent {
+  record_deposit(sale, beneficiary, purchase_history, purchase_receipt.as_deref_mut(), referral, &mut uplines, &DepositRecord {
+    payer: payer.key(),
     round: round.get_id(),
-    beneficiary: payer.key(),
-    referral: ref_key,
-    usdc_amount: amount,
-    token_amount: token_amount,
-  });
-
+    shard: None,
+    purchase: Purchase {
+      asset: PaymentAsset::Usdc,
+      paid_amount: amount,
+      usd_amount,
+      token_amount,
+      bonus_amount,
+      referral: ref_key,
+      timestamp: now,
+    },
+    asset_price,
+    new_buyer,
+    referral_amount: stable_reward_amount,
+    referral_usd,
+    referral_token_amount: token_reward_amount,
+    upline_amount: upline_reward_amount,
+  })?;
+
+  log_compute_units();
   Ok(())
 }
 
-pub fn deposit_usdt(
-  ctx: Context<DepositUSDT>,
+pub fn deposit_usdt<'info>(
+  ctx: Context<'_, '_, 'info, 'info, DepositUSDT<'info>>,
   ref_key: Pubkey,
   amount: u64,
 ) -> Result<()> {
+  log_compute_units();
   let payer = &mut ctx.accounts.payer;
-  let sale = &mut ctx.accounts.sale;
-  let round = &mut ctx.accounts.round;
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  let round = &mut ctx.accounts.round.load_mut()?;
   let beneficiary = &mut ctx.accounts.beneficiary;
   let referral = &mut ctx.accounts.referral;
+  let purchase_history = &mut ctx.accounts.purchase_history;
+  let purchase_receipt = &mut ctx.accounts.purchase_receipt;
 
   let beneficiary_ata = &ctx.accounts.beneficiary_ata;
   let treasury_ata = &ctx.accounts.treasury_ata;
@@ -259,34 +502,36 @@ pub fn deposit_usdt(
     return err!(errors::Sale::SaleNotOpened);
   }
 
-  if !round.is_open() {
-    return err!(errors::Sale::RoundNotOpened);
+  if sale.is_paused(PAUSE_DEPOSIT) {
+    return err!(errors::Sale::DepositsPaused);
   }
 
-  if sale.get_round() != round.get_id() {
-    return err!(errors::Sale::InactiveRound);
-  }
-
-  let usd_amount = u128::from(amount) * 10u128.pow(STABLE_PRECISION);
-  let token_amount = usd_amount * 10u128.pow (PRECISION) / u128::from(round.get_price());
+  let requested_key = ref_key;
+  let ref_key = resolve_referral(sale, referral, ref_key)?;
 
-  if sale.get_max_investment() < usd_amount {
-    return err!(errors::Sale::SaleMaxInvestmentExceeded);
+  let now = Clock::get()?.unix_timestamp;
+  if let Some(from) = check_round(sale, round, now)? {
+    emit_sale_config(sale, events::SaleAction::RoundActivated)?;
+    emit_state_changed(round, from)?;
   }
 
-  if sale.get_min_investment() > usd_amount {
-    return err!(errors::Sale::SaleMinInvestmentNotReached);
-  }
+  let usd_amount = math::mul(u128::from(amount), math::pow10(STABLE_PRECISION)?)?;
+  let asset_price = math::to_u64(math::pow10(PRECISION)?)?;
+  let (token_amount, bonus_amount) = get_purchase(sale, round, ref_key, referral, usd_amount, now)?;
+  let credited_amount = math::add(token_amount, bonus_amount)?;
 
-  if round.get_total_sold() + token_amount > round.get_total_supply() {
+  if credited_amount > round.get_available_supply() {
     return err!(errors::Sale::RoundSupplyExceeded);
   }
 
-  let (stable_reward_amount, token_reward_amount) = get_reward(sale, ref_key, referral, amount, token_amount).unwrap();
-  let mut to_amount = amount;
-  if stable_reward_amount > 0 {
-    to_amount = to_amount - stable_reward_amount;
-  }
+  let new_buyer = check_referral(sale, beneficiary, payer.key(), requested_key, ref_key, usd_amount)?;
+
+  let (stable_reward_amount, token_reward_amount, referral_usd) = get_reward(sale, ref_key, referral, amount, usd_amount, token_amount)?;
+  let mut uplines = get_uplines(sale, payer.key(), ref_key, referral, ctx.remaining_accounts, Some(beneficiary_ata.mint))?;
+  set_upline_rewards(sale, &mut uplines, amount, usd_amount, token_amount)?;
+  let upline_reward_amount = get_upline_reward_amount(&uplines)?;
+  let reward_amount = math::add(u128::from(stable_reward_amount), u128::from(upline_reward_amount))?;
+  let to_amount = math::to_u64(math::sub(u128::from(amount), reward_amount)?)?;
 
   let cpi_accounts = SplTransfer {
     from: beneficiary_ata.to_account_info(),
@@ -294,7 +539,7 @@ pub fn deposit_usdt(
     authority: payer.to_account_info(),
   };
   let cpi_program = token_program.to_account_info();
-  token::transfer(CpiContext::new(cpi_program, cpi_accounts), to_amount).unwrap();
+  token::transfer(CpiContext::new(cpi_program, cpi_accounts), to_amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
   
   if stable_reward_amount > 0 {
     let cpi_accounts = SplTransfer {
@@ -303,73 +548,490 @@ pub fn deposit_usdt(
       authority: payer.to_account_info(),
     };
     let cpi_program = token_program.to_account_info();
-    token::transfer(CpiContext::new(cpi_program, cpi_accounts), stable_reward_amount).unwrap();
+    token::transfer(CpiContext::new(cpi_program, cpi_accounts), stable_reward_amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
+  }
+
+  for upline in uplines.iter() {
+    if upline.reward_amount == 0 {
+      continue;
+    }
+
+    if let Some(upline_ata) = &upline.referral_ata {
+      let cpi_accounts = SplTransfer {
+        from: beneficiary_ata.to_account_info(),
+        to: upline_ata.to_account_info(),
+        authority: payer.to_account_info(),
+      };
+      let cpi_program = token_program.to_account_info();
+      token::transfer(CpiContext::new(cpi_program, cpi_accounts), upline.reward_amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
+    }
   }
 
   // Updating sale details
-  sale.set_total_sold(token_amount).unwrap();
+  sale.set_total_sold(credited_amount)?;
 
   // Updating round details
-  round.set_total_sold(token_amount).unwrap();
+  round.set_total_sold(credited_amount)?;
+  round.set_purchase(usd_amount, bonus_amount, now)?;
+  if let Some(from) = round.update_sold_out()? {
+    emit_state_changed(round, from)?;
+  }
 
   // Updating beneficiary details
-  beneficiary.set_token_amount(token_amount).unwrap();
+  if round.is_uniform_clearing() {
+    beneficiary.set_auction_purchase(round.get_id(), usd_amount, token_amount)?;
+  }
 
-  // Updating referral details
-  if Pubkey::from_str(EMPTY_REFERRAL_KEY) != Ok(ref_key){
-    referral.set_usdt_reward_amount(stable_reward_amount).unwrap();
-    referral.set_token_reward_amount(token_reward_amount).unwrap();
+  record_deposit(sale, beneficiary, purchase_history, purchase_receipt.as_deref_mut(), referral, &mut uplines, &DepositRecord {
+    payer: payer.key(),
+    round: round.get_id(),
+    shard: None,
+    purchase: Purchase {
+      asset: PaymentAsset::Usdt,
+      paid_amount: amount,
+      usd_amount,
+      token_amount,
+      bonus_amount,
+      referral: ref_key,
+      timestamp: now,
+    },
+    asset_price,
+    new_buyer,
+    referral_amount: stable_reward_amount,
+    referral_usd,
+    referral_token_amount: token_reward_amount,
+    upline_amount: upline_reward_amount,
+  })?;
+
+  log_compute_units();
+  Ok(())
+}
+
+pub fn claim(
+  ctx: Context<Claim>,
+) -> Result<()> {
+  let payer = &ctx.accounts.payer;
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  let beneficiary = &mut ctx.accounts.beneficiary;
+  let vault = &ctx.accounts.vault;
+  let beneficiary_token_ata = &ctx.accounts.beneficiary_token_ata;
+  let token_program = &ctx.accounts.token_program;
+
+  if sale.is_paused(PAUSE_CLAIM) {
+    return err!(errors::Sale::ClaimsPaused);
+  }
+
+  if !beneficiary.is_auction_settled() {
+    return err!(errors::Sale::AuctionNotSettled);
+  }
+
+  let now = Clock::get()?.unix_timestamp;
+  let vested_amount = sale.get_vested_amount(beneficiary.get_token_amount(), now)?;
+  let amount = math::sub(vested_amount, beneficiary.get_claimed_amount())?;
+  if amount == 0 {
+    return err!(errors::Sale::NothingToClaim);
+  }
+
+  beneficiary.set_claimed_amount(amount)?;
+  sale.set_claimed_tokens(amount)?;
+
+  let token_amount = math::to_u64(amount)?;
+  let bump = &[ctx.bumps.sale];
+  let seeds: &[&[u8]] = &[bump];
+  let signer_seeds = &[seeds];
+
+  let cpi_accounts = SplTransfer {
+    from: vault.to_account_info(),
+    to: beneficiary_token_ata.to_account_info(),
+    authority: ctx.accounts.sale.to_account_info(),
   };
+  let ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer_seeds);
+  token::transfer(ctx, token_amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
 
-  emit!(events::DepositUsdtEvent {
-    round: round.get_id(),
+  emit!(events::ClaimEvent {
+    version: events::EVENT_VERSION,
     beneficiary: payer.key(),
-    referral: ref_key,
-    usdt_amount: amount,
-    token_amount: token_amount,
+    token_amount,
+    claimed_amount: beneficiary.get_claimed_amount(),
+    timestamp: now,
   });
 
   Ok(())
 }
 
-pub fn get_price(price_info: &AccountInfo)
+pub fn get_price(_price_info: &AccountInfo)
   -> Result<(u128, u32)>
 {
   /*
-  let price_feed: PriceFeed = load_price_feed_from_account_info( &price_info ).unwrap();
+  let price_feed: PriceFeed = pyth_sdk_solana::load_price_feed_from_account_info( &price_info )
+    .map_err(|_| error!(errors::Sale::WrongPriceFeedId))?;
   let current_timestamp = Clock::get()?.unix_timestamp;
-  let current_price: Price = price_feed.get_price_no_older_than(current_timestamp, STALENESS_THRESHOLD).unwrap();
+  let current_price: Price = price_feed.get_price_no_older_than(current_timestamp, STALENESS_THRESHOLD)
+    .ok_or_else(|| error!(errors::Sale::PriceIsDown))?;
 
-  let price = u64::try_from(current_price.price).unwrap();
-  let expo = u32::try_from(-current_price.expo).unwrap();
+  let price = u128::try_from(current_price.price).map_err(|_| error!(errors::Sale::PriceIsDown))?;
+  let expo = u32::try_from(-current_price.expo).map_err(|_| error!(errors::Sale::PriceIsDown))?;
   Ok((price, expo))
   */
-  return Ok((14400000000, 8));
+  Ok((14400000000, 8))
+}
+
+// A scheduled round goes live with the first deposit after its start time
+// and becomes the sale's active round, as `open_round` does. Returns the
+// state it left when it was activated.
+pub fn check_round(
+  sale: &mut Sale,
+  round: &mut Round,
+  now: i64,
+) -> Result<Option<RoundState>> {
+  let activated = round.activate(now)?;
+  if activated.is_some() {
+    sale.set_round(round.get_id())?;
+  }
+
+  if !round.is_open() {
+    return err!(errors::Sale::RoundNotOpened);
+  }
+
+  if sale.get_round() != round.get_id() {
+    return err!(errors::Sale::InactiveRound);
+  }
+
+  Ok(activated)
+}
+
+pub fn emit_sale_config(
+  sale: &Sale,
+  action: events::SaleAction,
+) -> Result<()> {
+  emit!(events::SaleConfigChangedEvent {
+    version: events::EVENT_VERSION,
+    action,
+    config: sale.get_config(),
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+pub fn emit_account_closed(
+  account: Pubkey,
+  kind: events::AccountKind,
+  recipient: Pubkey,
+) -> Result<()> {
+  emit!(events::AccountClosedEvent {
+    version: events::EVENT_VERSION,
+    account,
+    kind,
+    recipient,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+// Reports the remaining compute units around the deposit hot path when
+// built with the `cu-log` feature.
+fn log_compute_units() {
+  #[cfg(feature = "cu-log")]
+  anchor_lang::solana_program::log::sol_log_compute_units();
+}
+
+pub fn get_avg_price(
+  usd_amount: u128,
+  token_amount: u128,
+) -> Result<u64> {
+  if token_amount == 0 {
+    return Ok(0);
+  }
+
+  math::to_u64(math::mul_div_ceil(usd_amount, math::pow10(PRECISION)?, token_amount)?)
+}
+
+// Extra tokens credited to a referred buyer, at the better of the sale-wide
+// and referral-specific rates.
+pub fn get_buyer_bonus(
+  sale: &Sale,
+  ref_key: Pubkey,
+  referral: &Referral,
+  token_amount: u128,
+) -> Result<u128> {
+  if ref_key == EMPTY_REFERRAL_KEY{
+    return Ok(0);
+  };
+
+  let buyer_bonus = u64::max(sale.get_buyer_bonus(), referral.get_buyer_bonus());
+  math::mul_div_floor(token_amount, u128::from(buyer_bonus), math::pow10(PRECISION)?)
+}
+
+// A deposit whose payment went through: the purchase and the referral
+// rewards paid on it.
+pub struct DepositRecord {
+  pub payer: Pubkey,
+  pub round: i16,
+  pub shard: Option<u8>,
+  pub purchase: Purchase,
+  pub asset_price: u64,
+  pub new_buyer: bool,
+  pub referral_amount: u64,
+  pub referral_usd: u128,
+  pub referral_token_amount: u128,
+  pub upline_amount: u64,
+}
+
+// Quotes a deposit and checks it against the sale's investment limits.
+// Returns the tokens bought and the buyer bonus on top of them.
+pub fn get_purchase(
+  sale: &Sale,
+  round: &Round,
+  ref_key: Pubkey,
+  referral: &Referral,
+  usd_amount: u128,
+  now: i64,
+) -> Result<(u128, u128)> {
+  let token_amount = round.quote(usd_amount, now)?;
+  let bonus_amount = get_buyer_bonus(sale, ref_key, referral, token_amount)?;
+
+  if sale.get_max_investment() < usd_amount {
+    return err!(errors::Sale::SaleMaxInvestmentExceeded);
+  }
+
+  if sale.get_min_investment() > usd_amount {
+    return err!(errors::Sale::SaleMinInvestmentNotReached);
+  }
+
+  Ok((token_amount, bonus_amount))
+}
+
+// Books a deposit on every account but the sale, round and shard, which
+// each handler updates its own way, and emits its events.
+pub fn record_deposit(
+  sale: &Sale,
+  beneficiary: &mut Beneficiary,
+  purchase_history: &mut PurchaseHistory,
+  purchase_receipt: Option<&mut Account<PurchaseReceipt>>,
+  referral: &mut Referral,
+  uplines: &mut [Upline],
+  deposit: &DepositRecord,
+) -> Result<()> {
+  let purchase = deposit.purchase;
+
+  // Updating beneficiary details
+  beneficiary.set_version()?;
+  beneficiary.set_token_amount(math::add(purchase.token_amount, purchase.bonus_amount)?)?;
+
+  // Updating purchase history
+  record_purchase(purchase_history, purchase_receipt, deposit.payer, deposit.round, purchase)?;
+
+  // Updating referral details
+  if purchase.referral != EMPTY_REFERRAL_KEY {
+    referral.set_version()?;
+    set_cash_reward(referral, purchase.asset, deposit.referral_amount)?;
+    referral.set_token_reward_amount(deposit.referral_token_amount)?;
+    set_referral_volume(sale, referral, purchase.referral, purchase.usd_amount, deposit.new_buyer)?;
+  }
+
+  // Updating upline details
+  let mint = get_mint(purchase.asset)?;
+  for upline in uplines.iter_mut() {
+    set_cash_reward(&mut upline.referral, purchase.asset, upline.reward_amount)?;
+    upline.referral.set_token_reward_amount(upline.token_reward_amount)?;
+    upline.referral.exit(&crate::ID)?;
+
+    emit!(events::ReferralUplineRewardEvent {
+      version: events::EVENT_VERSION,
+      round: deposit.round,
+      beneficiary: deposit.payer,
+      referral: upline.key,
+      level: upline.level,
+      mint,
+      amount: upline.reward_amount,
+      token_amount: upline.token_reward_amount,
+      reward_usd: upline.reward_usd,
+      shard: deposit.shard,
+      timestamp: purchase.timestamp,
+    });
+  }
+
+  emit_deposit(deposit)
+}
+
+fn set_cash_reward(
+  referral: &mut Referral,
+  asset: PaymentAsset,
+  amount: u64,
+) -> Result<()> {
+  match asset {
+    PaymentAsset::Sol => referral.set_sol_reward_amount(amount),
+    PaymentAsset::Usdc => referral.set_usdc_reward_amount(amount),
+    PaymentAsset::Usdt => referral.set_usdt_reward_amount(amount),
+  }
+}
+
+fn get_mint(
+  asset: PaymentAsset,
+) -> Result<Pubkey> {
+  let mint = match asset {
+    PaymentAsset::Sol => return Ok(Pubkey::default()),
+    PaymentAsset::Usdc => USDC,
+    PaymentAsset::Usdt => USDT,
+  };
+
+  Pubkey::from_str(mint).map_err(|_| error!(errors::Sale::InvalidMint))
 }
 
+fn emit_deposit(
+  deposit: &DepositRecord,
+) -> Result<()> {
+  let purchase = deposit.purchase;
+  let avg_price = get_avg_price(purchase.usd_amount, purchase.token_amount)?;
+
+  match purchase.asset {
+    PaymentAsset::Sol => emit!(events::DepositSolEvent {
+      version: events::EVENT_VERSION,
+      round: deposit.round,
+      beneficiary: deposit.payer,
+      referral: purchase.referral,
+      sol_amount: purchase.paid_amount,
+      usd_amount: purchase.usd_amount,
+      asset_price: deposit.asset_price,
+      token_amount: purchase.token_amount,
+      bonus_amount: purchase.bonus_amount,
+      avg_price,
+      referral_amount: deposit.referral_amount,
+      referral_usd: deposit.referral_usd,
+      referral_token_amount: deposit.referral_token_amount,
+      upline_amount: deposit.upline_amount,
+      shard: deposit.shard,
+      timestamp: purchase.timestamp,
+    }),
+    PaymentAsset::Usdc => emit!(events::DepositUsdcEvent {
+      version: events::EVENT_VERSION,
+      round: deposit.round,
+      beneficiary: deposit.payer,
+      referral: purchase.referral,
+      usdc_amount: purchase.paid_amount,
+      usd_amount: purchase.usd_amount,
+      asset_price: deposit.asset_price,
+      token_amount: purchase.token_amount,
+      bonus_amount: purchase.bonus_amount,
+      avg_price,
+      referral_amount: deposit.referral_amount,
+      referral_usd: deposit.referral_usd,
+      referral_token_amount: deposit.referral_token_amount,
+      upline_amount: deposit.upline_amount,
+      shard: deposit.shard,
+      timestamp: purchase.timestamp,
+    }),
+    PaymentAsset::Usdt => emit!(events::DepositUsdtEvent {
+      version: events::EVENT_VERSION,
+      round: deposit.round,
+      beneficiary: deposit.payer,
+      referral: purchase.referral,
+      usdt_amount: purchase.paid_amount,
+      usd_amount: purchase.usd_amount,
+      asset_price: deposit.asset_price,
+      token_amount: purchase.token_amount,
+      bonus_amount: purchase.bonus_amount,
+      avg_price,
+      referral_amount: deposit.referral_amount,
+      referral_usd: deposit.referral_usd,
+      referral_token_amount: deposit.referral_token_amount,
+      upline_amount: deposit.upline_amount,
+      shard: deposit.shard,
+      timestamp: purchase.timestamp,
+    }),
+  }
+
+  Ok(())
+}
+
+// Adds the deposit to the beneficiary's per-round history and, when the
+// buyer passed one, writes its receipt.
+pub fn record_purchase(
+  purchase_history: &mut PurchaseHistory,
+  purchase_receipt: Option<&mut Account<PurchaseReceipt>>,
+  owner: Pubkey,
+  round: i16,
+  mut purchase: Purchase,
+) -> Result<()> {
+  if purchase.referral == EMPTY_REFERRAL_KEY {
+    purchase.referral = Pubkey::default();
+  }
+
+  let index = purchase_history.set_purchase(owner, round, &purchase)?;
+  if let Some(purchase_receipt) = purchase_receipt {
+    purchase_receipt.init(owner, round, index, purchase)?;
+  }
+
+  Ok(())
+}
+
+// Returns the referrer's cut in the deposited asset, in sale tokens, and the
+// USD value of the former.
 pub fn get_reward(
-  sale: &mut Account<Sale>,
+  sale: &mut Sale,
   ref_key: Pubkey,
-  referral: &mut Account<Referral>,
+  referral: &mut Referral,
   amount: u64,
+  usd_amount: u128,
   token_amount: u128,
 )
-  -> Result<(u64, u128)>
+  -> Result<(u64, u128, u128)>
 {
-  if Pubkey::from_str(EMPTY_REFERRAL_KEY) == Ok(ref_key){
-    return Ok((0, 0));
+  if ref_key == EMPTY_REFERRAL_KEY{
+    return Ok((0, 0, 0));
   };
 
   let (sale_main_reward, sale_secondary_reward) = sale.get_reward();
   let (ref_main_reward, ref_secondary_reward) = referral.get_reward();
 
+  // Volume tiers, when configured, replace the sale-wide main reward.
+  let (referred_usd, _) = referral.get_referred();
+  let sale_main_reward = match sale.get_tier(referred_usd) {
+    Some((_, tier_reward)) => tier_reward,
+    None => sale_main_reward,
+  };
+
   let main_reward = u64::max(sale_main_reward, ref_main_reward);
   let secondary_reward = u64::max(sale_secondary_reward, ref_secondary_reward);
 
-  let sol_reward_amount = amount * main_reward / 10u64.pow(PRECISION);
-  let token_reward_amount = token_amount * u128::from(secondary_reward) / 10u128.pow(PRECISION);
+  let one = math::pow10(PRECISION)?;
+  let cash_share = u128::from(sale.get_cash_share(referral.get_reward_mode()));
+  let cash_reward = math::mul_div_floor(u128::from(main_reward), cash_share, one)?;
+  let token_main_reward = math::sub(u128::from(main_reward), cash_reward)?;
+
+  let sol_reward_amount = math::to_u64(math::mul_div_floor(u128::from(amount), cash_reward, one)?)?;
+  let token_reward_amount = math::add(
+    math::mul_div_floor(token_amount, u128::from(secondary_reward), one)?,
+    math::mul_div_floor(token_amount, token_main_reward, one)?,
+  )?;
+  let reward_usd = math::mul_div_floor(usd_amount, cash_reward, one)?;
 
-  Ok((sol_reward_amount, token_reward_amount))
+  spend_reward(sale, referral, sol_reward_amount, reward_usd, token_reward_amount)
+}
+
+// Clamps a referral payout to the referrer's lifetime cap and the sale-wide
+// budget, scaling the paid asset by the share of the USD reward still
+// allowed, and books what is left on both.
+pub fn spend_reward(
+  sale: &mut Sale,
+  referral: &mut Referral,
+  reward_amount: u64,
+  reward_usd: u128,
+  token_reward_amount: u128,
+) -> Result<(u64, u128, u128)> {
+  let mut reward_amount = reward_amount;
+  let allowed_usd = u128::min(reward_usd, sale.get_remaining_reward_usd(referral.get_reward_usd()));
+  if allowed_usd < reward_usd {
+    reward_amount = math::to_u64(math::mul_div_floor(u128::from(reward_amount), allowed_usd, reward_usd)?)?;
+  }
+  let token_reward_amount = u128::min(token_reward_amount, sale.get_remaining_reward_tokens());
+
+  sale.set_referral_spent(allowed_usd, token_reward_amount)?;
+  referral.set_reward_usd(allowed_usd)?;
+
+  Ok((reward_amount, token_reward_amount, allowed_usd))
 }
 
 #[derive(Accounts)]
@@ -377,11 +1039,11 @@ pub struct InitSale<'info> {
   #[account(
     init,
     payer = payer,
-    space = 680,
+    space = 8 + std::mem::size_of::<Sale>(),
     seeds = [],
     bump,
   )]
-  pub sale: Account<'info, Sale>,
+  pub sale: AccountLoader<'info, Sale>,
   #[account(mut)]
   pub payer: Signer<'info>,
   pub system_program: Program<'info, System>,
@@ -391,7 +1053,7 @@ pub struct InitSale<'info> {
 #[instruction(max_investment: u64, min_investment: u64)]
 pub struct SetSaleInvestment<'info> {
   #[account(mut)]
-  pub sale: Account<'info, Sale>,
+  pub sale: AccountLoader<'info, Sale>,
   #[account(mut)]
   pub payer: Signer<'info>,
 }
@@ -400,7 +1062,79 @@ pub struct SetSaleInvestment<'info> {
 #[instruction(main_reward: u64, secondary_reward: u64)]
 pub struct SetSaleReward<'info> {
   #[account(mut)]
-  pub sale: Account<'info, Sale>,
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(referral_depth: u8, upline_rewards: [u64; MAX_UPLINE_LEVELS])]
+pub struct SetSaleRefLevels<'info> {
+  #[account(mut)]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(claim_start: i64, vesting_duration: i64)]
+pub struct SetSaleClaim<'info> {
+  #[account(mut)]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(
+    constraint = token_mint.decimals == PRECISION as u8 @ errors::Sale::InvalidMint,
+  )]
+  pub token_mint: Account<'info, Mint>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(bind_referrer: bool, min_referral_usd: u64)]
+pub struct SetSaleReferralRules<'info> {
+  #[account(mut)]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetSaleRefBudget<'info> {
+  #[account(mut)]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetSaleRewardMode<'info> {
+  #[account(mut)]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetSaleBuyerBonus<'info> {
+  #[account(mut)]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetSaleReferralProgram<'info> {
+  #[account(mut)]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(tiers: Vec<RewardTier>)]
+pub struct SetSaleRefTiers<'info> {
+  #[account(mut)]
+  pub sale: AccountLoader<'info, Sale>,
   #[account(mut)]
   pub payer: Signer<'info>,
 }
@@ -408,7 +1142,7 @@ pub struct SetSaleReward<'info> {
 #[derive(Accounts)]
 pub struct SetSaleOpened<'info> {
   #[account(mut)]
-  pub sale: Account<'info, Sale>,
+  pub sale: AccountLoader<'info, Sale>,
   #[account(mut)]
   pub payer: Signer<'info>,
 }
@@ -416,25 +1150,85 @@ pub struct SetSaleOpened<'info> {
 #[derive(Accounts)]
 pub struct SetSaleClosed<'info> {
   #[account(mut)]
-  pub sale: Account<'info, Sale>,
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSaleAccount<'info> {
+  #[account(
+    mut,
+    seeds = [],
+    bump,
+    close = payer,
+  )]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(
+    constraint = vault.mint == sale.load()?.get_token_mint() @ errors::Sale::InvalidMint,
+    constraint = vault.owner == sale.key() @ errors::Sale::InvalidOwner,
+  )]
+  pub vault: Option<Account<'info, TokenAccount>>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseBeneficiary<'info> {
+  #[account(
+    mut,
+    seeds = [
+      BENEFICIARY_TAG,
+      b"_",
+      payer.key().as_ref()
+    ],
+    bump,
+    close = payer,
+  )]
+  pub beneficiary: Account<'info, Beneficiary>,
+  #[account(
+    seeds = [],
+    bump,
+  )]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(scope: u8, reason: u8)]
+pub struct SetSalePaused<'info> {
+  #[account(mut)]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(scope: u8)]
+pub struct SetSaleUnpaused<'info> {
+  #[account(mut)]
+  pub sale: AccountLoader<'info, Sale>,
   #[account(mut)]
   pub payer: Signer<'info>,
 }
 
 pub const BENEFICIARY_TAG: &[u8] = b"BENEFICIARY";
+pub const PURCHASE_HISTORY_TAG: &[u8] = b"PURCHASE_HISTORY";
+pub const PURCHASE_RECEIPT_TAG: &[u8] = b"PURCHASE_RECEIPT";
 #[derive(Accounts)]
 #[instruction(ref_key: Pubkey, amount: u64)]
 pub struct Deposit<'info> {
   #[account(mut)]
-  pub sale: Account<'info, Sale>,
+  pub sale: AccountLoader<'info, Sale>,
   #[account(mut)]
   pub payer: Signer<'info>,
   #[account(mut)]
-  pub round: Account<'info, Round>,
+  pub round: AccountLoader<'info, Round>,
   #[account(
     init_if_needed,
     payer = payer,
-    space = 680,
+    space = 8 + Beneficiary::INIT_SPACE,
     seeds = [
       BENEFICIARY_TAG,
       b"_",
@@ -446,7 +1240,7 @@ pub struct Deposit<'info> {
   #[account(
     init_if_needed,
     payer = payer,
-    space = 680,
+    space = 8 + Referral::INIT_SPACE,
     seeds = [
       REFERRAL_TAG,
       b"_",
@@ -455,6 +1249,34 @@ pub struct Deposit<'info> {
     bump
   )]
   pub referral: Account<'info, Referral>,
+  #[account(
+    init_if_needed,
+    payer = payer,
+    space = 8 + PurchaseHistory::INIT_SPACE,
+    seeds = [
+      PURCHASE_HISTORY_TAG,
+      b"_",
+      payer.key().as_ref(),
+      b"_",
+      round.key().as_ref()
+    ],
+    bump
+  )]
+  pub purchase_history: Box<Account<'info, PurchaseHistory>>,
+  #[account(
+    init,
+    payer = payer,
+    space = 8 + PurchaseReceipt::INIT_SPACE,
+    seeds = [
+      PURCHASE_RECEIPT_TAG,
+      b"_",
+      purchase_history.key().as_ref(),
+      b"_",
+      &purchase_history.get_purchase_count().to_le_bytes()
+    ],
+    bump
+  )]
+  pub purchase_receipt: Option<Box<Account<'info, PurchaseReceipt>>>,
   /// CHECK : We will manually check this against the Pubkey of the price feed
   pub price_info : AccountInfo<'info>,
   /// CHECK : We will manually check this against the Pubkey of the treasury
@@ -467,15 +1289,15 @@ pub struct Deposit<'info> {
 #[instruction(ref_key: Pubkey, amount: u64)]
 pub struct DepositUSDC<'info> {
   #[account(mut)]
-  pub sale: Account<'info, Sale>,
+  pub sale: AccountLoader<'info, Sale>,
   #[account(mut)]
   pub payer: Signer<'info>,
   #[account(mut)]
-  pub round: Account<'info, Round>,
+  pub round: AccountLoader<'info, Round>,
   #[account(
     init_if_needed,
     payer = payer,
-    space = 680,
+    space = 8 + Beneficiary::INIT_SPACE,
     seeds = [
       BENEFICIARY_TAG,
       b"_",
@@ -487,7 +1309,7 @@ pub struct DepositUSDC<'info> {
   #[account(
     init_if_needed,
     payer = payer,
-    space = 680,
+    space = 8 + Referral::INIT_SPACE,
     seeds = [
       REFERRAL_TAG,
       b"_",
@@ -496,22 +1318,50 @@ pub struct DepositUSDC<'info> {
     bump
   )]
   pub referral: Account<'info, Referral>,
+  #[account(
+    init_if_needed,
+    payer = payer,
+    space = 8 + PurchaseHistory::INIT_SPACE,
+    seeds = [
+      PURCHASE_HISTORY_TAG,
+      b"_",
+      payer.key().as_ref(),
+      b"_",
+      round.key().as_ref()
+    ],
+    bump
+  )]
+  pub purchase_history: Box<Account<'info, PurchaseHistory>>,
+  #[account(
+    init,
+    payer = payer,
+    space = 8 + PurchaseReceipt::INIT_SPACE,
+    seeds = [
+      PURCHASE_RECEIPT_TAG,
+      b"_",
+      purchase_history.key().as_ref(),
+      b"_",
+      &purchase_history.get_purchase_count().to_le_bytes()
+    ],
+    bump
+  )]
+  pub purchase_receipt: Option<Box<Account<'info, PurchaseReceipt>>>,
   #[account(
     mut,
-    constraint = beneficiary_ata.mint == USDC.parse::<Pubkey>().unwrap(),
-    constraint = beneficiary_ata.owner == payer.key(),
+    constraint = USDC.parse::<Pubkey>() == Ok(beneficiary_ata.mint) @ errors::Sale::InvalidMint,
+    constraint = beneficiary_ata.owner == payer.key() @ errors::Sale::InvalidOwner,
   )]
   pub beneficiary_ata: Account<'info, TokenAccount>,
   #[account(
     mut,
-    constraint = treasury_ata.mint == USDC.parse::<Pubkey>().unwrap(),
-    constraint = treasury_ata.owner == TREASURY.parse::<Pubkey>().unwrap(),
+    constraint = USDC.parse::<Pubkey>() == Ok(treasury_ata.mint) @ errors::Sale::InvalidMint,
+    constraint = TREASURY.parse::<Pubkey>() == Ok(treasury_ata.owner) @ errors::Sale::WrongTreasury,
   )]
   pub treasury_ata: Account<'info, TokenAccount>,
   #[account(
     mut,
-    constraint = referral_pda_ata.mint == USDC.parse::<Pubkey>().unwrap(),
-    constraint = referral_pda_ata.owner == referral.key(),
+    constraint = USDC.parse::<Pubkey>() == Ok(referral_pda_ata.mint) @ errors::Sale::InvalidMint,
+    constraint = referral_pda_ata.owner == referral.key() @ errors::Sale::InvalidOwner,
   )]
   pub referral_pda_ata: Account<'info, TokenAccount>,
   pub token_program: Program<'info, Token>,
@@ -522,15 +1372,15 @@ pub struct DepositUSDC<'info> {
 #[instruction(ref_key: Pubkey, amount: u64)]
 pub struct DepositUSDT<'info> {
   #[account(mut)]
-  pub sale: Account<'info, Sale>,
+  pub sale: AccountLoader<'info, Sale>,
   #[account(mut)]
   pub payer: Signer<'info>,
   #[account(mut)]
-  pub round: Account<'info, Round>,
+  pub round: AccountLoader<'info, Round>,
   #[account(
     init_if_needed,
     payer = payer,
-    space = 680,
+    space = 8 + Beneficiary::INIT_SPACE,
     seeds = [
       BENEFICIARY_TAG,
       b"_",
@@ -542,7 +1392,7 @@ pub struct DepositUSDT<'info> {
   #[account(
     init_if_needed,
     payer = payer,
-    space = 680,
+    space = 8 + Referral::INIT_SPACE,
     seeds = [
       REFERRAL_TAG,
       b"_",
@@ -551,24 +1401,157 @@ pub struct DepositUSDT<'info> {
     bump
   )]
   pub referral: Account<'info, Referral>,
+  #[account(
+    init_if_needed,
+    payer = payer,
+    space = 8 + PurchaseHistory::INIT_SPACE,
+    seeds = [
+      PURCHASE_HISTORY_TAG,
+      b"_",
+      payer.key().as_ref(),
+      b"_",
+      round.key().as_ref()
+    ],
+    bump
+  )]
+  pub purchase_history: Box<Account<'info, PurchaseHistory>>,
+  #[account(
+    init,
+    payer = payer,
+    space = 8 + PurchaseReceipt::INIT_SPACE,
+    seeds = [
+      PURCHASE_RECEIPT_TAG,
+      b"_",
+      purchase_history.key().as_ref(),
+      b"_",
+      &purchase_history.get_purchase_count().to_le_bytes()
+    ],
+    bump
+  )]
+  pub purchase_receipt: Option<Box<Account<'info, PurchaseReceipt>>>,
   #[account(
     mut,
-    constraint = beneficiary_ata.mint == USDT.parse::<Pubkey>().unwrap(),
-    constraint = beneficiary_ata.owner == payer.key(),
+    constraint = USDT.parse::<Pubkey>() == Ok(beneficiary_ata.mint) @ errors::Sale::InvalidMint,
+    constraint = beneficiary_ata.owner == payer.key() @ errors::Sale::InvalidOwner,
   )]
   pub beneficiary_ata: Account<'info, TokenAccount>,
   #[account(
     mut,
-    constraint = treasury_ata.mint == USDT.parse::<Pubkey>().unwrap(),
-    constraint = treasury_ata.owner == TREASURY.parse::<Pubkey>().unwrap(),
+    constraint = USDT.parse::<Pubkey>() == Ok(treasury_ata.mint) @ errors::Sale::InvalidMint,
+    constraint = TREASURY.parse::<Pubkey>() == Ok(treasury_ata.owner) @ errors::Sale::WrongTreasury,
   )]
   pub treasury_ata: Account<'info, TokenAccount>,
   #[account(
     mut,
-    constraint = referral_pda_ata.mint == USDT.parse::<Pubkey>().unwrap(),
-    constraint = referral_pda_ata.owner == referral.key(),
+    constraint = USDT.parse::<Pubkey>() == Ok(referral_pda_ata.mint) @ errors::Sale::InvalidMint,
+    constraint = referral_pda_ata.owner == referral.key() @ errors::Sale::InvalidOwner,
   )]
   pub referral_pda_ata: Account<'info, TokenAccount>,
   pub token_program: Program<'info, Token>,
   pub system_program: Program<'info, System>,
 }
+
+#[derive(Accounts)]
+pub struct Claim<'info> {
+  #[account(
+    mut,
+    seeds = [],
+    bump,
+  )]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(
+    mut,
+    seeds = [
+      BENEFICIARY_TAG,
+      b"_",
+      payer.key().as_ref()
+    ],
+    bump
+  )]
+  pub beneficiary: Account<'info, Beneficiary>,
+  #[account(
+    mut,
+    constraint = vault.mint == sale.load()?.get_token_mint() @ errors::Sale::InvalidMint,
+    constraint = vault.owner == sale.key() @ errors::Sale::InvalidOwner,
+  )]
+  pub vault: Account<'info, TokenAccount>,
+  #[account(
+    mut,
+    constraint = beneficiary_token_ata.mint == sale.load()?.get_token_mint() @ errors::Sale::InvalidMint,
+    constraint = beneficiary_token_ata.owner == payer.key() @ errors::Sale::InvalidOwner,
+  )]
+  pub beneficiary_token_ata: Account<'info, TokenAccount>,
+  pub token_program: Program<'info, Token>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use anchor_lang::Discriminator;
+  use bytemuck::Zeroable;
+
+  #[test]
+  fn scheduled_round_goes_live_on_deposit() {
+    let mut sale = Sale::zeroed();
+    sale.set_round(-1).unwrap();
+    let mut round = Round::zeroed();
+    round.init(2, 1_000_000_000, 1_000_000).unwrap();
+    round.set_scheduled(100).unwrap();
+
+    assert_eq!(check_round(&mut sale, &mut round, 99).unwrap_err(), errors::Sale::RoundNotOpened.into());
+    assert_eq!(sale.get_round(), -1);
+
+    assert_eq!(check_round(&mut sale, &mut round, 100).unwrap(), Some(RoundState::Scheduled));
+    assert_eq!(sale.get_round(), 2);
+    assert_eq!(check_round(&mut sale, &mut round, 101).unwrap(), None);
+  }
+
+  #[test]
+  fn purchase_receipts_follow_the_history() {
+    let owner = Pubkey::new_unique();
+    let referral = Pubkey::new_unique();
+    let mut history = PurchaseHistory::deserialize(&mut &vec![0u8; PurchaseHistory::INIT_SPACE][..]).unwrap();
+    let mut purchase = Purchase {
+      asset: PaymentAsset::Usdc,
+      paid_amount: 10,
+      usd_amount: 10,
+      token_amount: 100,
+      bonus_amount: 0,
+      referral,
+      timestamp: 100,
+    };
+
+    let mut receipts = Vec::new();
+    for _ in 0..2 {
+      let mut data = PurchaseReceipt::DISCRIMINATOR.to_vec();
+      data.resize(8 + PurchaseReceipt::INIT_SPACE, 0);
+      let info = AccountInfo::new(
+        Box::leak(Box::new(Pubkey::new_unique())),
+        false,
+        true,
+        Box::leak(Box::new(0)),
+        Box::leak(data.into_boxed_slice()),
+        &crate::ID,
+        false,
+        0,
+      );
+      receipts.push(Account::<PurchaseReceipt>::try_from(Box::leak(Box::new(info))).unwrap());
+    }
+
+    record_purchase(&mut history, Some(&mut receipts[0]), owner, 1, purchase).unwrap();
+    purchase.referral = EMPTY_REFERRAL_KEY;
+    purchase.timestamp = 200;
+    record_purchase(&mut history, None, owner, 1, purchase).unwrap();
+    record_purchase(&mut history, Some(&mut receipts[1]), owner, 1, purchase).unwrap();
+
+    assert_eq!(receipts[0].get_index(), 0);
+    assert_eq!(receipts[0].get_purchase().referral, referral);
+    assert_eq!(receipts[1].get_index(), 2);
+    assert_eq!(receipts[1].get_purchase().referral, Pubkey::default());
+    assert_eq!(history.get_last_referral(), referral);
+    assert_eq!(history.get_purchase_times(), (100, 200));
+    assert_eq!(history.get_totals(PaymentAsset::Usdc).purchase_count, 3);
+  }
+}
@@ -0,0 +1,663 @@
+use anchor_lang::{
+  prelude::*,
+  solana_program::{ program::invoke, system_instruction::transfer },
+};
+use anchor_spl::token::{ self, Token, TokenAccount, Transfer as SplTransfer };
+use std::str::FromStr;
+
+use crate::errors;
+use crate::events;
+use crate::math;
+use crate::state::sale::{ Sale, PAUSE_DEPOSIT };
+use crate::state::round::{ Round, PriceMode };
+use crate::state::shard::Shard;
+use crate::state::referral::Referral;
+use crate::state::beneficiary::Beneficiary;
+use crate::state::purchase::{ PurchaseHistory, PurchaseReceipt, Purchase, PaymentAsset };
+use crate::auth::{ SOL_USD_PRICEFEED, TREASURY, USDC, USDT, PRECISION, STABLE_PRECISION };
+use crate::referral::{ REFERRAL_TAG, check_referral, resolve_referral, get_uplines, set_upline_rewards, get_upline_reward_amount };
+use crate::round::{ ROUND_TAG, emit_state_changed, emit_round_config };
+use crate::instructions::sale::{ BENEFICIARY_TAG, PURCHASE_HISTORY_TAG, PURCHASE_RECEIPT_TAG, DepositRecord, get_price, get_purchase, get_reward, record_deposit };
+
+pub fn initialize_shard(
+  ctx: Context<InitShard>,
+  round_id: i16,
+  index: u8,
+) -> Result<()> {
+  let round = &ctx.accounts.round.load()?;
+  if round.get_id() != round_id || index >= round.get_shard_count() {
+    return err!(errors::Sale::ShardInvalid);
+  }
+
+  let shard = &mut ctx.accounts.shard.load_init()?;
+  shard.init(round_id, index)?;
+
+  emit!(events::ShardInitializedEvent {
+    version: events::EVENT_VERSION,
+    round: round_id,
+    shard: index,
+    timestamp: Clock::get()?.unix_timestamp,
+  });
+
+  Ok(())
+}
+
+pub fn set_round_shards(
+  ctx: Context<SetRoundShards>,
+  shard_count: u8,
+) -> Result<()> {
+  let round = &mut ctx.accounts.round.load_mut()?;
+  round.set_shard_count(shard_count)?;
+
+  emit_round_config(round, events::RoundAction::ShardsChanged)
+}
+
+// Rolls every shard passed in remaining accounts up into the sale and the
+// round, then re-grants each one a slice of the unreserved supply while the
+// round is still the open, active one.
+pub fn settle_shards<'info>(
+  ctx: Context<'_, '_, 'info, 'info, SettleShards<'info>>,
+) -> Result<()> {
+  let sale = &mut ctx.accounts.sale.load_mut()?;
+  let round = &mut ctx.accounts.round.load_mut()?;
+
+  if ctx.remaining_accounts.is_empty() {
+    return err!(errors::Sale::ShardInvalid);
+  }
+
+  let now = Clock::get()?.unix_timestamp;
+  let active = sale.get_round() == round.get_id();
+  for shard_info in ctx.remaining_accounts.iter() {
+    if !shard_info.is_writable {
+      return err!(errors::Sale::ShardInvalid);
+    }
+
+    let loader = AccountLoader::<Shard>::try_from(shard_info)?;
+    let shard = &mut loader.load_mut()?;
+    if shard.get_round() != round.get_id() {
+      return err!(errors::Sale::ShardInvalid);
+    }
+
+    emit!(settle_shard(sale, round, shard, active, now)?);
+  }
+
+  if let Some(from) = round.update_sold_out()? {
+    emit_state_changed(round, from)?;
+  }
+
+  Ok(())
+}
+
+// Rolls one shard up into the sale and the round, releasing its capacity,
+// and re-grants it a slice of the unreserved supply when `active`.
+pub fn settle_shard(
+  sale: &mut Sale,
+  round: &mut Round,
+  shard: &mut Shard,
+  active: bool,
+  now: i64,
+) -> Result<events::ShardSettledEvent> {
+  let (sold_amount, released, spent_usd, spent_tokens) = shard.settle()?;
+  sale.set_total_sold(sold_amount)?;
+  sale.set_referral_spent(spent_usd, spent_tokens)?;
+  round.set_total_sold(sold_amount)?;
+  round.set_shard_reserved(released, 0)?;
+
+  let capacity = match active && shard.get_index() < round.get_shard_count() {
+    true => round.get_shard_grant()?,
+    false => 0,
+  };
+  round.set_shard_reserved(0, capacity)?;
+  shard.set_capacity(capacity)?;
+
+  Ok(events::ShardSettledEvent {
+    version: events::EVENT_VERSION,
+    round: round.get_id(),
+    shard: shard.get_index(),
+    sold_amount,
+    released,
+    capacity,
+    spent_usd,
+    spent_tokens,
+    timestamp: now,
+  })
+}
+
+pub fn deposit_sharded<'info>(
+  ctx: Context<'_, '_, 'info, 'info, DepositSharded<'info>>,
+  ref_key: Pubkey,
+  amount: u64,
+) -> Result<()> {
+  let to_account_infos = &mut ctx.accounts.to_account_infos();
+  let payer = &mut ctx.accounts.payer;
+  let sale = &ctx.accounts.sale.load()?;
+  let round = &ctx.accounts.round.load()?;
+  let shard = &mut ctx.accounts.shard.load_mut()?;
+  let beneficiary = &mut ctx.accounts.beneficiary;
+  let referral = &mut ctx.accounts.referral;
+  let purchase_history = &mut ctx.accounts.purchase_history;
+  let purchase_receipt = &mut ctx.accounts.purchase_receipt;
+  let price_info = &ctx.accounts.price_info;
+  let treasury_info = &mut ctx.accounts.treasury_info;
+  let system_program = &ctx.accounts.system_program;
+
+  check_shard(sale, round, shard)?;
+
+  let requested_key = ref_key;
+  let ref_key = resolve_referral(sale, referral, ref_key)?;
+
+  if Pubkey::from_str(TREASURY) != Ok(treasury_info.key()){
+    return Err(error!(errors::Sale::WrongTreasury))
+  };
+
+  if Pubkey::from_str(SOL_USD_PRICEFEED) != Ok(price_info.key()){
+    return Err(error!(errors::Sale::WrongPriceFeedId))
+  };
+
+  let now = Clock::get()?.unix_timestamp;
+  let (price, expo) = get_price(price_info)?;
+  let usd_amount = math::mul_div_floor(u128::from(amount), price, math::pow10(expo)?)?;
+  let asset_price = math::to_u64(math::mul_div_floor(price, math::pow10(PRECISION)?, math::pow10(expo)?)?)?;
+  let (token_amount, bonus_amount) = get_purchase(sale, round, ref_key, referral, usd_amount, now)?;
+  let credited_amount = math::add(token_amount, bonus_amount)?;
+
+  if credited_amount > shard.get_available() {
+    return err!(errors::Sale::ShardCapacityExceeded);
+  }
+
+  let new_buyer = check_referral(sale, beneficiary, payer.key(), requested_key, ref_key, usd_amount)?;
+
+  let mut ledger = **sale;
+  let (sol_reward_amount, token_reward_amount, referral_usd) = get_reward(&mut ledger, ref_key, referral, amount, usd_amount, token_amount)?;
+  let mut uplines = get_uplines(sale, payer.key(), ref_key, referral, ctx.remaining_accounts, None)?;
+  set_upline_rewards(&mut ledger, &mut uplines, amount, usd_amount, token_amount)?;
+  set_shard_spent(sale, &ledger, shard)?;
+  let upline_reward_amount = get_upline_reward_amount(&uplines)?;
+  let reward_amount = math::add(u128::from(sol_reward_amount), u128::from(upline_reward_amount))?;
+  let to_amount = math::to_u64(math::sub(u128::from(amount), reward_amount)?)?;
+
+  let instruction = &transfer(&payer.key(), &treasury_info.key(), to_amount);
+  invoke(instruction, to_account_infos).map_err(|_| error!(errors::Sale::TransferFailed))?;
+
+  if sol_reward_amount > 0 {
+    let instruction = &transfer(&payer.key(), &referral.key(), sol_reward_amount);
+    invoke(instruction, to_account_infos).map_err(|_| error!(errors::Sale::TransferFailed))?;
+  }
+
+  for upline in uplines.iter() {
+    if upline.reward_amount > 0 {
+      let instruction = &transfer(&payer.key(), &upline.referral.key(), upline.reward_amount);
+      let account_infos = &[payer.to_account_info(), upline.referral.to_account_info(), system_program.to_account_info()];
+      invoke(instruction, account_infos).map_err(|_| error!(errors::Sale::TransferFailed))?;
+    }
+  }
+
+  // Updating shard details
+  shard.set_sold(credited_amount, usd_amount)?;
+
+  record_deposit(sale, beneficiary, purchase_history, purchase_receipt.as_deref_mut(), referral, &mut uplines, &DepositRecord {
+    payer: payer.key(),
+    round: round.get_id(),
+    shard: Some(shard.get_index()),
+    purchase: Purchase {
+      asset: PaymentAsset::Sol,
+      paid_amount: amount,
+      usd_amount,
+      token_amount,
+      bonus_amount,
+      referral: ref_key,
+      timestamp: now,
+    },
+    asset_price,
+    new_buyer,
+    referral_amount: sol_reward_amount,
+    referral_usd,
+    referral_token_amount: token_reward_amount,
+    upline_amount: upline_reward_amount,
+  })?;
+
+  Ok(())
+}
+
+// USDC and USDT only differ by mint, so one instruction serves both.
+pub fn deposit_sharded_stable<'info>(
+  ctx: Context<'_, '_, 'info, 'info, DepositShardedStable<'info>>,
+  ref_key: Pubkey,
+  amount: u64,
+) -> Result<()> {
+  let payer = &mut ctx.accounts.payer;
+  let sale = &ctx.accounts.sale.load()?;
+  let round = &ctx.accounts.round.load()?;
+  let shard = &mut ctx.accounts.shard.load_mut()?;
+  let beneficiary = &mut ctx.accounts.beneficiary;
+  let referral = &mut ctx.accounts.referral;
+  let purchase_history = &mut ctx.accounts.purchase_history;
+  let purchase_receipt = &mut ctx.accounts.purchase_receipt;
+
+  let beneficiary_ata = &ctx.accounts.beneficiary_ata;
+  let treasury_ata = &ctx.accounts.treasury_ata;
+  let referral_pda_ata = &ctx.accounts.referral_pda_ata;
+  let token_program = &ctx.accounts.token_program;
+  let is_usdc = USDC.parse::<Pubkey>() == Ok(beneficiary_ata.mint);
+
+  check_shard(sale, round, shard)?;
+
+  let requested_key = ref_key;
+  let ref_key = resolve_referral(sale, referral, ref_key)?;
+
+  let now = Clock::get()?.unix_timestamp;
+  let usd_amount = math::mul(u128::from(amount), math::pow10(STABLE_PRECISION)?)?;
+  let asset_price = math::to_u64(math::pow10(PRECISION)?)?;
+  let (token_amount, bonus_amount) = get_purchase(sale, round, ref_key, referral, usd_amount, now)?;
+  let credited_amount = math::add(token_amount, bonus_amount)?;
+
+  if credited_amount > shard.get_available() {
+    return err!(errors::Sale::ShardCapacityExceeded);
+  }
+
+  let new_buyer = check_referral(sale, beneficiary, payer.key(), requested_key, ref_key, usd_amount)?;
+
+  let mut ledger = **sale;
+  let (stable_reward_amount, token_reward_amount, referral_usd) = get_reward(&mut ledger, ref_key, referral, amount, usd_amount, token_amount)?;
+  let mut uplines = get_uplines(sale, payer.key(), ref_key, referral, ctx.remaining_accounts, Some(beneficiary_ata.mint))?;
+  set_upline_rewards(&mut ledger, &mut uplines, amount, usd_amount, token_amount)?;
+  set_shard_spent(sale, &ledger, shard)?;
+  let upline_reward_amount = get_upline_reward_amount(&uplines)?;
+  let reward_amount = math::add(u128::from(stable_reward_amount), u128::from(upline_reward_amount))?;
+  let to_amount = math::to_u64(math::sub(u128::from(amount), reward_amount)?)?;
+
+  let cpi_accounts = SplTransfer {
+    from: beneficiary_ata.to_account_info(),
+    to: treasury_ata.to_account_info(),
+    authority: payer.to_account_info(),
+  };
+  let cpi_program = token_program.to_account_info();
+  token::transfer(CpiContext::new(cpi_program, cpi_accounts), to_amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
+
+  if stable_reward_amount > 0 {
+    let cpi_accounts = SplTransfer {
+      from: beneficiary_ata.to_account_info(),
+      to: referral_pda_ata.to_account_info(),
+      authority: payer.to_account_info(),
+    };
+    let cpi_program = token_program.to_account_info();
+    token::transfer(CpiContext::new(cpi_program, cpi_accounts), stable_reward_amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
+  }
+
+  for upline in uplines.iter() {
+    if upline.reward_amount == 0 {
+      continue;
+    }
+
+    if let Some(upline_ata) = &upline.referral_ata {
+      let cpi_accounts = SplTransfer {
+        from: beneficiary_ata.to_account_info(),
+        to: upline_ata.to_account_info(),
+        authority: payer.to_account_info(),
+      };
+      let cpi_program = token_program.to_account_info();
+      token::transfer(CpiContext::new(cpi_program, cpi_accounts), upline.reward_amount).map_err(|_| error!(errors::Sale::TransferFailed))?;
+    }
+  }
+
+  // Updating shard details
+  shard.set_sold(credited_amount, usd_amount)?;
+
+  record_deposit(sale, beneficiary, purchase_history, purchase_receipt.as_deref_mut(), referral, &mut uplines, &DepositRecord {
+    payer: payer.key(),
+    round: round.get_id(),
+    shard: Some(shard.get_index()),
+    purchase: Purchase {
+      asset: match is_usdc {
+        true => PaymentAsset::Usdc,
+        false => PaymentAsset::Usdt,
+      },
+      paid_amount: amount,
+      usd_amount,
+      token_amount,
+      bonus_amount,
+      referral: ref_key,
+      timestamp: now,
+    },
+    asset_price,
+    new_buyer,
+    referral_amount: stable_reward_amount,
+    referral_usd,
+    referral_token_amount: token_reward_amount,
+    upline_amount: upline_reward_amount,
+  })?;
+
+  Ok(())
+}
+
+// Sharded deposits never write the sale or round, so anything that has to
+// update them per purchase is left to the regular deposit path: scheduled
+// rounds are not activated here, and only fixed-price rounds without a
+// sale-wide referral budget can be sharded.
+pub fn check_shard(
+  sale: &Sale,
+  round: &Round,
+  shard: &Shard,
+) -> Result<()> {
+  if !sale.is_open() {
+    return err!(errors::Sale::SaleNotOpened);
+  }
+
+  if sale.is_paused(PAUSE_DEPOSIT) {
+    return err!(errors::Sale::DepositsPaused);
+  }
+
+  if !round.is_open() {
+    return err!(errors::Sale::RoundNotOpened);
+  }
+
+  if sale.get_round() != round.get_id() {
+    return err!(errors::Sale::InactiveRound);
+  }
+
+  if shard.get_round() != round.get_id() || shard.get_index() >= round.get_shard_count() {
+    return err!(errors::Sale::ShardInvalid);
+  }
+
+  if round.get_price_mode() != PriceMode::Fixed || sale.has_referral_budget() {
+    return err!(errors::Sale::ShardingUnsupported);
+  }
+
+  Ok(())
+}
+
+// Sharded deposits only read the sale, so rewards are booked on a copy of
+// it and the difference lands on the shard until `settle_shards`.
+fn set_shard_spent(
+  sale: &Sale,
+  ledger: &Sale,
+  shard: &mut Shard,
+) -> Result<()> {
+  let (spent_usd, spent_tokens) = sale.get_referral_spent();
+  let (ledger_usd, ledger_tokens) = ledger.get_referral_spent();
+
+  shard.set_referral_spent(math::sub(ledger_usd, spent_usd)?, math::sub(ledger_tokens, spent_tokens)?)
+}
+
+pub const SHARD_TAG: &[u8] = b"SHARD";
+
+#[derive(Accounts)]
+#[instruction(round_id: i16, index: u8)]
+pub struct InitShard<'info> {
+  #[account(
+    seeds = [
+      ROUND_TAG,
+      b"_",
+      &round_id.to_le_bytes()
+    ],
+    bump,
+  )]
+  pub round: AccountLoader<'info, Round>,
+  #[account(
+    init,
+    payer = payer,
+    space = 8 + std::mem::size_of::<Shard>(),
+    seeds = [
+      SHARD_TAG,
+      b"_",
+      &round_id.to_le_bytes(),
+      b"_",
+      &[index]
+    ],
+    bump,
+  )]
+  pub shard: AccountLoader<'info, Shard>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+  pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(shard_count: u8)]
+pub struct SetRoundShards<'info> {
+  #[account(mut)]
+  pub round: AccountLoader<'info, Round>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SettleShards<'info> {
+  #[account(
+    mut,
+    seeds = [],
+    bump,
+  )]
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub round: AccountLoader<'info, Round>,
+  pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(ref_key: Pubkey, amount: u64)]
+pub struct DepositSharded<'info> {
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+  pub round: AccountLoader<'info, Round>,
+  #[account(mut)]
+  pub shard: AccountLoader<'info, Shard>,
+  #[account(
+    init_if_needed,
+    payer = payer,
+    space = 8 + Beneficiary::INIT_SPACE,
+    seeds = [
+      BENEFICIARY_TAG,
+      b"_",
+      payer.key().as_ref()
+    ],
+    bump
+  )]
+  pub beneficiary: Account<'info, Beneficiary>,
+  #[account(
+    init_if_needed,
+    payer = payer,
+    space = 8 + Referral::INIT_SPACE,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      ref_key.key().as_ref()
+    ],
+    bump
+  )]
+  pub referral: Account<'info, Referral>,
+  #[account(
+    init_if_needed,
+    payer = payer,
+    space = 8 + PurchaseHistory::INIT_SPACE,
+    seeds = [
+      PURCHASE_HISTORY_TAG,
+      b"_",
+      payer.key().as_ref(),
+      b"_",
+      round.key().as_ref()
+    ],
+    bump
+  )]
+  pub purchase_history: Box<Account<'info, PurchaseHistory>>,
+  #[account(
+    init,
+    payer = payer,
+    space = 8 + PurchaseReceipt::INIT_SPACE,
+    seeds = [
+      PURCHASE_RECEIPT_TAG,
+      b"_",
+      purchase_history.key().as_ref(),
+      b"_",
+      &purchase_history.get_purchase_count().to_le_bytes()
+    ],
+    bump
+  )]
+  pub purchase_receipt: Option<Box<Account<'info, PurchaseReceipt>>>,
+  /// CHECK : We will manually check this against the Pubkey of the price feed
+  pub price_info : AccountInfo<'info>,
+  /// CHECK : We will manually check this against the Pubkey of the treasury
+  #[account(mut)]
+  pub treasury_info : AccountInfo<'info>,
+  pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(ref_key: Pubkey, amount: u64)]
+pub struct DepositShardedStable<'info> {
+  pub sale: AccountLoader<'info, Sale>,
+  #[account(mut)]
+  pub payer: Signer<'info>,
+  pub round: AccountLoader<'info, Round>,
+  #[account(mut)]
+  pub shard: AccountLoader<'info, Shard>,
+  #[account(
+    init_if_needed,
+    payer = payer,
+    space = 8 + Beneficiary::INIT_SPACE,
+    seeds = [
+      BENEFICIARY_TAG,
+      b"_",
+      payer.key().as_ref()
+    ],
+    bump
+  )]
+  pub beneficiary: Account<'info, Beneficiary>,
+  #[account(
+    init_if_needed,
+    payer = payer,
+    space = 8 + Referral::INIT_SPACE,
+    seeds = [
+      REFERRAL_TAG,
+      b"_",
+      ref_key.key().as_ref()
+    ],
+    bump
+  )]
+  pub referral: Account<'info, Referral>,
+  #[account(
+    init_if_needed,
+    payer = payer,
+    space = 8 + PurchaseHistory::INIT_SPACE,
+    seeds = [
+      PURCHASE_HISTORY_TAG,
+      b"_",
+      payer.key().as_ref(),
+      b"_",
+      round.key().as_ref()
+    ],
+    bump
+  )]
+  pub purchase_history: Box<Account<'info, PurchaseHistory>>,
+  #[account(
+    init,
+    payer = payer,
+    space = 8 + PurchaseReceipt::INIT_SPACE,
+    seeds = [
+      PURCHASE_RECEIPT_TAG,
+      b"_",
+      purchase_history.key().as_ref(),
+      b"_",
+      &purchase_history.get_purchase_count().to_le_bytes()
+    ],
+    bump
+  )]
+  pub purchase_receipt: Option<Box<Account<'info, PurchaseReceipt>>>,
+  #[account(
+    mut,
+    constraint = USDC.parse::<Pubkey>() == Ok(beneficiary_ata.mint) || USDT.parse::<Pubkey>() == Ok(beneficiary_ata.mint) @ errors::Sale::InvalidMint,
+    constraint = beneficiary_ata.owner == payer.key() @ errors::Sale::InvalidOwner,
+  )]
+  pub beneficiary_ata: Account<'info, TokenAccount>,
+  #[account(
+    mut,
+    constraint = treasury_ata.mint == beneficiary_ata.mint @ errors::Sale::InvalidMint,
+    constraint = TREASURY.parse::<Pubkey>() == Ok(treasury_ata.owner) @ errors::Sale::WrongTreasury,
+  )]
+  pub treasury_ata: Account<'info, TokenAccount>,
+  #[account(
+    mut,
+    constraint = referral_pda_ata.mint == beneficiary_ata.mint @ errors::Sale::InvalidMint,
+    constraint = referral_pda_ata.owner == referral.key() @ errors::Sale::InvalidOwner,
+  )]
+  pub referral_pda_ata: Account<'info, TokenAccount>,
+  pub token_program: Program<'info, Token>,
+  pub system_program: Program<'info, System>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bytemuck::Zeroable;
+
+  fn sharded_round(
+    supply: u128,
+    shard_count: u8,
+  ) -> (Sale, Round, Vec<Shard>) {
+    let mut sale = Sale::zeroed();
+    sale.set_round(1).unwrap();
+    let mut round = Round::zeroed();
+    round.init(1, 1_000_000_000, supply).unwrap();
+    round.set_shard_count(shard_count).unwrap();
+    round.set_open().unwrap();
+
+    let shards = (0..shard_count).map(|index| {
+      let mut shard = Shard::zeroed();
+      shard.init(1, index).unwrap();
+      shard
+    }).collect();
+
+    (sale, round, shards)
+  }
+
+  #[test]
+  fn settle_rolls_up_and_regrants() {
+    let (mut sale, mut round, mut shards) = sharded_round(1_000, 2);
+    let shard = &mut shards[0];
+
+    let settled = settle_shard(&mut sale, &mut round, shard, true, 0).unwrap();
+    assert_eq!((settled.sold_amount, settled.released, settled.capacity), (0, 0, 500));
+    assert_eq!(round.get_shard_reserved(), 500);
+
+    shard.set_sold(300, 300).unwrap();
+    shard.set_referral_spent(7, 11).unwrap();
+    let settled = settle_shard(&mut sale, &mut round, shard, true, 0).unwrap();
+    assert_eq!((settled.sold_amount, settled.released, settled.capacity), (300, 500, 350));
+    assert_eq!((settled.spent_usd, settled.spent_tokens), (7, 11));
+    assert_eq!(round.get_total_sold(), 300);
+    assert_eq!(round.get_shard_reserved(), 350);
+    assert_eq!(sale.get_referral_spent(), (7, 11));
+
+    // Once the round is no longer active the capacity is only released.
+    let settled = settle_shard(&mut sale, &mut round, shard, false, 0).unwrap();
+    assert_eq!((settled.released, settled.capacity), (350, 0));
+    assert_eq!(round.get_shard_reserved(), 0);
+    assert_eq!(shard.get_available(), 0);
+  }
+
+  #[test]
+  fn shards_never_sell_more_than_the_supply() {
+    let (mut sale, mut round, mut shards) = sharded_round(1_001, 3);
+
+    for _ in 0..10 {
+      for shard in shards.iter_mut() {
+        settle_shard(&mut sale, &mut round, shard, true, 0).unwrap();
+        let available = shard.get_available();
+        assert_eq!(shard.set_sold(available + 1, 0).unwrap_err(), errors::Sale::ShardCapacityExceeded.into());
+        shard.set_sold(available, 0).unwrap();
+      }
+
+      let pending: u128 = shards.iter().map(|shard| shard.get_totals().0).sum();
+      assert!(pending <= round.get_total_supply());
+      assert!(round.get_total_sold() + round.get_shard_reserved() <= round.get_total_supply());
+    }
+
+    for shard in shards.iter_mut() {
+      settle_shard(&mut sale, &mut round, shard, true, 0).unwrap();
+    }
+    let sold: u128 = shards.iter().map(|shard| shard.get_totals().0).sum();
+    assert_eq!(round.get_total_sold(), sold);
+    assert!(sold <= round.get_total_supply());
+  }
+}
@@ -3,6 +3,7 @@ use instructions::*;
 pub mod auth;
 pub mod errors;
 pub mod events;
+pub mod math;
 pub mod state;
 pub mod instructions;
 
@@ -42,6 +43,101 @@ pub mod sale {
     instructions::sale::set_sale_reward(ctx, main_reward, secondary_reward)
   }
 
+  pub fn set_sale_ref_levels(
+    ctx: Context<SetSaleRefLevels>,
+    referral_depth: u8,
+    upline_rewards: [u64; state::sale::MAX_UPLINE_LEVELS],
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::sale::set_sale_ref_levels(ctx, referral_depth, upline_rewards)
+  }
+
+  pub fn set_sale_ref_budget(
+    ctx: Context<SetSaleRefBudget>,
+    referrer_cap_usd: u64,
+    budget_usd: u64,
+    budget_tokens: u128,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::sale::set_sale_ref_budget(ctx, referrer_cap_usd, budget_usd, budget_tokens)
+  }
+
+  pub fn set_sale_reward_mode(
+    ctx: Context<SetSaleRewardMode>,
+    reward_mode: state::sale::RewardMode,
+    cash_share: u64,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::sale::set_sale_reward_mode(ctx, reward_mode, cash_share)
+  }
+
+  pub fn set_sale_buyer_bonus(
+    ctx: Context<SetSaleBuyerBonus>,
+    buyer_bonus: u64,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::sale::set_sale_buyer_bonus(ctx, buyer_bonus)
+  }
+
+  pub fn set_sale_referral_program(
+    ctx: Context<SetSaleReferralProgram>,
+    enabled: bool,
+    reject_disabled_referral: bool,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::sale::set_sale_referral_program(ctx, enabled, reject_disabled_referral)
+  }
+
+  pub fn set_sale_ref_tiers(
+    ctx: Context<SetSaleRefTiers>,
+    tiers: Vec<state::sale::RewardTier>,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::sale::set_sale_ref_tiers(ctx, tiers)
+  }
+
+  pub fn set_sale_referral_rules(
+    ctx: Context<SetSaleReferralRules>,
+    bind_referrer: bool,
+    min_referral_usd: u64,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::sale::set_sale_referral_rules(ctx, bind_referrer, min_referral_usd)
+  }
+
+  pub fn set_sale_claim(
+    ctx: Context<SetSaleClaim>,
+    claim_start: i64,
+    vesting_duration: i64,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::sale::set_sale_claim(ctx, claim_start, vesting_duration)
+  }
+
   pub fn open_sale(
     ctx: Context<SetSaleOpened>,
   ) -> Result<()> {
@@ -62,30 +158,75 @@ pub mod sale {
     instructions::sale::close_sale(ctx)
   }
 
-  pub fn deposit(
-    ctx: Context<Deposit>,
+  pub fn close_sale_account(
+    ctx: Context<CloseSaleAccount>,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::sale::close_sale_account(ctx)
+  }
+
+  pub fn pause(
+    ctx: Context<SetSalePaused>,
+    scope: u8,
+    reason: u8,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::sale::pause_sale(ctx, scope, reason)
+  }
+
+  pub fn unpause(
+    ctx: Context<SetSaleUnpaused>,
+    scope: u8,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::sale::unpause_sale(ctx, scope)
+  }
+
+  pub fn deposit<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Deposit<'info>>,
     ref_key: Pubkey,
     amount: u64,
   ) -> Result<()> {
     instructions::sale::deposit(ctx, ref_key, amount)
   }
 
-  pub fn deposit_usdc(
-    ctx: Context<DepositUSDC>,
+  pub fn deposit_usdc<'info>(
+    ctx: Context<'_, '_, 'info, 'info, DepositUSDC<'info>>,
     ref_key: Pubkey,
     amount: u64,
   ) -> Result<()> {
     instructions::sale::deposit_usdc(ctx, ref_key, amount)
   }
 
-  pub fn deposit_usdt(
-    ctx: Context<DepositUSDT>,
+  pub fn deposit_usdt<'info>(
+    ctx: Context<'_, '_, 'info, 'info, DepositUSDT<'info>>,
     ref_key: Pubkey,
     amount: u64,
   ) -> Result<()> {
     instructions::sale::deposit_usdt(ctx, ref_key, amount)
   }
 
+  pub fn claim(
+    ctx: Context<Claim>,
+  ) -> Result<()> {
+    instructions::sale::claim(ctx)
+  }
+
+  pub fn close_beneficiary(
+    ctx: Context<CloseBeneficiary>,
+  ) -> Result<()> {
+    instructions::sale::close_beneficiary(ctx)
+  }
+
   pub fn init_round(
     ctx: Context<InitRound>,
     id: i16,
@@ -110,6 +251,35 @@ pub mod sale {
     instructions::round::set_round_price(ctx, price)
   }
 
+  pub fn set_round_pricing(
+    ctx: Context<SetRoundPricing>,
+    price_mode: state::round::PriceMode,
+    end_price: u64,
+    price_steps: u16,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::round::set_round_pricing(ctx, price_mode, end_price, price_steps)
+  }
+
+  pub fn set_round_auction(
+    ctx: Context<SetRoundAuction>,
+    start_price: u64,
+    floor_price: u64,
+    start_time: i64,
+    end_time: i64,
+    decay_curve: state::round::DecayCurve,
+    uniform_clearing: bool,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::round::set_round_auction(ctx, start_price, floor_price, start_time, end_time, decay_curve, uniform_clearing)
+  }
+
   pub fn set_round_supply(
     ctx: Context<SetRoundSupply>,
     total_supply: u128,
@@ -121,6 +291,17 @@ pub mod sale {
     instructions::round::set_round_supply(ctx, total_supply)
   }
 
+  pub fn schedule_round(
+    ctx: Context<SetRoundScheduled>,
+    start_time: i64,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::round::schedule_round(ctx, start_time)
+  }
+
   pub fn open_round(
     ctx: Context<SetRoundOpened>,
   ) -> Result<()> {
@@ -141,9 +322,101 @@ pub mod sale {
     instructions::round::close_round(ctx)
   }
 
+  pub fn close_round_account(
+    ctx: Context<CloseRoundAccount>,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::round::close_round_account(ctx)
+  }
+
+  pub fn pause_round(
+    ctx: Context<SetRoundPaused>,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::round::pause_round(ctx)
+  }
+
+  pub fn resume_round(
+    ctx: Context<SetRoundResumed>,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::round::resume_round(ctx)
+  }
+
+  pub fn cancel_round(
+    ctx: Context<SetRoundCancelled>,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::round::cancel_round(ctx)
+  }
+
+  pub fn settle_auction(
+    ctx: Context<SettleAuction>,
+    owner: Pubkey,
+  ) -> Result<()> {
+    instructions::round::settle_auction(ctx, owner)
+  }
+
+  pub fn set_round_shards(
+    ctx: Context<SetRoundShards>,
+    shard_count: u8,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::shard::set_round_shards(ctx, shard_count)
+  }
+
+  pub fn init_shard(
+    ctx: Context<InitShard>,
+    round_id: i16,
+    index: u8,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::shard::initialize_shard(ctx, round_id, index)
+  }
+
+  pub fn settle_shards<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SettleShards<'info>>,
+  ) -> Result<()> {
+    instructions::shard::settle_shards(ctx)
+  }
+
+  pub fn deposit_sharded<'info>(
+    ctx: Context<'_, '_, 'info, 'info, DepositSharded<'info>>,
+    ref_key: Pubkey,
+    amount: u64,
+  ) -> Result<()> {
+    instructions::shard::deposit_sharded(ctx, ref_key, amount)
+  }
+
+  pub fn deposit_sharded_stable<'info>(
+    ctx: Context<'_, '_, 'info, 'info, DepositShardedStable<'info>>,
+    ref_key: Pubkey,
+    amount: u64,
+  ) -> Result<()> {
+    instructions::shard::deposit_sharded_stable(ctx, ref_key, amount)
+  }
+
   pub fn init_referral(
     ctx: Context<InitReferral>,
-    _ref_key: Pubkey,
+    ref_key: Pubkey,
     main_reward: u64,
     secondary_reward: u64,
   ) -> Result<()> {
@@ -151,11 +424,26 @@ pub mod sale {
       return err!(errors::Sale::Unauthorized);
     }
 
-    instructions::referral::initialize_referral(ctx, main_reward, secondary_reward)
+    instructions::referral::initialize_referral(ctx, ref_key, main_reward, secondary_reward)
+  }
+
+  pub fn register_referral(
+    ctx: Context<RegisterReferral>,
+    upline: Pubkey,
+  ) -> Result<()> {
+    instructions::referral::register_referral(ctx, upline)
+  }
+
+  pub fn register_referral_code(
+    ctx: Context<RegisterReferralCode>,
+    code: String,
+  ) -> Result<()> {
+    instructions::referral::register_referral_code(ctx, code)
   }
 
   pub fn set_referral_reward(
     ctx: Context<SetReferralReward>,
+    ref_key: Pubkey,
     main_reward: u64,
     secondary_reward: u64,
   ) -> Result<()> {
@@ -163,27 +451,65 @@ pub mod sale {
       return err!(errors::Sale::Unauthorized);
     }
 
-    instructions::referral::set_referral_reward(ctx, main_reward, secondary_reward)
+    instructions::referral::set_referral_reward(ctx, ref_key, main_reward, secondary_reward)
+  }
+
+  pub fn set_referral_reward_mode(
+    ctx: Context<SetReferralRewardMode>,
+    ref_key: Pubkey,
+    reward_mode: Option<state::sale::RewardMode>,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::referral::set_referral_reward_mode(ctx, ref_key, reward_mode)
+  }
+
+  pub fn set_referral_buyer_bonus(
+    ctx: Context<SetReferralBuyerBonus>,
+    ref_key: Pubkey,
+    buyer_bonus: u64,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::referral::set_referral_buyer_bonus(ctx, ref_key, buyer_bonus)
+  }
+
+  pub fn set_referral_upline(
+    ctx: Context<SetReferralUpline>,
+    ref_key: Pubkey,
+    upline: Pubkey,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::referral::set_referral_upline(ctx, ref_key, upline)
   }
 
   pub fn enable_referral(
     ctx: Context<SetReferralEnabled>,
+    ref_key: Pubkey,
   ) -> Result<()> {
     if !auth::only_admin(ctx.accounts.payer.key()) {
       return err!(errors::Sale::Unauthorized);
     }
 
-    instructions::referral::enable_referral(ctx)
+    instructions::referral::enable_referral(ctx, ref_key)
   }
 
   pub fn disable_referral(
     ctx: Context<SetReferralDisabled>,
+    ref_key: Pubkey,
   ) -> Result<()> {
     if !auth::only_admin(ctx.accounts.payer.key()) {
       return err!(errors::Sale::Unauthorized);
     }
 
-    instructions::referral::disable_referral(ctx)
+    instructions::referral::disable_referral(ctx, ref_key)
   }
 
   pub fn withdraw_ref(
@@ -203,4 +529,97 @@ pub mod sale {
   ) -> Result<()> {
     instructions::referral::withdraw_usdt(ctx)
   }
+
+  pub fn withdraw_ref_all<'info>(
+    ctx: Context<'_, '_, 'info, 'info, WithdrawAll<'info>>,
+  ) -> Result<()> {
+    instructions::referral::withdraw_all(ctx)
+  }
+
+  pub fn set_referral_payout(
+    ctx: Context<SetReferralPayout>,
+    payout_address: Pubkey,
+  ) -> Result<()> {
+    instructions::referral::set_referral_payout(ctx, payout_address)
+  }
+
+  pub fn withdraw_ref_to_payout<'info>(
+    ctx: Context<'_, '_, 'info, 'info, WithdrawToPayout<'info>>,
+    ref_key: Pubkey,
+  ) -> Result<()> {
+    instructions::referral::withdraw_to_payout(ctx, ref_key)
+  }
+
+  pub fn clawback_referral<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ClawbackReferral<'info>>,
+    ref_key: Pubkey,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::referral::clawback_referral(ctx, ref_key)
+  }
+
+  pub fn close_referral(
+    ctx: Context<CloseReferral>,
+  ) -> Result<()> {
+    instructions::referral::close_referral(ctx)
+  }
+
+  pub fn claim_ref_tokens(
+    ctx: Context<ClaimRefTokens>,
+  ) -> Result<()> {
+    instructions::referral::claim_ref_tokens(ctx)
+  }
+
+  pub fn migrate_sale(
+    ctx: Context<MigrateAccount>,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::migrate::migrate_sale(ctx)
+  }
+
+  pub fn migrate_round(
+    ctx: Context<MigrateAccount>,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::migrate::migrate_round(ctx)
+  }
+
+  pub fn migrate_referral(
+    ctx: Context<MigrateAccount>,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::migrate::migrate_referral(ctx)
+  }
+
+  pub fn migrate_referral_code(
+    ctx: Context<MigrateAccount>,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::migrate::migrate_referral_code(ctx)
+  }
+
+  pub fn migrate_beneficiary(
+    ctx: Context<MigrateAccount>,
+  ) -> Result<()> {
+    if !auth::only_admin(ctx.accounts.payer.key()) {
+      return err!(errors::Sale::Unauthorized);
+    }
+
+    instructions::migrate::migrate_beneficiary(ctx)
+  }
 }
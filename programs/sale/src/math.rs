@@ -0,0 +1,205 @@
+use anchor_lang::prelude::*;
+use crate::errors;
+
+// Every helper fails with `MathOverflow` instead of wrapping or panicking.
+// Callers pick the rounding direction explicitly: amounts paid out by the
+// protocol round down, amounts owed to the protocol round up.
+
+pub fn add(
+  a: u128,
+  b: u128,
+) -> Result<u128> {
+  a.checked_add(b).ok_or_else(|| error!(errors::Sale::MathOverflow))
+}
+
+pub fn sub(
+  a: u128,
+  b: u128,
+) -> Result<u128> {
+  a.checked_sub(b).ok_or_else(|| error!(errors::Sale::MathOverflow))
+}
+
+pub fn mul(
+  a: u128,
+  b: u128,
+) -> Result<u128> {
+  a.checked_mul(b).ok_or_else(|| error!(errors::Sale::MathOverflow))
+}
+
+pub fn div_floor(
+  a: u128,
+  b: u128,
+) -> Result<u128> {
+  a.checked_div(b).ok_or_else(|| error!(errors::Sale::MathOverflow))
+}
+
+pub fn div_ceil(
+  a: u128,
+  b: u128,
+) -> Result<u128> {
+  if b == 0 {
+    return err!(errors::Sale::MathOverflow);
+  }
+
+  Ok(a.div_ceil(b))
+}
+
+pub fn mul_div_floor(
+  a: u128,
+  b: u128,
+  c: u128,
+) -> Result<u128> {
+  div_floor(mul(a, b)?, c)
+}
+
+pub fn mul_div_ceil(
+  a: u128,
+  b: u128,
+  c: u128,
+) -> Result<u128> {
+  div_ceil(mul(a, b)?, c)
+}
+
+pub fn pow10(
+  exp: u32,
+) -> Result<u128> {
+  10u128.checked_pow(exp).ok_or_else(|| error!(errors::Sale::MathOverflow))
+}
+
+pub fn to_u64(
+  value: u128,
+) -> Result<u64> {
+  u64::try_from(value).map_err(|_| error!(errors::Sale::MathOverflow))
+}
+
+// Seconds from `start` to `end`, which must not be earlier.
+pub fn elapsed(
+  start: i64,
+  end: i64,
+) -> Result<u128> {
+  end
+    .checked_sub(start)
+    .and_then(|seconds| u128::try_from(seconds).ok())
+    .ok_or_else(|| error!(errors::Sale::MathOverflow))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use proptest::prelude::*;
+
+  fn overflow() -> Error {
+    errors::Sale::MathOverflow.into()
+  }
+
+  #[test]
+  fn mul_div_overflows() {
+    assert_eq!(mul_div_floor(u128::MAX, 2, 2).unwrap_err(), overflow());
+    assert_eq!(mul_div_ceil(u128::MAX, 2, 2).unwrap_err(), overflow());
+    assert_eq!(mul_div_floor(u128::MAX, 1, 1).unwrap(), u128::MAX);
+    assert_eq!(mul_div_ceil(u128::MAX, 1, 1).unwrap(), u128::MAX);
+  }
+
+  #[test]
+  fn mul_div_by_zero() {
+    assert_eq!(mul_div_floor(1, 1, 0).unwrap_err(), overflow());
+    assert_eq!(mul_div_ceil(1, 1, 0).unwrap_err(), overflow());
+    assert_eq!(mul_div_ceil(0, 1, 0).unwrap_err(), overflow());
+  }
+
+  #[test]
+  fn mul_div_rounding() {
+    assert_eq!(mul_div_floor(10, 2, 3).unwrap(), 6);
+    assert_eq!(mul_div_ceil(10, 2, 3).unwrap(), 7);
+    assert_eq!(mul_div_floor(9, 2, 3).unwrap(), 6);
+    assert_eq!(mul_div_ceil(9, 2, 3).unwrap(), 6);
+  }
+
+  #[test]
+  fn floor_and_ceil_round_trip() {
+    let scale = pow10(9).unwrap();
+    for amount in [0u128, 1, 7, 999_999_999, 1_000_000_001, 123_456_789_012_345] {
+      for price in [1u128, 3, 999_999_999, 1_000_000_000, 1_500_000_001] {
+        // Tokens paid out for a USD amount never cost more than it.
+        let token_amount = mul_div_floor(amount, scale, price).unwrap();
+        assert!(mul_div_ceil(token_amount, price, scale).unwrap() <= amount);
+
+        // USD owed for a token amount always buys it back.
+        let owed_usd = mul_div_ceil(amount, price, scale).unwrap();
+        assert!(mul_div_floor(owed_usd, scale, price).unwrap() >= amount);
+
+        let floor = mul_div_floor(amount, price, scale).unwrap();
+        let ceil = mul_div_ceil(amount, price, scale).unwrap();
+        assert!(ceil - floor <= 1);
+        assert_eq!(ceil == floor, (amount * price) % scale == 0);
+      }
+    }
+  }
+
+  #[test]
+  fn pow10_bounds() {
+    assert_eq!(pow10(0).unwrap(), 1);
+    assert_eq!(pow10(38).unwrap(), 10u128.pow(38));
+    assert_eq!(pow10(39).unwrap_err(), overflow());
+    assert_eq!(pow10(u32::MAX).unwrap_err(), overflow());
+  }
+
+  #[test]
+  fn to_u64_does_not_truncate() {
+    assert_eq!(to_u64(u128::from(u64::MAX)).unwrap(), u64::MAX);
+    assert_eq!(to_u64(u128::from(u64::MAX) + 1).unwrap_err(), overflow());
+    assert_eq!(to_u64(u128::MAX).unwrap_err(), overflow());
+  }
+
+  #[test]
+  fn checked_arithmetic() {
+    assert_eq!(add(u128::MAX, 1).unwrap_err(), overflow());
+    assert_eq!(sub(0, 1).unwrap_err(), overflow());
+    assert_eq!(mul(u128::MAX, 2).unwrap_err(), overflow());
+    assert_eq!(div_floor(1, 0).unwrap_err(), overflow());
+    assert_eq!(div_ceil(1, 0).unwrap_err(), overflow());
+  }
+
+  #[test]
+  fn elapsed_rejects_going_back() {
+    assert_eq!(elapsed(100, 250).unwrap(), 150);
+    assert_eq!(elapsed(i64::MIN, i64::MAX).unwrap_err(), overflow());
+    assert_eq!(elapsed(250, 100).unwrap_err(), overflow());
+  }
+
+  proptest! {
+    #[test]
+    fn mul_div_brackets_the_exact_quotient(a in any::<u64>(), b in any::<u64>(), c in 1..=u64::MAX) {
+      let (a, b, c) = (u128::from(a), u128::from(b), u128::from(c));
+      let floor = mul_div_floor(a, b, c).unwrap();
+      let ceil = mul_div_ceil(a, b, c).unwrap();
+
+      // floor <= a * b / c <= ceil, compared without dividing.
+      prop_assert!(floor * c <= a * b);
+      prop_assert!(ceil * c >= a * b);
+      prop_assert!(ceil - floor <= 1);
+    }
+
+    #[test]
+    fn mul_div_overflow_is_an_error(a in any::<u128>(), b in any::<u128>(), c in any::<u128>()) {
+      let product = a.checked_mul(b);
+      for result in [mul_div_floor(a, b, c), mul_div_ceil(a, b, c)] {
+        match (product, c) {
+          (Some(_), 1..) => prop_assert!(result.is_ok()),
+          _ => prop_assert_eq!(result.unwrap_err(), overflow()),
+        }
+      }
+    }
+
+    #[test]
+    fn to_u64_round_trips(value in any::<u128>()) {
+      match to_u64(value) {
+        Ok(narrowed) => prop_assert_eq!(u128::from(narrowed), value),
+        Err(error) => {
+          prop_assert!(value > u128::from(u64::MAX));
+          prop_assert_eq!(error, overflow());
+        }
+      }
+    }
+  }
+}
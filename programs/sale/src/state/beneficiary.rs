@@ -1,8 +1,20 @@
 use anchor_lang::prelude::*;
+use crate::state::{ ACCOUNT_VERSION, LEGACY_ACCOUNT_VERSION };
+use crate::errors;
+use crate::auth::PRECISION;
+use crate::math;
 
 #[account]
+#[derive(InitSpace)]
 pub struct Beneficiary {
   token_amount: u128,
+  auction_round: i16,
+  auction_usd_amount: u128,
+  auction_token_amount: u128,
+  claimed_amount: u128,
+  referrer: Pubkey,
+  version: u8,
+  _reserved: [u8; 64],
 }
 
 impl Beneficiary {
@@ -10,6 +22,50 @@ impl Beneficiary {
     &mut self,
   ) -> Result<()> {
     self.token_amount = 0;
+    self.auction_round = 0;
+    self.auction_usd_amount = 0;
+    self.auction_token_amount = 0;
+    self.claimed_amount = 0;
+    self.referrer = Pubkey::default();
+    self.version = ACCOUNT_VERSION;
+
+    Ok(())
+  }
+
+  pub fn set_version(
+    &mut self,
+  ) -> Result<u8> {
+    let previous = self.get_version();
+    self.version = ACCOUNT_VERSION;
+
+    Ok(previous)
+  }
+
+  pub fn get_version(
+    &self,
+  ) -> u8 {
+    u8::max(self.version, LEGACY_ACCOUNT_VERSION)
+  }
+
+  // Records the first referrer used and reports whether it was just bound.
+  pub fn set_referrer(
+    &mut self,
+    referrer: Pubkey,
+  ) -> Result<bool> {
+    if self.referrer != Pubkey::default() {
+      return Ok(false);
+    }
+
+    self.referrer = referrer;
+
+    Ok(true)
+  }
+
+  pub fn set_claimed_amount(
+    &mut self,
+    claimed_amount: u128,
+  ) -> Result<()> {
+    self.claimed_amount = math::add(self.claimed_amount, claimed_amount)?;
 
     Ok(())
   }
@@ -18,14 +74,80 @@ impl Beneficiary {
     &mut self,
     token_amount: u128,
   ) -> Result<()> {
-    self.token_amount += token_amount;
+    self.token_amount = math::add(self.token_amount, token_amount)?;
 
     Ok(())
   }
 
+  pub fn set_auction_purchase(
+    &mut self,
+    round: i16,
+    usd_amount: u128,
+    token_amount: u128,
+  ) -> Result<()> {
+    if self.auction_usd_amount > 0 && self.auction_round != round {
+      return err!(errors::Sale::AuctionNotSettled);
+    }
+
+    self.auction_round = round;
+    self.auction_usd_amount = math::add(self.auction_usd_amount, usd_amount)?;
+    self.auction_token_amount = math::add(self.auction_token_amount, token_amount)?;
+
+    Ok(())
+  }
+
+  pub fn settle_auction(
+    &mut self,
+    round: i16,
+    clearing_price: u64,
+  ) -> Result<(u128, u128, u128)> {
+    if self.auction_usd_amount == 0 || self.auction_round != round {
+      return err!(errors::Sale::AuctionNothingToSettle);
+    }
+
+    // Every buyer ends up with exactly what the clearing price buys, so a
+    // fill below it gives back the difference.
+    let cleared_amount = math::mul_div_floor(self.auction_usd_amount, math::pow10(PRECISION)?, u128::from(clearing_price))?;
+    let bought_amount = self.auction_token_amount;
+
+    let usd_amount = self.auction_usd_amount;
+    self.token_amount = math::add(math::sub(self.token_amount, bought_amount)?, cleared_amount)?;
+    self.auction_usd_amount = 0;
+    self.auction_token_amount = 0;
+
+    Ok((bought_amount, cleared_amount, usd_amount))
+  }
+
   pub fn get_token_amount(
     &mut self,
   ) -> u128 {
     self.token_amount
   }
-}
\ No newline at end of file
+
+  // Auction fills are re-priced when settled, so they cannot be claimed
+  // before.
+  pub fn is_auction_settled(
+    &self,
+  ) -> bool {
+    self.auction_usd_amount == 0
+  }
+
+  // Everything bought has been settled and claimed.
+  pub fn is_closable(
+    &self,
+  ) -> bool {
+    self.is_auction_settled() && self.claimed_amount >= self.token_amount
+  }
+
+  pub fn get_referrer(
+    &self,
+  ) -> Pubkey {
+    self.referrer
+  }
+
+  pub fn get_claimed_amount(
+    &self,
+  ) -> u128 {
+    self.claimed_amount
+  }
+}
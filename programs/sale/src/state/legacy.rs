@@ -0,0 +1,63 @@
+use anchor_lang::prelude::*;
+use crate::state::sale::{ State as SaleState, RewardMode, RewardTier, MAX_UPLINE_LEVELS, MAX_REWARD_TIERS };
+use crate::state::round::{ State as RoundState, PriceMode, DecayCurve };
+
+// Borsh layouts `Sale` and `Round` had before they became zero-copy, kept so
+// `migrate_sale` and `migrate_round` can read the old accounts.
+
+#[derive(InitSpace, AnchorDeserialize, AnchorSerialize)]
+pub struct LegacySale {
+  pub max_investment: u64,
+  pub min_investment: u64,
+  pub main_reward: u64,
+  pub secondary_reward: u64,
+  pub total_sold: u128,
+  pub round: i16,
+  pub state: SaleState,
+  pub enabled: bool,
+  pub paused: u8,
+  pub pause_reason: u8,
+  pub referral_depth: u8,
+  pub upline_rewards: [u64; MAX_UPLINE_LEVELS],
+  pub token_mint: Pubkey,
+  pub claim_start: i64,
+  pub vesting_duration: i64,
+  pub bind_referrer: bool,
+  pub min_referral_usd: u64,
+  pub tier_count: u8,
+  pub reward_tiers: [RewardTier; MAX_REWARD_TIERS],
+  pub referrer_cap_usd: u64,
+  pub budget_usd: u64,
+  pub budget_tokens: u128,
+  pub spent_usd: u128,
+  pub spent_tokens: u128,
+  pub reward_mode: RewardMode,
+  pub cash_share: u64,
+  pub buyer_bonus: u64,
+  pub referrals_disabled: bool,
+  pub reject_disabled_referral: bool,
+  pub version: u8,
+  pub _reserved: [u8; 64],
+}
+
+#[derive(InitSpace, AnchorDeserialize, AnchorSerialize)]
+pub struct LegacyRound {
+  pub id: i16,
+  pub price: u64,
+  pub total_sold: u128,
+  pub total_supply: u128,
+  pub state: RoundState,
+  pub start_time: i64,
+  pub price_mode: PriceMode,
+  pub end_price: u64,
+  pub price_steps: u16,
+  pub end_time: i64,
+  pub decay_curve: DecayCurve,
+  pub uniform_clearing: bool,
+  pub total_usd: u128,
+  pub last_price: u64,
+  pub clearing_price: u64,
+  pub settled_usd: u128,
+  pub version: u8,
+  pub _reserved: [u8; 64],
+}
@@ -1,4 +1,15 @@
 pub mod sale;
 pub mod round;
 pub mod referral;
-pub mod beneficiary;
\ No newline at end of file
+pub mod beneficiary;
+pub mod referral_code;
+pub mod legacy;
+pub mod shard;
+pub mod purchase;
+
+// Accounts written before the version field existed read back as zero and
+// are treated as the legacy layout.
+pub const LEGACY_ACCOUNT_VERSION: u8 = 1;
+pub const ACCOUNT_VERSION: u8 = 2;
+// Every Borsh account used to be allocated with this fixed size.
+pub const LEGACY_ACCOUNT_SPACE: usize = 680;
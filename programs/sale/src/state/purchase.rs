@@ -0,0 +1,249 @@
+use anchor_lang::prelude::*;
+use crate::state::{ ACCOUNT_VERSION, LEGACY_ACCOUNT_VERSION };
+use crate::errors;
+use crate::math;
+
+#[derive(Clone, Copy, Debug, PartialEq, InitSpace, AnchorDeserialize, AnchorSerialize)]
+pub enum PaymentAsset {
+  Sol,
+  Usdc,
+  Usdt,
+}
+
+#[derive(Clone, Copy, InitSpace, AnchorDeserialize, AnchorSerialize)]
+pub struct Purchase {
+  pub asset: PaymentAsset,
+  pub paid_amount: u64,
+  pub usd_amount: u128,
+  pub token_amount: u128,
+  pub bonus_amount: u128,
+  pub referral: Pubkey,
+  pub timestamp: i64,
+}
+
+#[derive(Clone, Copy, Default, InitSpace, AnchorDeserialize, AnchorSerialize)]
+pub struct PurchaseTotals {
+  pub paid_amount: u64,
+  pub usd_amount: u128,
+  pub token_amount: u128,
+  pub bonus_amount: u128,
+  pub purchase_count: u32,
+}
+
+impl PurchaseTotals {
+  fn add(
+    &mut self,
+    purchase: &Purchase,
+  ) -> Result<()> {
+    self.paid_amount = math::to_u64(math::add(u128::from(self.paid_amount), u128::from(purchase.paid_amount))?)?;
+    self.usd_amount = math::add(self.usd_amount, purchase.usd_amount)?;
+    self.token_amount = math::add(self.token_amount, purchase.token_amount)?;
+    self.bonus_amount = math::add(self.bonus_amount, purchase.bonus_amount)?;
+    self.purchase_count = self.purchase_count.checked_add(1).ok_or_else(|| error!(errors::Sale::MathOverflow))?;
+
+    Ok(())
+  }
+}
+
+// Everything one beneficiary bought in one round, split by payment asset.
+#[account]
+#[derive(InitSpace)]
+pub struct PurchaseHistory {
+  owner: Pubkey,
+  round: i16,
+  sol: PurchaseTotals,
+  usdc: PurchaseTotals,
+  usdt: PurchaseTotals,
+  last_referral: Pubkey,
+  first_purchase_at: i64,
+  last_purchase_at: i64,
+  purchase_count: u32,
+  version: u8,
+  _reserved: [u8; 64],
+}
+
+impl PurchaseHistory {
+  // Returns the index of the recorded purchase, which is also the seed of
+  // its optional receipt.
+  pub fn set_purchase(
+    &mut self,
+    owner: Pubkey,
+    round: i16,
+    purchase: &Purchase,
+  ) -> Result<u32> {
+    if self.purchase_count == 0 {
+      self.owner = owner;
+      self.round = round;
+      self.first_purchase_at = purchase.timestamp;
+    }
+
+    match purchase.asset {
+      PaymentAsset::Sol => self.sol.add(purchase)?,
+      PaymentAsset::Usdc => self.usdc.add(purchase)?,
+      PaymentAsset::Usdt => self.usdt.add(purchase)?,
+    }
+
+    let index = self.purchase_count;
+    self.purchase_count = index.checked_add(1).ok_or_else(|| error!(errors::Sale::MathOverflow))?;
+    self.last_purchase_at = purchase.timestamp;
+    self.version = ACCOUNT_VERSION;
+    if purchase.referral != Pubkey::default() {
+      self.last_referral = purchase.referral;
+    }
+
+    Ok(index)
+  }
+
+  pub fn get_version(
+    &self,
+  ) -> u8 {
+    u8::max(self.version, LEGACY_ACCOUNT_VERSION)
+  }
+
+  pub fn get_owner(
+    &self,
+  ) -> Pubkey {
+    self.owner
+  }
+
+  pub fn get_round(
+    &self,
+  ) -> i16 {
+    self.round
+  }
+
+  pub fn get_totals(
+    &self,
+    asset: PaymentAsset,
+  ) -> PurchaseTotals {
+    match asset {
+      PaymentAsset::Sol => self.sol,
+      PaymentAsset::Usdc => self.usdc,
+      PaymentAsset::Usdt => self.usdt,
+    }
+  }
+
+  pub fn get_last_referral(
+    &self,
+  ) -> Pubkey {
+    self.last_referral
+  }
+
+  pub fn get_purchase_times(
+    &self,
+  ) -> (i64, i64) {
+    (self.first_purchase_at, self.last_purchase_at)
+  }
+
+  pub fn get_purchase_count(
+    &self,
+  ) -> u32 {
+    self.purchase_count
+  }
+}
+
+// An append-only record of a single deposit, written only when the buyer
+// passes the receipt account.
+#[account]
+#[derive(InitSpace)]
+pub struct PurchaseReceipt {
+  owner: Pubkey,
+  round: i16,
+  index: u32,
+  purchase: Purchase,
+  version: u8,
+}
+
+impl PurchaseReceipt {
+  pub fn init(
+    &mut self,
+    owner: Pubkey,
+    round: i16,
+    index: u32,
+    purchase: Purchase,
+  ) -> Result<()> {
+    self.owner = owner;
+    self.round = round;
+    self.index = index;
+    self.purchase = purchase;
+    self.version = ACCOUNT_VERSION;
+
+    Ok(())
+  }
+
+  pub fn get_index(
+    &self,
+  ) -> u32 {
+    self.index
+  }
+
+  pub fn get_purchase(
+    &self,
+  ) -> Purchase {
+    self.purchase
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn zeroed<T: AnchorDeserialize + Space>() -> T {
+    T::deserialize(&mut &vec![0u8; T::INIT_SPACE][..]).unwrap()
+  }
+
+  fn purchase(
+    asset: PaymentAsset,
+    paid_amount: u64,
+    referral: Pubkey,
+    timestamp: i64,
+  ) -> Purchase {
+    Purchase {
+      asset,
+      paid_amount,
+      usd_amount: u128::from(paid_amount) * 10,
+      token_amount: u128::from(paid_amount) * 5,
+      bonus_amount: u128::from(paid_amount),
+      referral,
+      timestamp,
+    }
+  }
+
+  #[test]
+  fn history_totals_each_asset() {
+    let owner = Pubkey::new_unique();
+    let referral = Pubkey::new_unique();
+    let mut history: PurchaseHistory = zeroed();
+
+    assert_eq!(history.set_purchase(owner, 2, &purchase(PaymentAsset::Sol, 10, referral, 100)).unwrap(), 0);
+    assert_eq!(history.set_purchase(owner, 2, &purchase(PaymentAsset::Usdc, 20, Pubkey::default(), 200)).unwrap(), 1);
+    assert_eq!(history.set_purchase(owner, 2, &purchase(PaymentAsset::Sol, 30, Pubkey::default(), 300)).unwrap(), 2);
+
+    let sol = history.get_totals(PaymentAsset::Sol);
+    assert_eq!((sol.paid_amount, sol.usd_amount, sol.token_amount, sol.bonus_amount, sol.purchase_count), (40, 400, 200, 40, 2));
+    let usdc = history.get_totals(PaymentAsset::Usdc);
+    assert_eq!((usdc.paid_amount, usdc.purchase_count), (20, 1));
+    assert_eq!(history.get_totals(PaymentAsset::Usdt).purchase_count, 0);
+
+    assert_eq!((history.get_owner(), history.get_round()), (owner, 2));
+    assert_eq!(history.get_purchase_times(), (100, 300));
+    assert_eq!(history.get_purchase_count(), 3);
+    // Purchases without a referrer keep the last one used.
+    assert_eq!(history.get_last_referral(), referral);
+    assert_eq!(history.get_version(), ACCOUNT_VERSION);
+  }
+
+  #[test]
+  fn receipt_keeps_its_purchase() {
+    let owner = Pubkey::new_unique();
+    let referral = Pubkey::new_unique();
+    let mut receipt: PurchaseReceipt = zeroed();
+    receipt.init(owner, 3, 7, purchase(PaymentAsset::Usdt, 50, referral, 400)).unwrap();
+
+    assert_eq!((receipt.owner, receipt.round, receipt.index, receipt.version), (owner, 3, 7, ACCOUNT_VERSION));
+    let stored = receipt.get_purchase();
+    assert_eq!(stored.asset, PaymentAsset::Usdt);
+    assert_eq!((stored.paid_amount, stored.usd_amount, stored.token_amount, stored.bonus_amount), (50, 500, 250, 50));
+    assert_eq!((stored.referral, stored.timestamp), (referral, 400));
+  }
+}
@@ -1,6 +1,26 @@
 use anchor_lang::prelude::*;
+use crate::state::{ ACCOUNT_VERSION, LEGACY_ACCOUNT_VERSION };
+use crate::errors;
+use crate::math;
+use crate::state::sale::RewardMode;
+
+// Admin-controlled referral settings, as carried by
+// `ReferralConfigChangedEvent`.
+#[derive(Clone, AnchorDeserialize, AnchorSerialize)]
+pub struct ReferralConfig {
+  pub main_reward: u64,
+  pub secondary_reward: u64,
+  pub enabled: bool,
+  pub initialized: bool,
+  pub upline: Pubkey,
+  pub tier: u8,
+  pub reward_mode: Option<RewardMode>,
+  pub buyer_bonus: u64,
+  pub payout_address: Pubkey,
+}
 
 #[account]
+#[derive(InitSpace)]
 pub struct Referral {
   main_reward: u64,
   secondary_reward: u64,
@@ -11,6 +31,18 @@ pub struct Referral {
   token_reward_amount: u128,
 
   enabled: bool,
+  upline: Pubkey,
+  token_claimed_amount: u128,
+  initialized: bool,
+  referred_usd: u128,
+  referred_count: u32,
+  tier: u8,
+  reward_usd: u128,
+  reward_mode: Option<RewardMode>,
+  buyer_bonus: u64,
+  payout_address: Pubkey,
+  version: u8,
+  _reserved: [u8; 64],
 }
 
 impl Referral {
@@ -18,7 +50,12 @@ impl Referral {
     &mut self,
     main_ref_reward: u64,
     secondary_ref_reward: u64,
+    max_main_reward: u64,
   ) -> Result<()> {
+    if main_ref_reward > max_main_reward {
+      return err!(errors::Sale::SaleMainRefRewardTooLarge);
+    }
+
     self.main_reward = main_ref_reward;
     self.secondary_reward = secondary_ref_reward;
 
@@ -28,6 +65,144 @@ impl Referral {
     self.token_reward_amount = 0;
 
     self.enabled = true;
+    self.upline = Pubkey::default();
+    self.token_claimed_amount = 0;
+    self.initialized = true;
+    self.referred_usd = 0;
+    self.referred_count = 0;
+    self.tier = 0;
+    self.reward_usd = 0;
+    self.reward_mode = None;
+    self.buyer_bonus = 0;
+    self.payout_address = Pubkey::default();
+    self.version = ACCOUNT_VERSION;
+
+    Ok(())
+  }
+
+  pub fn set_version(
+    &mut self,
+  ) -> Result<u8> {
+    let previous = self.get_version();
+    self.version = ACCOUNT_VERSION;
+
+    Ok(previous)
+  }
+
+  pub fn get_version(
+    &self,
+  ) -> u8 {
+    u8::max(self.version, LEGACY_ACCOUNT_VERSION)
+  }
+
+  pub fn get_config(
+    &self,
+  ) -> ReferralConfig {
+    ReferralConfig {
+      main_reward: self.main_reward,
+      secondary_reward: self.secondary_reward,
+      enabled: self.enabled,
+      initialized: self.initialized,
+      upline: self.upline,
+      tier: self.tier,
+      reward_mode: self.reward_mode,
+      buyer_bonus: self.buyer_bonus,
+      payout_address: self.payout_address,
+    }
+  }
+
+  pub fn set_payout_address(
+    &mut self,
+    payout_address: Pubkey,
+  ) -> Result<()> {
+    self.payout_address = payout_address;
+
+    Ok(())
+  }
+
+  pub fn set_buyer_bonus(
+    &mut self,
+    buyer_bonus: u64,
+  ) -> Result<()> {
+    if buyer_bonus > 1_000_000_000 {
+      return err!(errors::Sale::BuyerBonusInvalid);
+    }
+
+    self.buyer_bonus = buyer_bonus;
+
+    Ok(())
+  }
+
+  pub fn set_reward_mode(
+    &mut self,
+    reward_mode: Option<RewardMode>,
+  ) -> Result<()> {
+    self.reward_mode = reward_mode;
+
+    Ok(())
+  }
+
+  pub fn set_reward_usd(
+    &mut self,
+    usd_amount: u128,
+  ) -> Result<()> {
+    self.reward_usd = math::add(self.reward_usd, usd_amount)?;
+
+    Ok(())
+  }
+
+  // `new_buyer` is only set when a beneficiary binds to its first referrer,
+  // so a buyer counts towards one referral ever.
+  pub fn set_referred(
+    &mut self,
+    usd_amount: u128,
+    new_buyer: bool,
+  ) -> Result<()> {
+    self.referred_usd = math::add(self.referred_usd, usd_amount)?;
+    if new_buyer {
+      self.referred_count = self.referred_count.checked_add(1).ok_or_else(|| error!(errors::Sale::MathOverflow))?;
+    }
+
+    Ok(())
+  }
+
+  pub fn set_tier(
+    &mut self,
+    tier: u8,
+  ) -> Result<u8> {
+    let previous = self.tier;
+    self.tier = tier;
+
+    Ok(previous)
+  }
+
+  // Referral accounts created implicitly by deposits start zeroed, so
+  // registering keeps any balances they have already accrued.
+  pub fn register(
+    &mut self,
+    main_reward: u64,
+    secondary_reward: u64,
+    upline: Pubkey,
+  ) -> Result<()> {
+    if self.is_initialized() {
+      return err!(errors::Sale::ReferralAlreadyRegistered);
+    }
+
+    self.main_reward = main_reward;
+    self.secondary_reward = secondary_reward;
+    self.upline = upline;
+    self.enabled = true;
+    self.initialized = true;
+    self.version = ACCOUNT_VERSION;
+
+    Ok(())
+  }
+
+  pub fn set_upline(
+    &mut self,
+    upline: Pubkey,
+  ) -> Result<()> {
+    self.upline = upline;
 
     Ok(())
   }
@@ -36,7 +211,12 @@ impl Referral {
     &mut self,
     main_reward: u64,
     secondary_reward: u64,
+    max_main_reward: u64,
   ) -> Result<()> {
+    if main_reward > max_main_reward {
+      return err!(errors::Sale::SaleMainRefRewardTooLarge);
+    }
+
     self.main_reward = main_reward;
     self.secondary_reward = secondary_reward;
 
@@ -47,7 +227,7 @@ impl Referral {
     &mut self,
     reward_amount: u64,
   ) -> Result<()> {
-    self.sol_reward_amount += reward_amount;
+    self.sol_reward_amount = math::to_u64(math::add(u128::from(self.sol_reward_amount), u128::from(reward_amount))?)?;
 
     Ok(())
   }
@@ -64,7 +244,7 @@ impl Referral {
     &mut self,
     usdt_reward_amount: u64,
   ) -> Result<()> {
-    self.usdt_reward_amount += usdt_reward_amount;
+    self.usdt_reward_amount = math::to_u64(math::add(u128::from(self.usdt_reward_amount), u128::from(usdt_reward_amount))?)?;
 
     Ok(())
   }
@@ -81,7 +261,7 @@ impl Referral {
     &mut self,
     usdc_reward_amount: u64,
   ) -> Result<()> {
-    self.usdc_reward_amount += usdc_reward_amount;
+    self.usdc_reward_amount = math::to_u64(math::add(u128::from(self.usdc_reward_amount), u128::from(usdc_reward_amount))?)?;
 
     Ok(())
   }
@@ -98,7 +278,25 @@ impl Referral {
     &mut self,
     token_reward_amount: u128,
   ) -> Result<()> {
-    self.token_reward_amount += token_reward_amount;
+    self.token_reward_amount = math::add(self.token_reward_amount, token_reward_amount)?;
+
+    Ok(())
+  }
+
+  pub fn forfeit_token_reward_amount(
+    &mut self,
+  ) -> Result<u128> {
+    let forfeited = math::sub(self.token_reward_amount, self.token_claimed_amount)?;
+    self.token_reward_amount = self.token_claimed_amount;
+
+    Ok(forfeited)
+  }
+
+  pub fn set_token_claimed_amount(
+    &mut self,
+    token_claimed_amount: u128,
+  ) -> Result<()> {
+    self.token_claimed_amount = math::add(self.token_claimed_amount, token_claimed_amount)?;
 
     Ok(())
   }
@@ -109,6 +307,42 @@ impl Referral {
     (self.main_reward, self.secondary_reward)
   }
 
+  pub fn get_upline(
+    &self,
+  ) -> Pubkey {
+    self.upline
+  }
+
+  pub fn get_payout_address(
+    &self,
+  ) -> Pubkey {
+    self.payout_address
+  }
+
+  pub fn get_buyer_bonus(
+    &self,
+  ) -> u64 {
+    self.buyer_bonus
+  }
+
+  pub fn get_reward_mode(
+    &self,
+  ) -> Option<RewardMode> {
+    self.reward_mode
+  }
+
+  pub fn get_reward_usd(
+    &self,
+  ) -> u128 {
+    self.reward_usd
+  }
+
+  pub fn get_referred(
+    &self,
+  ) -> (u128, u32) {
+    (self.referred_usd, self.referred_count)
+  }
+
   pub fn get_sol_reward_amount(
     &mut self,
   ) -> u64 {
@@ -133,10 +367,43 @@ impl Referral {
     self.token_reward_amount
   }
 
+  pub fn get_token_claimed_amount(
+    &self,
+  ) -> u128 {
+    self.token_claimed_amount
+  }
+
+  // Nothing is left to withdraw or claim, so the account can be closed.
+  pub fn is_empty(
+    &self,
+  ) -> bool {
+    self.sol_reward_amount == 0
+      && self.usdc_reward_amount == 0
+      && self.usdt_reward_amount == 0
+      && self.token_claimed_amount >= self.token_reward_amount
+  }
+
+  // Referrals created implicitly by a deposit are not registered yet and
+  // stay eligible; only an explicit disable turns rewards off. Legacy
+  // referrals never stored `initialized`, so one that is off reads as
+  // unregistered.
+  pub fn is_disabled(
+    &self,
+  ) -> bool {
+    self.initialized && !self.enabled
+  }
+
+  pub fn is_initialized(
+    &self,
+  ) -> bool {
+    self.initialized || self.enabled
+  }
+
   pub fn enable(
     &mut self,
   ) -> Result<()> {
     self.enabled = true;
+    self.initialized = true;
 
     Ok(())
   }
@@ -145,6 +412,7 @@ impl Referral {
     &mut self,
   ) -> Result<()> {
     self.enabled = false;
+    self.initialized = true;
 
     Ok(())
   }
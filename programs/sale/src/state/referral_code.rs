@@ -0,0 +1,61 @@
+use anchor_lang::prelude::*;
+use crate::state::{ ACCOUNT_VERSION, LEGACY_ACCOUNT_VERSION };
+use crate::errors;
+
+pub const MIN_CODE_LENGTH: usize = 3;
+pub const MAX_CODE_LENGTH: usize = 16;
+
+#[account]
+#[derive(InitSpace)]
+pub struct ReferralCode {
+  referrer: Pubkey,
+  version: u8,
+  _reserved: [u8; 32],
+}
+
+impl ReferralCode {
+  pub fn init(
+    &mut self,
+    referrer: Pubkey,
+  ) -> Result<()> {
+    self.referrer = referrer;
+    self.version = ACCOUNT_VERSION;
+
+    Ok(())
+  }
+
+  pub fn set_version(
+    &mut self,
+  ) -> Result<u8> {
+    let previous = self.get_version();
+    self.version = ACCOUNT_VERSION;
+
+    Ok(previous)
+  }
+
+  pub fn get_version(
+    &self,
+  ) -> u8 {
+    u8::max(self.version, LEGACY_ACCOUNT_VERSION)
+  }
+
+  pub fn validate(
+    code: &str,
+  ) -> Result<()> {
+    let valid = code.len() >= MIN_CODE_LENGTH
+      && code.len() <= MAX_CODE_LENGTH
+      && code.bytes().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+
+    if !valid {
+      return err!(errors::Sale::InvalidReferralCode);
+    }
+
+    Ok(())
+  }
+
+  pub fn get_referrer(
+    &self,
+  ) -> Pubkey {
+    self.referrer
+  }
+}
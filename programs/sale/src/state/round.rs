@@ -1,20 +1,119 @@
 use anchor_lang::prelude::*;
+use crate::state::LEGACY_ACCOUNT_VERSION;
+use crate::state::legacy::LegacyRound;
 use crate::errors;
+use crate::auth::PRECISION;
+use crate::math;
 
-#[derive(Clone, PartialEq, AnchorDeserialize, AnchorSerialize)]
+pub const MAX_PRICE_STEPS: u16 = 100;
+pub const MAX_SHARDS: u8 = 16;
+
+// Version 3 is the zero-copy layout.
+pub const ROUND_VERSION: u8 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, InitSpace, AnchorDeserialize, AnchorSerialize)]
+pub enum PriceMode {
+  Fixed,
+  Linear,
+  Step,
+  Dutch,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, InitSpace, AnchorDeserialize, AnchorSerialize)]
+pub enum DecayCurve {
+  Linear,
+  Quadratic,
+}
+
+// Discriminants of Draft, Open and Closed match the former None, Opened and
+// Closed variants so existing round accounts keep their meaning.
+#[derive(Clone, Copy, Debug, PartialEq, InitSpace, AnchorDeserialize, AnchorSerialize)]
 pub enum State {
-  None,
-  Opened,
+  Draft,
+  Open,
   Closed,
+  Scheduled,
+  Paused,
+  SoldOut,
+  Cancelled,
+}
+
+impl State {
+  pub fn can_transition(
+    &self,
+    to: State,
+  ) -> bool {
+    matches!(
+      (self, to),
+      (State::Draft, State::Scheduled)
+        | (State::Draft, State::Open)
+        | (State::Draft, State::Cancelled)
+        | (State::Scheduled, State::Draft)
+        | (State::Scheduled, State::Open)
+        | (State::Scheduled, State::Cancelled)
+        | (State::Open, State::Paused)
+        | (State::Open, State::SoldOut)
+        | (State::Open, State::Closed)
+        | (State::Paused, State::Open)
+        | (State::Paused, State::Closed)
+        | (State::Paused, State::Cancelled)
+        | (State::SoldOut, State::Open)
+        | (State::SoldOut, State::Closed)
+        | (State::Closed, State::Open)
+    )
+  }
+}
+
+// Pricing, supply and counters of a round, as carried by
+// `RoundConfigChangedEvent`.
+#[derive(Clone, AnchorDeserialize, AnchorSerialize)]
+pub struct RoundConfig {
+  pub state: State,
+  pub price_mode: PriceMode,
+  pub price: u64,
+  pub end_price: u64,
+  pub price_steps: u16,
+  pub start_time: i64,
+  pub end_time: i64,
+  pub decay_curve: DecayCurve,
+  pub uniform_clearing: bool,
+  pub total_supply: u128,
+  pub total_sold: u128,
+  pub total_usd: u128,
+  pub settled_usd: u128,
+  pub clearing_price: u64,
+  pub shard_count: u8,
+  pub shard_reserved: u128,
 }
 
-#[account]
+// Fields are ordered by alignment so the `repr(C)` layout has no implicit
+// padding; enums and flags are stored as `u8`. The shard fields and
+// `bonus_sold` were carved out of the reserved tail, hence the explicit
+// padding.
+#[account(zero_copy)]
 pub struct Round {
-  id: i16,
-  price: u64,
   total_sold: u128,
   total_supply: u128,
-  state: State,
+  total_usd: u128,
+  settled_usd: u128,
+  price: u64,
+  end_price: u64,
+  last_price: u64,
+  clearing_price: u64,
+  start_time: i64,
+  end_time: i64,
+  id: i16,
+  price_steps: u16,
+  state: u8,
+  price_mode: u8,
+  decay_curve: u8,
+  uniform_clearing: u8,
+  version: u8,
+  shard_count: u8,
+  _padding: [u8; 6],
+  shard_reserved: u128,
+  bonus_sold: u128,
+  _reserved: [u8; 32],
 }
 
 impl Round {
@@ -28,7 +127,159 @@ impl Round {
     self.price = price;
     self.total_supply = total_supply;
     self.total_sold = 0;
-    self.state = State::None;
+    self.state = State::Draft as u8;
+    self.start_time = 0;
+    self.price_mode = PriceMode::Fixed as u8;
+    self.end_price = price;
+    self.price_steps = 0;
+    self.end_time = 0;
+    self.decay_curve = DecayCurve::Linear as u8;
+    self.uniform_clearing = 0;
+    self.total_usd = 0;
+    self.settled_usd = 0;
+    self.last_price = 0;
+    self.clearing_price = 0;
+    self.shard_count = 0;
+    self.shard_reserved = 0;
+    self.bonus_sold = 0;
+    self.version = ROUND_VERSION;
+
+    Ok(())
+  }
+
+  // Copies an account written with the Borsh layout into this one and
+  // returns the version it was migrated from.
+  pub fn migrate(
+    &mut self,
+    legacy: &LegacyRound,
+  ) -> Result<u8> {
+    self.total_sold = legacy.total_sold;
+    self.total_supply = legacy.total_supply;
+    self.total_usd = legacy.total_usd;
+    self.settled_usd = legacy.settled_usd;
+    self.price = legacy.price;
+    self.end_price = legacy.end_price;
+    self.last_price = legacy.last_price;
+    self.clearing_price = legacy.clearing_price;
+    self.start_time = legacy.start_time;
+    self.end_time = legacy.end_time;
+    self.id = legacy.id;
+    self.price_steps = legacy.price_steps;
+    self.state = legacy.state as u8;
+    self.price_mode = legacy.price_mode as u8;
+    self.decay_curve = legacy.decay_curve as u8;
+    self.uniform_clearing = u8::from(legacy.uniform_clearing);
+    self.version = ROUND_VERSION;
+
+    Ok(u8::max(legacy.version, LEGACY_ACCOUNT_VERSION))
+  }
+
+  pub fn set_version(
+    &mut self,
+  ) -> Result<u8> {
+    let previous = self.get_version();
+    self.version = ROUND_VERSION;
+
+    Ok(previous)
+  }
+
+  pub fn get_version(
+    &self,
+  ) -> u8 {
+    u8::max(self.version, LEGACY_ACCOUNT_VERSION)
+  }
+
+  pub fn get_config(
+    &self,
+  ) -> RoundConfig {
+    RoundConfig {
+      state: self.get_state(),
+      price_mode: self.get_price_mode(),
+      price: self.price,
+      end_price: self.end_price,
+      price_steps: self.price_steps,
+      start_time: self.start_time,
+      end_time: self.end_time,
+      decay_curve: self.get_decay_curve(),
+      uniform_clearing: self.uniform_clearing != 0,
+      total_supply: self.total_supply,
+      total_sold: self.total_sold,
+      total_usd: self.total_usd,
+      settled_usd: self.settled_usd,
+      clearing_price: self.clearing_price,
+      shard_count: self.shard_count,
+      shard_reserved: self.shard_reserved,
+    }
+  }
+
+  pub fn set_shard_count(
+    &mut self,
+    shard_count: u8,
+  ) -> Result<()> {
+    if shard_count > MAX_SHARDS {
+      return err!(errors::Sale::ShardInvalid);
+    }
+
+    self.shard_count = shard_count;
+
+    Ok(())
+  }
+
+  pub fn set_shard_reserved(
+    &mut self,
+    released: u128,
+    granted: u128,
+  ) -> Result<()> {
+    self.shard_reserved = math::add(math::sub(self.shard_reserved, released)?, granted)?;
+
+    Ok(())
+  }
+
+  // Each shard is granted at most an equal slice of the supply nobody else
+  // holds, so the shards together can never oversell the round.
+  pub fn get_shard_grant(
+    &self,
+  ) -> Result<u128> {
+    if !self.is_open() || self.shard_count == 0 {
+      return Ok(0);
+    }
+
+    Ok(self.get_available_supply() / u128::from(self.shard_count))
+  }
+
+  pub fn get_available_supply(
+    &self,
+  ) -> u128 {
+    self.total_supply.saturating_sub(self.total_sold.saturating_add(self.shard_reserved))
+  }
+
+  pub fn get_shard_count(
+    &self,
+  ) -> u8 {
+    self.shard_count
+  }
+
+  pub fn get_shard_reserved(
+    &self,
+  ) -> u128 {
+    self.shard_reserved
+  }
+
+  // Credits a buyer's auction fill at the clearing price. `set_close` took
+  // the fills out of `total_sold`, so settling in any order never oversells
+  // the round.
+  pub fn set_auction_settled(
+    &mut self,
+    token_amount: u128,
+    usd_amount: u128,
+  ) -> Result<()> {
+    let total_sold = math::add(self.total_sold, token_amount)?;
+    if total_sold > self.total_supply {
+      return err!(errors::Sale::RoundSupplyExceeded);
+    }
+
+    self.total_sold = total_sold;
+    self.settled_usd = math::add(self.settled_usd, usd_amount)?;
 
     Ok(())
   }
@@ -37,88 +288,656 @@ impl Round {
     &mut self,
     price: u64,
   ) -> Result<()> {
-    if self.state == State::Opened || self.state == State::Closed {
+    if self.get_state() == State::Open {
       return err!(errors::Sale::RoundOpened);
     }
 
+    if self.get_state() == State::Cancelled {
+      return err!(errors::Sale::RoundCancelled);
+    }
+
+    match self.get_price_mode() {
+      PriceMode::Linear | PriceMode::Step if price > self.end_price => {
+        return err!(errors::Sale::RoundInvalidPricing);
+      }
+      PriceMode::Dutch if price < self.end_price => {
+        return err!(errors::Sale::RoundInvalidPricing);
+      }
+      _ => {}
+    }
+
     self.price = price;
 
     Ok(())
   }
 
+  pub fn set_pricing(
+    &mut self,
+    price_mode: PriceMode,
+    end_price: u64,
+    price_steps: u16,
+  ) -> Result<()> {
+    if self.get_state() == State::Open {
+      return err!(errors::Sale::RoundOpened);
+    }
+
+    if self.get_state() == State::Cancelled {
+      return err!(errors::Sale::RoundCancelled);
+    }
+
+    match price_mode {
+      PriceMode::Fixed => {
+        self.end_price = self.price;
+        self.price_steps = 0;
+      }
+      PriceMode::Linear => {
+        if end_price < self.price {
+          return err!(errors::Sale::RoundInvalidPricing);
+        }
+
+        self.end_price = end_price;
+        self.price_steps = 0;
+      }
+      PriceMode::Step => {
+        if end_price < self.price || price_steps == 0 || price_steps > MAX_PRICE_STEPS {
+          return err!(errors::Sale::RoundInvalidPricing);
+        }
+
+        self.end_price = end_price;
+        self.price_steps = price_steps;
+      }
+      PriceMode::Dutch => {
+        return err!(errors::Sale::RoundInvalidPricing);
+      }
+    }
+
+    self.price_mode = price_mode as u8;
+
+    Ok(())
+  }
+
+  pub fn set_auction(
+    &mut self,
+    start_price: u64,
+    floor_price: u64,
+    start_time: i64,
+    end_time: i64,
+    decay_curve: DecayCurve,
+    uniform_clearing: bool,
+  ) -> Result<()> {
+    if self.get_state() != State::Draft && self.get_state() != State::Scheduled {
+      return err!(errors::Sale::RoundOpened);
+    }
+
+    if floor_price == 0 || floor_price > start_price || start_time < 0 || end_time <= start_time {
+      return err!(errors::Sale::RoundInvalidPricing);
+    }
+
+    self.price_mode = PriceMode::Dutch as u8;
+    self.price = start_price;
+    self.end_price = floor_price;
+    self.price_steps = 0;
+    self.start_time = start_time;
+    self.end_time = end_time;
+    self.decay_curve = decay_curve as u8;
+    self.uniform_clearing = u8::from(uniform_clearing);
+
+    Ok(())
+  }
+
+  pub fn quote(
+    &self,
+    usd_amount: u128,
+    now: i64,
+  ) -> Result<u128> {
+    match self.get_price_mode() {
+      PriceMode::Fixed => math::mul_div_floor(usd_amount, math::pow10(PRECISION)?, u128::from(self.price)),
+      PriceMode::Linear => self.quote_linear(usd_amount),
+      PriceMode::Step => self.quote_step(usd_amount),
+      PriceMode::Dutch => math::mul_div_floor(usd_amount, math::pow10(PRECISION)?, u128::from(self.get_auction_price(now)?)),
+    }
+  }
+
+  pub fn get_auction_price(
+    &self,
+    now: i64,
+  ) -> Result<u64> {
+    if now <= self.start_time {
+      return Ok(self.price);
+    }
+
+    if now >= self.end_time {
+      return Ok(self.end_price);
+    }
+
+    let scale = math::pow10(PRECISION)?;
+    let elapsed = math::mul_div_floor(
+      math::elapsed(self.start_time, now)?,
+      scale,
+      math::elapsed(self.start_time, self.end_time)?,
+    )?;
+    let decay = match self.get_decay_curve() {
+      DecayCurve::Linear => elapsed,
+      // Falls fastest right after the start and flattens towards the floor.
+      DecayCurve::Quadratic => {
+        let remaining = math::sub(scale, elapsed)?;
+        math::sub(scale, math::mul_div_ceil(remaining, remaining, scale)?)?
+      }
+    };
+
+    // The price only ever drops by the rounded-down amount.
+    let drop = math::mul_div_floor(math::sub(u128::from(self.price), u128::from(self.end_price))?, decay, scale)?;
+    math::to_u64(math::sub(u128::from(self.price), drop)?)
+  }
+
+  // `bonus_amount` is the buyer bonus credited on top of the quote, which
+  // uniform clearing has to leave room for.
+  pub fn set_purchase(
+    &mut self,
+    usd_amount: u128,
+    bonus_amount: u128,
+    now: i64,
+  ) -> Result<()> {
+    if self.get_price_mode() == PriceMode::Dutch {
+      self.total_usd = math::add(self.total_usd, usd_amount)?;
+      self.bonus_sold = math::add(self.bonus_sold, bonus_amount)?;
+      self.last_price = self.get_auction_price(now)?;
+    }
+
+    Ok(())
+  }
+
+  // Largest token amount whose integral over the ramp, starting at
+  // `total_sold`, does not exceed `usd_amount`.
+  fn quote_linear(
+    &self,
+    usd_amount: u128,
+  ) -> Result<u128> {
+    let mut low = 0u128;
+    let mut high = math::mul_div_floor(usd_amount, math::pow10(PRECISION)?, u128::from(self.price))?;
+
+    while low < high {
+      let mid = low + (high - low).div_ceil(2);
+      if self.linear_cost(mid)? <= usd_amount {
+        low = mid;
+      } else {
+        high = mid - 1;
+      }
+    }
+
+    Ok(low)
+  }
+
+  fn linear_cost(
+    &self,
+    token_amount: u128,
+  ) -> Result<u128> {
+    let start = u128::from(self.price);
+    let delta = math::sub(u128::from(self.end_price), u128::from(self.price))?;
+    let span = math::add(math::mul(2, self.total_sold)?, token_amount)?;
+    let area = math::mul_div_ceil(token_amount, span, math::mul(2, self.total_supply)?)?;
+    let cost = math::add(math::mul(start, token_amount)?, math::mul(delta, area)?)?;
+
+    math::div_ceil(cost, math::pow10(PRECISION)?)
+  }
+
+  fn quote_step(
+    &self,
+    usd_amount: u128,
+  ) -> Result<u128> {
+    let scale = math::pow10(PRECISION)?;
+    let steps = u128::from(self.price_steps);
+    let last_step = math::sub(steps, 1)?;
+    let delta = math::sub(u128::from(self.end_price), u128::from(self.price))?;
+
+    let mut remaining = usd_amount;
+    let mut sold = self.total_sold;
+    let mut token_amount = 0u128;
+
+    loop {
+      let mut step = math::mul_div_floor(sold, steps, self.total_supply)?;
+      let next_step = math::add(step, 1)?;
+      if math::mul_div_floor(next_step, self.total_supply, steps)? <= sold {
+        step = next_step;
+      }
+      step = u128::min(step, last_step);
+      let price = if last_step > 0 {
+        math::add(u128::from(self.price), math::mul_div_floor(delta, step, last_step)?)?
+      } else {
+        u128::from(self.price)
+      };
+
+      let affordable = math::mul_div_floor(remaining, scale, price)?;
+      if step == last_step {
+        return math::add(token_amount, affordable);
+      }
+
+      let capacity = math::sub(math::mul_div_floor(math::add(step, 1)?, self.total_supply, steps)?, sold)?;
+      if affordable <= capacity {
+        return math::add(token_amount, affordable);
+      }
+
+      token_amount = math::add(token_amount, capacity)?;
+      remaining = math::sub(remaining, math::mul_div_ceil(capacity, price, scale)?)?;
+      sold = math::add(sold, capacity)?;
+    }
+  }
+
   pub fn set_total_supply(
     &mut self,
     total_supply: u128,
   ) -> Result<()> {
-    if self.total_sold > total_supply {
+    if math::add(self.total_sold, self.shard_reserved)? > total_supply {
       return err!(errors::Sale::RoundSupplyTooSmall);
     }
 
+    if self.get_state() == State::Cancelled {
+      return err!(errors::Sale::RoundCancelled);
+    }
+
     self.total_supply = total_supply;
 
     Ok(())
   }
 
+  pub fn set_state(
+    &mut self,
+    state: State,
+  ) -> Result<State> {
+    if !self.get_state().can_transition(state) {
+      return err!(errors::Sale::RoundInvalidTransition);
+    }
+
+    let previous = self.get_state();
+    self.state = state as u8;
+
+    Ok(previous)
+  }
+
+  // A Dutch round opens when its auction starts, so it can only be
+  // scheduled at that time.
+  pub fn set_scheduled(
+    &mut self,
+    start_time: i64,
+  ) -> Result<State> {
+    if self.get_price_mode() == PriceMode::Dutch && start_time != self.start_time {
+      return err!(errors::Sale::RoundScheduleConflict);
+    }
+
+    let previous = self.set_state(State::Scheduled)?;
+    self.start_time = start_time;
+
+    Ok(previous)
+  }
+
   pub fn set_open(
     &mut self,
-  ) -> Result<()> {
-    if self.state == State::Opened || self.state == State::Closed {
-      return err!(errors::Sale::RoundOpened);
+  ) -> Result<State> {
+    if self.get_state() == State::Paused || self.clearing_price != 0 {
+      return err!(errors::Sale::RoundInvalidTransition);
+    }
+
+    if self.total_sold >= self.total_supply {
+      return err!(errors::Sale::RoundSupplyExceeded);
     }
 
-    self.state = State::Opened;
+    self.set_state(State::Open)
+  }
 
-    Ok(())
+  pub fn set_paused(
+    &mut self,
+  ) -> Result<State> {
+    self.set_state(State::Paused)
+  }
+
+  pub fn set_resumed(
+    &mut self,
+  ) -> Result<State> {
+    if self.get_state() != State::Paused {
+      return err!(errors::Sale::RoundInvalidTransition);
+    }
+
+    self.set_state(State::Open)
   }
 
   pub fn set_close(
     &mut self,
-  ) -> Result<()> {
-    if self.state != State::Opened {
-      return err!(errors::Sale::RoundClosed);
+  ) -> Result<State> {
+    let previous = self.set_state(State::Closed)?;
+
+    // Uniform clearing settles everyone at the lowest fill price, raised
+    // when needed so the cleared tokens plus buyer bonuses never exceed the
+    // round supply. Every fill is re-credited at that price by
+    // `set_auction_settled`, so only the bonuses stay sold until then.
+    if self.is_uniform_clearing() && self.total_usd > 0 {
+      let clearing_supply = math::sub(self.total_supply, self.bonus_sold)?;
+      let floor = math::mul_div_ceil(self.total_usd, math::pow10(PRECISION)?, clearing_supply)?;
+      self.clearing_price = u64::max(self.last_price, math::to_u64(floor)?);
+      self.total_sold = self.bonus_sold;
     }
 
-    self.state = State::Closed;
+    Ok(previous)
+  }
+
+  pub fn set_cancel(
+    &mut self,
+  ) -> Result<State> {
+    self.set_state(State::Cancelled)
+  }
 
-    Ok(())
+  pub fn activate(
+    &mut self,
+    now: i64,
+  ) -> Result<Option<State>> {
+    if self.get_state() != State::Scheduled || now < self.start_time {
+      return Ok(None);
+    }
+
+    self.set_state(State::Open).map(Some)
+  }
+
+  pub fn update_sold_out(
+    &mut self,
+  ) -> Result<Option<State>> {
+    if self.get_state() != State::Open || self.total_sold < self.total_supply {
+      return Ok(None);
+    }
+
+    self.set_state(State::SoldOut).map(Some)
   }
 
   pub fn set_total_sold(
     &mut self,
     total_sold: u128,
   ) -> Result<()> {
-    self.total_sold += total_sold;
+    self.total_sold = math::add(self.total_sold, total_sold)?;
 
     Ok(())
   }
 
   pub fn get_id(
-    &mut self,
+    &self,
   ) -> i16 {
     self.id
   }
 
   pub fn get_price(
-    &mut self,
+    &self,
   ) -> u64 {
     self.price
   }
 
   pub fn get_total_sold(
-    &mut self,
+    &self,
   ) -> u128 {
     self.total_sold
   }
 
   pub fn get_total_supply(
-    &mut self,
+    &self,
   ) -> u128 {
     self.total_supply
   }
 
+  pub fn get_pricing(
+    &self,
+  ) -> (PriceMode, u64, u64, u16) {
+    (self.get_price_mode(), self.price, self.end_price, self.price_steps)
+  }
+
+  pub fn get_clearing_price(
+    &self,
+  ) -> u64 {
+    self.clearing_price
+  }
+
+  pub fn is_uniform_clearing(
+    &self,
+  ) -> bool {
+    self.get_price_mode() == PriceMode::Dutch && self.uniform_clearing != 0
+  }
+
+  // A closed uniform-clearing round stays live until every purchase has
+  // been settled at the clearing price, and any round until its shards have
+  // been rolled up.
+  pub fn is_finalised(
+    &self,
+  ) -> bool {
+    match self.get_state() {
+      State::Cancelled => self.shard_reserved == 0,
+      State::Closed => self.shard_reserved == 0 && (!self.is_uniform_clearing() || self.settled_usd >= self.total_usd),
+      _ => false,
+    }
+  }
+
+  pub fn get_state(
+    &self,
+  ) -> State {
+    match self.state {
+      1 => State::Open,
+      2 => State::Closed,
+      3 => State::Scheduled,
+      4 => State::Paused,
+      5 => State::SoldOut,
+      6 => State::Cancelled,
+      _ => State::Draft,
+    }
+  }
+
+  pub fn get_price_mode(
+    &self,
+  ) -> PriceMode {
+    match self.price_mode {
+      1 => PriceMode::Linear,
+      2 => PriceMode::Step,
+      3 => PriceMode::Dutch,
+      _ => PriceMode::Fixed,
+    }
+  }
+
+  pub fn get_decay_curve(
+    &self,
+  ) -> DecayCurve {
+    match self.decay_curve {
+      1 => DecayCurve::Quadratic,
+      _ => DecayCurve::Linear,
+    }
+  }
+
+  pub fn get_start_time(
+    &self,
+  ) -> i64 {
+    self.start_time
+  }
+
   pub fn is_open(
     &self,
   ) -> bool {
-    self.state == State::Opened
+    self.get_state() == State::Open
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bytemuck::Zeroable;
+  use crate::state::LEGACY_ACCOUNT_SPACE;
+  use crate::state::beneficiary::Beneficiary;
+
+  fn buy(
+    round: &mut Round,
+    usd_amount: u128,
+    bonus_share: u128,
+    now: i64,
+  ) -> u128 {
+    let token_amount = round.quote(usd_amount, now).unwrap();
+    let bonus_amount = token_amount * bonus_share / 100;
+    round.set_total_sold(token_amount + bonus_amount).unwrap();
+    round.set_purchase(usd_amount, bonus_amount, now).unwrap();
+    bonus_amount
   }
-}
\ No newline at end of file
+
+  fn bid(
+    round: &mut Round,
+    usd_amount: u128,
+    bonus_share: u128,
+    now: i64,
+  ) -> Beneficiary {
+    let mut beneficiary = Beneficiary::deserialize(&mut &vec![0u8; Beneficiary::INIT_SPACE][..]).unwrap();
+    let token_amount = round.quote(usd_amount, now).unwrap();
+    let bonus_amount = buy(round, usd_amount, bonus_share, now);
+    beneficiary.set_token_amount(token_amount + bonus_amount).unwrap();
+    beneficiary.set_auction_purchase(round.get_id(), usd_amount, token_amount).unwrap();
+    beneficiary
+  }
+
+  #[test]
+  fn uniform_clearing_leaves_room_for_buyer_bonus() {
+    let mut round = Round::zeroed();
+    round.init(1, 2_000_000_000, 1_000_000).unwrap();
+    round.set_auction(2_000_000_000, 1_000_000_000, 0, 100, DecayCurve::Linear, true).unwrap();
+    round.set_open().unwrap();
+
+    let mut buyers = [bid(&mut round, 1_000_000, 10, 0), bid(&mut round, 400_000, 10, 100)];
+    assert_eq!(round.get_total_sold(), 990_000);
+
+    round.set_close().unwrap();
+    let clearing_price = round.get_clearing_price();
+    for beneficiary in buyers.iter_mut() {
+      let (_, cleared_amount, usd_amount) = beneficiary.settle_auction(1, clearing_price).unwrap();
+      round.set_auction_settled(cleared_amount, usd_amount).unwrap();
+    }
+
+    // The early buyer is raised to the clearing price, the late one gives
+    // back what it bought below it.
+    assert_eq!(buyers[0].get_token_amount(), 649_999 + 50_000);
+    assert_eq!(buyers[1].get_token_amount(), 259_999 + 40_000);
+    let credited_amount: u128 = buyers.iter_mut().map(|b| b.get_token_amount()).sum();
+    assert!(credited_amount <= round.get_total_supply());
+    assert_eq!(round.get_total_sold(), credited_amount);
+  }
+
+  #[test]
+  fn auction_settlement_rejects_oversell() {
+    let mut round = Round::zeroed();
+    round.init(1, 2_000_000_000, 1_000_000).unwrap();
+    round.set_auction(2_000_000_000, 1_000_000_000, 0, 100, DecayCurve::Linear, true).unwrap();
+    round.set_open().unwrap();
+    buy(&mut round, 1_000_000, 10, 0);
+    round.set_close().unwrap();
+
+    assert_eq!(round.set_auction_settled(950_001, 1_000_000).unwrap_err(), errors::Sale::RoundSupplyExceeded.into());
+    round.set_auction_settled(950_000, 1_000_000).unwrap();
+    assert_eq!(round.get_total_sold(), round.get_total_supply());
+  }
+
+  #[test]
+  fn shard_grants_split_the_unreserved_supply() {
+    let mut round = Round::zeroed();
+    round.init(1, 1_000_000_000, 1_000).unwrap();
+    round.set_shard_count(4).unwrap();
+    assert_eq!(round.get_shard_grant().unwrap(), 0);
+
+    round.set_open().unwrap();
+    round.set_total_sold(200).unwrap();
+    assert_eq!(round.get_shard_grant().unwrap(), 200);
+
+    round.set_shard_reserved(0, 200).unwrap();
+    assert_eq!(round.get_available_supply(), 600);
+    assert_eq!(round.get_shard_grant().unwrap(), 150);
+
+    round.set_shard_reserved(200, 0).unwrap();
+    assert_eq!(round.get_shard_reserved(), 0);
+    assert!(round.set_shard_reserved(1, 0).is_err());
+  }
+
+  #[test]
+  fn reserved_shard_capacity_holds_the_supply() {
+    let mut round = Round::zeroed();
+    round.init(1, 1_000_000_000, 1_000).unwrap();
+    round.set_total_sold(300).unwrap();
+    round.set_shard_reserved(0, 500).unwrap();
+
+    assert_eq!(round.set_total_supply(799).unwrap_err(), errors::Sale::RoundSupplyTooSmall.into());
+    round.set_total_supply(800).unwrap();
+    assert_eq!(round.get_available_supply(), 0);
+  }
+
+  #[test]
+  fn round_is_not_finalised_with_reserved_shards() {
+    let mut round = Round::zeroed();
+    round.init(1, 1_000_000_000, 1_000).unwrap();
+    round.set_open().unwrap();
+    round.set_shard_reserved(0, 100).unwrap();
+    round.set_close().unwrap();
+    assert!(!round.is_finalised());
+
+    round.set_shard_reserved(100, 0).unwrap();
+    assert!(round.is_finalised());
+  }
+
+  #[test]
+  fn auction_is_scheduled_at_its_start() {
+    let mut round = Round::zeroed();
+    round.init(1, 2_000_000_000, 1_000_000).unwrap();
+    round.set_auction(2_000_000_000, 1_000_000_000, 50, 100, DecayCurve::Linear, false).unwrap();
+
+    assert_eq!(round.set_scheduled(10).unwrap_err(), errors::Sale::RoundScheduleConflict.into());
+    round.set_scheduled(50).unwrap();
+    assert_eq!(round.get_start_time(), 50);
+  }
+
+  #[test]
+  fn migrates_legacy_layout() {
+    let legacy = LegacyRound {
+      id: 1,
+      price: 2,
+      total_sold: 3,
+      total_supply: 4,
+      state: State::Paused,
+      start_time: 5,
+      price_mode: PriceMode::Dutch,
+      end_price: 6,
+      price_steps: 7,
+      end_time: 8,
+      decay_curve: DecayCurve::Quadratic,
+      uniform_clearing: true,
+      total_usd: 9,
+      last_price: 10,
+      clearing_price: 11,
+      settled_usd: 12,
+      version: 0,
+      _reserved: [0; 64],
+    };
+
+    // Legacy accounts were allocated 680 bytes, discriminator included.
+    let mut data = legacy.try_to_vec().unwrap();
+    assert_eq!(data.len(), LegacyRound::INIT_SPACE);
+    data.resize(LEGACY_ACCOUNT_SPACE - 8, 0);
+    let legacy = LegacyRound::deserialize(&mut &data[..]).unwrap();
+
+    let mut round = Round::zeroed();
+    assert_eq!(round.migrate(&legacy).unwrap(), LEGACY_ACCOUNT_VERSION);
+
+    assert_eq!(round.id, legacy.id);
+    assert_eq!(round.price, legacy.price);
+    assert_eq!(round.total_sold, legacy.total_sold);
+    assert_eq!(round.total_supply, legacy.total_supply);
+    assert_eq!(round.get_state(), legacy.state);
+    assert_eq!(round.start_time, legacy.start_time);
+    assert_eq!(round.get_price_mode(), legacy.price_mode);
+    assert_eq!(round.end_price, legacy.end_price);
+    assert_eq!(round.price_steps, legacy.price_steps);
+    assert_eq!(round.end_time, legacy.end_time);
+    assert_eq!(round.get_decay_curve(), legacy.decay_curve);
+    assert_eq!(round.uniform_clearing, u8::from(legacy.uniform_clearing));
+    assert_eq!(round.total_usd, legacy.total_usd);
+    assert_eq!(round.last_price, legacy.last_price);
+    assert_eq!(round.clearing_price, legacy.clearing_price);
+    assert_eq!(round.settled_usd, legacy.settled_usd);
+    assert_eq!(round.shard_count, 0);
+    assert_eq!(round.shard_reserved, 0);
+    assert_eq!(round.bonus_sold, 0);
+    assert_eq!(round.version, ROUND_VERSION);
+  }
+}
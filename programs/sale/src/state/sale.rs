@@ -1,28 +1,122 @@
 use anchor_lang::prelude::*;
+use crate::state::LEGACY_ACCOUNT_VERSION;
+use crate::state::legacy::LegacySale;
 use crate::errors;
+use crate::math;
 
-pub const MAX_INVESTMENT: u64 = 1000_000_000_000_000; 
+// Version 3 is the zero-copy layout.
+pub const SALE_VERSION: u8 = 3;
+
+pub const MAX_INVESTMENT: u64 = 1_000_000_000_000_000; 
 pub const MIN_INVESTMENT: u64 = 100_000_000_000;
 pub const MAIN_REWARD: u64 = 50_000_000;
 pub const SECONDARY_REWARD: u64 = 50_000_000;
+pub const UPLINE_REWARD: u64 = 20_000_000;
+
+pub const MAX_REFERRAL_DEPTH: u8 = 3;
+pub const MAX_UPLINE_LEVELS: usize = 2;
+pub const MAX_REWARD_TIERS: usize = 5;
 
-#[derive(Clone, PartialEq, AnchorDeserialize, AnchorSerialize)]
+pub const PAUSE_DEPOSIT: u8 = 1 << 0;
+pub const PAUSE_WITHDRAW: u8 = 1 << 1;
+pub const PAUSE_CLAIM: u8 = 1 << 2;
+pub const PAUSE_ALL: u8 = PAUSE_DEPOSIT | PAUSE_WITHDRAW | PAUSE_CLAIM;
+
+#[derive(Clone, Debug, PartialEq, InitSpace, AnchorDeserialize, AnchorSerialize)]
 pub enum State {
   None,
   Opened,
   Closed,
 }
 
-#[account]
+// How the referrer's `main_reward` cut is paid: in the deposited asset, as
+// bonus sale tokens, or split between the two by `cash_share`.
+#[derive(Clone, Copy, Default, PartialEq, InitSpace, AnchorDeserialize, AnchorSerialize)]
+pub enum RewardMode {
+  #[default]
+  Cash,
+  Tokens,
+  Split,
+}
+
+// A tier applies once a referrer's cumulative referred volume reaches
+// `min_usd`, until the next tier's threshold.
+#[derive(Clone, Copy, Default, PartialEq, InitSpace, AnchorDeserialize, AnchorSerialize)]
+pub struct RewardTier {
+  pub min_usd: u64,
+  pub reward: u64,
+}
+
+// Everything an admin can change on the sale, plus the running counters,
+// as carried by `SaleConfigChangedEvent`.
+#[derive(Clone, AnchorDeserialize, AnchorSerialize)]
+pub struct SaleConfig {
+  pub state: State,
+  pub round: i16,
+  pub paused: u8,
+  pub pause_reason: u8,
+  pub max_investment: u64,
+  pub min_investment: u64,
+  pub main_reward: u64,
+  pub secondary_reward: u64,
+  pub referral_depth: u8,
+  pub upline_rewards: [u64; MAX_UPLINE_LEVELS],
+  pub reward_tiers: Vec<RewardTier>,
+  pub referrer_cap_usd: u64,
+  pub budget_usd: u64,
+  pub budget_tokens: u128,
+  pub reward_mode: RewardMode,
+  pub cash_share: u64,
+  pub buyer_bonus: u64,
+  pub bind_referrer: bool,
+  pub min_referral_usd: u64,
+  pub referrals_enabled: bool,
+  pub reject_disabled_referral: bool,
+  pub token_mint: Pubkey,
+  pub claim_start: i64,
+  pub vesting_duration: i64,
+  pub total_sold: u128,
+  pub spent_usd: u128,
+  pub spent_tokens: u128,
+}
+
+// Fields are ordered by alignment so the `repr(C)` layout has no implicit
+// padding; enums and flags are stored as `u8`.
+#[account(zero_copy)]
 pub struct Sale {
+  total_sold: u128,
+  budget_tokens: u128,
+  spent_usd: u128,
+  spent_tokens: u128,
+  claimed_tokens: u128,
   max_investment: u64,
   min_investment: u64,
   main_reward: u64,
   secondary_reward: u64,
-  total_sold: u128,
+  min_referral_usd: u64,
+  referrer_cap_usd: u64,
+  budget_usd: u64,
+  cash_share: u64,
+  buyer_bonus: u64,
+  upline_rewards: [u64; MAX_UPLINE_LEVELS],
+  tier_min_usd: [u64; MAX_REWARD_TIERS],
+  tier_rewards: [u64; MAX_REWARD_TIERS],
+  claim_start: i64,
+  vesting_duration: i64,
+  token_mint: Pubkey,
   round: i16,
-  state: State,
-  enabled: bool,
+  state: u8,
+  enabled: u8,
+  paused: u8,
+  pause_reason: u8,
+  referral_depth: u8,
+  bind_referrer: u8,
+  tier_count: u8,
+  reward_mode: u8,
+  referrals_disabled: u8,
+  reject_disabled_referral: u8,
+  version: u8,
+  _reserved: [u8; 59],
 }
 
 impl Sale {
@@ -35,8 +129,237 @@ impl Sale {
     self.main_reward = MAIN_REWARD;
     self.secondary_reward = SECONDARY_REWARD;
     self.total_sold = 0;
-    self.state = State::None;
-    self.enabled = true;
+    self.state = State::None as u8;
+    self.enabled = 1;
+    self.paused = 0;
+    self.pause_reason = 0;
+    self.referral_depth = 2;
+    self.upline_rewards = [UPLINE_REWARD, 0];
+    self.token_mint = Pubkey::default();
+    self.claim_start = 0;
+    self.vesting_duration = 0;
+    self.bind_referrer = 0;
+    self.min_referral_usd = 0;
+    self.tier_count = 0;
+    self.tier_min_usd = [0; MAX_REWARD_TIERS];
+    self.tier_rewards = [0; MAX_REWARD_TIERS];
+    self.referrer_cap_usd = 0;
+    self.budget_usd = 0;
+    self.budget_tokens = 0;
+    self.spent_usd = 0;
+    self.spent_tokens = 0;
+    self.claimed_tokens = 0;
+    self.reward_mode = RewardMode::Cash as u8;
+    self.cash_share = 0;
+    self.buyer_bonus = 0;
+    self.referrals_disabled = 0;
+    self.reject_disabled_referral = 0;
+    self.version = SALE_VERSION;
+
+    Ok(())
+  }
+
+  // Copies an account written with the Borsh layout into this one and
+  // returns the version it was migrated from.
+  pub fn migrate(
+    &mut self,
+    legacy: &LegacySale,
+  ) -> Result<u8> {
+    self.total_sold = legacy.total_sold;
+    self.budget_tokens = legacy.budget_tokens;
+    self.spent_usd = legacy.spent_usd;
+    self.spent_tokens = legacy.spent_tokens;
+    // Claims made before the counter existed are unknown, so a migrated sale
+    // that already paid out is never closable.
+    self.claimed_tokens = 0;
+    self.max_investment = legacy.max_investment;
+    self.min_investment = legacy.min_investment;
+    self.main_reward = legacy.main_reward;
+    self.secondary_reward = legacy.secondary_reward;
+    self.min_referral_usd = legacy.min_referral_usd;
+    self.referrer_cap_usd = legacy.referrer_cap_usd;
+    self.budget_usd = legacy.budget_usd;
+    self.cash_share = legacy.cash_share;
+    self.buyer_bonus = legacy.buyer_bonus;
+    self.upline_rewards = legacy.upline_rewards;
+    for (index, tier) in legacy.reward_tiers.iter().enumerate() {
+      self.tier_min_usd[index] = tier.min_usd;
+      self.tier_rewards[index] = tier.reward;
+    }
+    self.claim_start = legacy.claim_start;
+    self.vesting_duration = legacy.vesting_duration;
+    self.token_mint = legacy.token_mint;
+    self.round = legacy.round;
+    self.state = legacy.state.clone() as u8;
+    self.enabled = u8::from(legacy.enabled);
+    self.paused = legacy.paused;
+    self.pause_reason = legacy.pause_reason;
+    self.referral_depth = legacy.referral_depth;
+    self.bind_referrer = u8::from(legacy.bind_referrer);
+    self.tier_count = legacy.tier_count;
+    self.reward_mode = legacy.reward_mode as u8;
+    self.referrals_disabled = u8::from(legacy.referrals_disabled);
+    self.reject_disabled_referral = u8::from(legacy.reject_disabled_referral);
+    self.version = SALE_VERSION;
+
+    Ok(u8::max(legacy.version, LEGACY_ACCOUNT_VERSION))
+  }
+
+  pub fn set_version(
+    &mut self,
+  ) -> Result<u8> {
+    let previous = self.get_version();
+    self.version = SALE_VERSION;
+
+    Ok(previous)
+  }
+
+  pub fn get_version(
+    &self,
+  ) -> u8 {
+    u8::max(self.version, LEGACY_ACCOUNT_VERSION)
+  }
+
+  pub fn get_config(
+    &self,
+  ) -> SaleConfig {
+    let reward_tiers = (0..usize::from(self.tier_count))
+      .map(|index| RewardTier { min_usd: self.tier_min_usd[index], reward: self.tier_rewards[index] })
+      .collect();
+
+    SaleConfig {
+      state: self.get_state(),
+      round: self.round,
+      paused: self.paused,
+      pause_reason: self.pause_reason,
+      max_investment: self.max_investment,
+      min_investment: self.min_investment,
+      main_reward: self.main_reward,
+      secondary_reward: self.secondary_reward,
+      referral_depth: self.get_referral_depth(),
+      upline_rewards: self.upline_rewards,
+      reward_tiers,
+      referrer_cap_usd: self.referrer_cap_usd,
+      budget_usd: self.budget_usd,
+      budget_tokens: self.budget_tokens,
+      reward_mode: self.get_reward_mode(),
+      cash_share: self.cash_share,
+      buyer_bonus: self.buyer_bonus,
+      bind_referrer: self.is_referrer_bound(),
+      min_referral_usd: self.min_referral_usd,
+      referrals_enabled: self.is_referral_program_enabled(),
+      reject_disabled_referral: self.is_rejecting_disabled_referral(),
+      token_mint: self.token_mint,
+      claim_start: self.claim_start,
+      vesting_duration: self.vesting_duration,
+      total_sold: self.total_sold,
+      spent_usd: self.spent_usd,
+      spent_tokens: self.spent_tokens,
+    }
+  }
+
+  pub fn set_referral_program(
+    &mut self,
+    enabled: bool,
+    reject_disabled_referral: bool,
+  ) -> Result<()> {
+    self.referrals_disabled = u8::from(!enabled);
+    self.reject_disabled_referral = u8::from(reject_disabled_referral);
+
+    Ok(())
+  }
+
+  pub fn set_buyer_bonus(
+    &mut self,
+    buyer_bonus: u64,
+  ) -> Result<()> {
+    if buyer_bonus > 1_000_000_000 {
+      return err!(errors::Sale::BuyerBonusInvalid);
+    }
+
+    self.buyer_bonus = buyer_bonus;
+
+    Ok(())
+  }
+
+  pub fn set_reward_mode(
+    &mut self,
+    reward_mode: RewardMode,
+    cash_share: u64,
+  ) -> Result<()> {
+    if cash_share > 1_000_000_000 {
+      return err!(errors::Sale::SaleRewardModeInvalid);
+    }
+
+    self.reward_mode = reward_mode as u8;
+    self.cash_share = cash_share;
+
+    Ok(())
+  }
+
+  // A zero cap or budget means unlimited.
+  pub fn set_referral_budget(
+    &mut self,
+    referrer_cap_usd: u64,
+    budget_usd: u64,
+    budget_tokens: u128,
+  ) -> Result<()> {
+    self.referrer_cap_usd = referrer_cap_usd;
+    self.budget_usd = budget_usd;
+    self.budget_tokens = budget_tokens;
+
+    Ok(())
+  }
+
+  pub fn set_referral_spent(
+    &mut self,
+    usd_amount: u128,
+    token_amount: u128,
+  ) -> Result<()> {
+    self.spent_usd = math::add(self.spent_usd, usd_amount)?;
+    self.spent_tokens = math::add(self.spent_tokens, token_amount)?;
+
+    Ok(())
+  }
+
+  pub fn set_reward_tiers(
+    &mut self,
+    tiers: Vec<RewardTier>,
+  ) -> Result<()> {
+    if tiers.len() > MAX_REWARD_TIERS {
+      return err!(errors::Sale::SaleRewardTiersInvalid);
+    }
+
+    let upline_reward = get_upline_total(&self.upline_rewards)?;
+    for (index, tier) in tiers.iter().enumerate() {
+      let ordered = match index {
+        0 => tier.min_usd == 0,
+        _ => tier.min_usd > tiers[index - 1].min_usd,
+      };
+
+      if !ordered || math::add(upline_reward, u128::from(tier.reward))? > 1_000_000_000 {
+        return err!(errors::Sale::SaleRewardTiersInvalid);
+      }
+    }
+
+    self.tier_min_usd = [0; MAX_REWARD_TIERS];
+    self.tier_rewards = [0; MAX_REWARD_TIERS];
+    for (index, tier) in tiers.iter().enumerate() {
+      self.tier_min_usd[index] = tier.min_usd;
+      self.tier_rewards[index] = tier.reward;
+    }
+    self.tier_count = tiers.len() as u8;
+
+    Ok(())
+  }
+
+  pub fn set_referral_rules(
+    &mut self,
+    bind_referrer: bool,
+    min_referral_usd: u64,
+  ) -> Result<()> {
+    self.bind_referrer = u8::from(bind_referrer);
+    self.min_referral_usd = min_referral_usd;
 
     Ok(())
   }
@@ -61,28 +384,74 @@ impl Sale {
     main_reward: u64,
     secondary_reward: u64,
   ) -> Result<()> {
-    if main_reward > 1000_000_000 {
+    if main_reward > 1_000_000_000 {
       return err!(errors::Sale::SaleMainRefRewardTooLarge);
     }
 
-    if secondary_reward > 1000_000_000 {
+    if secondary_reward > 1_000_000_000 {
       return err!(errors::Sale::SaleSecondaryRefRewardTooLarge);
     }
 
+    if main_reward > self.get_max_main_reward()? {
+      return err!(errors::Sale::SaleMainRefRewardTooLarge);
+    }
+
     self.main_reward = main_reward;
     self.secondary_reward = secondary_reward;
 
     Ok(())
   }
 
+  pub fn set_referral_levels(
+    &mut self,
+    referral_depth: u8,
+    upline_rewards: [u64; MAX_UPLINE_LEVELS],
+  ) -> Result<()> {
+    if referral_depth == 0 || referral_depth > MAX_REFERRAL_DEPTH {
+      return err!(errors::Sale::SaleRefLevelsInvalid);
+    }
+
+    // Volume tiers replace the sale-wide main reward, so the largest one
+    // must leave room for the uplines as well.
+    let main_reward = self.tier_rewards[..usize::from(self.tier_count)]
+      .iter()
+      .fold(self.main_reward, |max, reward| u64::max(max, *reward));
+    let total_reward = math::add(u128::from(main_reward), get_upline_total(&upline_rewards)?)?;
+    if total_reward > 1_000_000_000 {
+      return err!(errors::Sale::SaleRefLevelsInvalid);
+    }
+
+    self.referral_depth = referral_depth;
+    self.upline_rewards = upline_rewards;
+
+    Ok(())
+  }
+
+  pub fn set_claim(
+    &mut self,
+    token_mint: Pubkey,
+    claim_start: i64,
+    vesting_duration: i64,
+  ) -> Result<()> {
+    if claim_start <= 0 || vesting_duration < 0 {
+      return err!(errors::Sale::SaleClaimInvalid);
+    }
+
+    self.token_mint = token_mint;
+    self.claim_start = claim_start;
+    self.vesting_duration = vesting_duration;
+
+    Ok(())
+  }
+
   pub fn set_open(
     &mut self,
   ) -> Result<()> {
-    if self.state == State::Opened || self.state == State::Closed {
+    if self.get_state() != State::None {
       return err!(errors::Sale::SaleOpened);
     }
 
-    self.state = State::Opened;
+    self.state = State::Opened as u8;
 
     Ok(())
   }
@@ -90,11 +459,44 @@ impl Sale {
   pub fn set_close(
     &mut self,
   ) -> Result<()> {
-    if self.state != State::Opened {
+    if self.get_state() != State::Opened {
       return err!(errors::Sale::SaleClosed);
     }
 
-    self.state = State::Closed;
+    self.state = State::Closed as u8;
+
+    Ok(())
+  }
+
+  pub fn set_paused(
+    &mut self,
+    scope: u8,
+    reason: u8,
+  ) -> Result<()> {
+    if scope == 0 || scope & !PAUSE_ALL != 0 {
+      return err!(errors::Sale::InvalidPauseScope);
+    }
+
+    self.paused |= scope;
+    self.pause_reason = reason;
+    self.enabled = 0;
+
+    Ok(())
+  }
+
+  pub fn set_unpaused(
+    &mut self,
+    scope: u8,
+  ) -> Result<()> {
+    if scope == 0 || scope & !PAUSE_ALL != 0 {
+      return err!(errors::Sale::InvalidPauseScope);
+    }
+
+    self.paused &= !scope;
+    if self.paused == 0 {
+      self.pause_reason = 0;
+      self.enabled = 1;
+    }
 
     Ok(())
   }
@@ -112,7 +514,36 @@ impl Sale {
     &mut self,
     total_sold: u128,
   ) -> Result<()> {
-    self.total_sold += total_sold;
+    self.total_sold = math::add(self.total_sold, total_sold)?;
+
+    Ok(())
+  }
+
+  // Counts tokens that left the sale's books, claimed or forfeited.
+  pub fn set_claimed_tokens(
+    &mut self,
+    token_amount: u128,
+  ) -> Result<()> {
+    self.claimed_tokens = math::add(self.claimed_tokens, token_amount)?;
+
+    Ok(())
+  }
+
+  // Tokens sold and referral token rewards stay claimable after the vault
+  // runs dry, so the sale is kept until they are all accounted for.
+  pub fn has_unclaimed_tokens(
+    &self,
+  ) -> bool {
+    self.claimed_tokens < self.total_sold.saturating_add(self.spent_tokens)
+  }
+
+  // Swaps a settled auction fill for the amount it cleared at.
+  pub fn set_auction_settled(
+    &mut self,
+    bought_amount: u128,
+    cleared_amount: u128,
+  ) -> Result<()> {
+    self.total_sold = math::add(math::sub(self.total_sold, bought_amount)?, cleared_amount)?;
 
     Ok(())
   }
@@ -141,10 +572,49 @@ impl Sale {
     self.total_sold
   }
 
+  pub fn get_state(
+    &self,
+  ) -> State {
+    match self.state {
+      1 => State::Opened,
+      2 => State::Closed,
+      _ => State::None,
+    }
+  }
+
+  pub fn get_reward_mode(
+    &self,
+  ) -> RewardMode {
+    match self.reward_mode {
+      1 => RewardMode::Tokens,
+      2 => RewardMode::Split,
+      _ => RewardMode::Cash,
+    }
+  }
+
   pub fn is_open(
     &self,
   ) -> bool {
-    self.state == State::Opened
+    self.get_state() == State::Opened
+  }
+
+  pub fn is_closed(
+    &self,
+  ) -> bool {
+    self.get_state() == State::Closed
+  }
+
+  pub fn is_paused(
+    &self,
+    scope: u8,
+  ) -> bool {
+    self.enabled == 0 && self.paused & scope != 0
+  }
+
+  pub fn get_paused(
+    &self,
+  ) -> (u8, u8) {
+    (self.paused, self.pause_reason)
   }
 
   pub fn get_reward(
@@ -152,4 +622,282 @@ impl Sale {
   ) -> (u64, u64) {
     (self.main_reward, self.secondary_reward)
   }
+
+  pub fn get_token_mint(
+    &self,
+  ) -> Pubkey {
+    self.token_mint
+  }
+
+  // Linear vesting from `claim_start`, rounded down.
+  pub fn get_vested_amount(
+    &self,
+    total_amount: u128,
+    now: i64,
+  ) -> Result<u128> {
+    if self.claim_start == 0 || now < self.claim_start {
+      return err!(errors::Sale::ClaimNotStarted);
+    }
+
+    let elapsed = math::elapsed(self.claim_start, now)?;
+    let vesting_duration = math::elapsed(0, self.vesting_duration)?;
+    if vesting_duration == 0 || elapsed >= vesting_duration {
+      return Ok(total_amount);
+    }
+
+    math::mul_div_floor(total_amount, elapsed, vesting_duration)
+  }
+
+  pub fn is_referrer_bound(
+    &self,
+  ) -> bool {
+    self.bind_referrer != 0
+  }
+
+  pub fn get_min_referral_usd(
+    &self,
+  ) -> u128 {
+    u128::from(self.min_referral_usd)
+  }
+
+  // Share of the main reward paid in the deposited asset, scaled by 1e9. A
+  // referral-level mode overrides the sale-wide one.
+  pub fn get_cash_share(
+    &self,
+    referral_mode: Option<RewardMode>,
+  ) -> u64 {
+    match referral_mode.unwrap_or(self.get_reward_mode()) {
+      RewardMode::Cash => 1_000_000_000,
+      RewardMode::Tokens => 0,
+      RewardMode::Split => self.cash_share,
+    }
+  }
+
+  pub fn is_referral_program_enabled(
+    &self,
+  ) -> bool {
+    self.referrals_disabled == 0
+  }
+
+  pub fn is_rejecting_disabled_referral(
+    &self,
+  ) -> bool {
+    self.reject_disabled_referral != 0
+  }
+
+  pub fn get_buyer_bonus(
+    &self,
+  ) -> u64 {
+    self.buyer_bonus
+  }
+
+  pub fn get_remaining_reward_usd(
+    &self,
+    referrer_reward_usd: u128,
+  ) -> u128 {
+    let referrer_left = match self.referrer_cap_usd {
+      0 => u128::MAX,
+      cap => u128::from(cap).saturating_sub(referrer_reward_usd),
+    };
+    let budget_left = match self.budget_usd {
+      0 => u128::MAX,
+      budget => u128::from(budget).saturating_sub(self.spent_usd),
+    };
+
+    u128::min(referrer_left, budget_left)
+  }
+
+  pub fn get_remaining_reward_tokens(
+    &self,
+  ) -> u128 {
+    match self.budget_tokens {
+      0 => u128::MAX,
+      budget => budget.saturating_sub(self.spent_tokens),
+    }
+  }
+
+  pub fn has_referral_budget(
+    &self,
+  ) -> bool {
+    self.budget_usd != 0 || self.budget_tokens != 0
+  }
+
+  pub fn get_referral_spent(
+    &self,
+  ) -> (u128, u128) {
+    (self.spent_usd, self.spent_tokens)
+  }
+
+  pub fn get_tier(
+    &self,
+    referred_usd: u128,
+  ) -> Option<(u8, u64)> {
+    self.tier_min_usd[..usize::from(self.tier_count)]
+      .iter()
+      .rposition(|min_usd| u128::from(*min_usd) <= referred_usd)
+      .map(|index| (index as u8, self.tier_rewards[index]))
+  }
+
+  // The largest main reward that still leaves room for every upline level.
+  pub fn get_max_main_reward(
+    &self,
+  ) -> Result<u64> {
+    let upline_reward = get_upline_total(&self.upline_rewards)?;
+
+    math::to_u64(1_000_000_000u128.saturating_sub(upline_reward))
+  }
+
+  pub fn get_referral_depth(
+    &self,
+  ) -> u8 {
+    u8::max(self.referral_depth, 1)
+  }
+
+  // Level 1 is the direct referrer, paid through `get_reward`.
+  pub fn get_upline_reward(
+    &self,
+    level: u8,
+  ) -> u64 {
+    if level < 2 || level > self.get_referral_depth() {
+      return 0;
+    }
+
+    self.upline_rewards[usize::from(level - 2)]
+  }
+}
+
+fn get_upline_total(
+  upline_rewards: &[u64; MAX_UPLINE_LEVELS],
+) -> Result<u128> {
+  let mut total_reward = 0u128;
+  for reward in upline_rewards {
+    total_reward = math::add(total_reward, u128::from(*reward))?;
+  }
+
+  Ok(total_reward)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bytemuck::Zeroable;
+  use crate::state::LEGACY_ACCOUNT_SPACE;
+
+  #[test]
+  fn sale_keeps_unclaimed_tokens() {
+    let mut sale = Sale::zeroed();
+    assert!(!sale.has_unclaimed_tokens());
+
+    sale.set_total_sold(1_000).unwrap();
+    sale.set_referral_spent(0, 50).unwrap();
+    sale.set_claimed_tokens(1_000).unwrap();
+    assert!(sale.has_unclaimed_tokens());
+
+    // Forfeited referral rewards are booked like claims.
+    sale.set_claimed_tokens(50).unwrap();
+    assert!(!sale.has_unclaimed_tokens());
+  }
+
+  #[test]
+  fn referral_levels_leave_room_for_the_largest_tier() {
+    let mut sale = Sale::zeroed();
+    sale.set_reward(100_000_000, 0).unwrap();
+    sale.set_reward_tiers(vec![
+      RewardTier { min_usd: 0, reward: 100_000_000 },
+      RewardTier { min_usd: 1_000, reward: 600_000_000 },
+    ]).unwrap();
+
+    let upline_rewards = [300_000_000, 200_000_000];
+    assert_eq!(sale.set_referral_levels(3, upline_rewards).unwrap_err(), errors::Sale::SaleRefLevelsInvalid.into());
+
+    sale.set_referral_levels(3, [300_000_000, 100_000_000]).unwrap();
+    assert_eq!(sale.get_max_main_reward().unwrap(), 600_000_000);
+    assert_eq!(sale.set_reward(600_000_001, 0).unwrap_err(), errors::Sale::SaleMainRefRewardTooLarge.into());
+  }
+
+  #[test]
+  fn migrates_legacy_layout() {
+    let legacy = LegacySale {
+      max_investment: 1,
+      min_investment: 2,
+      main_reward: 3,
+      secondary_reward: 4,
+      total_sold: 5,
+      round: 6,
+      state: State::Opened,
+      enabled: true,
+      paused: PAUSE_CLAIM,
+      pause_reason: 7,
+      referral_depth: 3,
+      upline_rewards: [8, 9],
+      token_mint: Pubkey::new_unique(),
+      claim_start: 10,
+      vesting_duration: 11,
+      bind_referrer: true,
+      min_referral_usd: 12,
+      tier_count: 2,
+      reward_tiers: [
+        RewardTier { min_usd: 13, reward: 14 },
+        RewardTier { min_usd: 15, reward: 16 },
+        RewardTier::default(),
+        RewardTier::default(),
+        RewardTier::default(),
+      ],
+      referrer_cap_usd: 17,
+      budget_usd: 18,
+      budget_tokens: 19,
+      spent_usd: 20,
+      spent_tokens: 21,
+      reward_mode: RewardMode::Split,
+      cash_share: 22,
+      buyer_bonus: 23,
+      referrals_disabled: true,
+      reject_disabled_referral: true,
+      version: 2,
+      _reserved: [0; 64],
+    };
+
+    // Legacy accounts were allocated 680 bytes, discriminator included.
+    let mut data = legacy.try_to_vec().unwrap();
+    assert_eq!(data.len(), LegacySale::INIT_SPACE);
+    data.resize(LEGACY_ACCOUNT_SPACE - 8, 0);
+    let legacy = LegacySale::deserialize(&mut &data[..]).unwrap();
+
+    let mut sale = Sale::zeroed();
+    assert_eq!(sale.migrate(&legacy).unwrap(), 2);
+
+    assert_eq!(sale.max_investment, legacy.max_investment);
+    assert_eq!(sale.min_investment, legacy.min_investment);
+    assert_eq!(sale.main_reward, legacy.main_reward);
+    assert_eq!(sale.secondary_reward, legacy.secondary_reward);
+    assert_eq!(sale.total_sold, legacy.total_sold);
+    assert_eq!(sale.round, legacy.round);
+    assert_eq!(sale.get_state(), legacy.state);
+    assert_eq!(sale.enabled, u8::from(legacy.enabled));
+    assert_eq!(sale.paused, legacy.paused);
+    assert_eq!(sale.pause_reason, legacy.pause_reason);
+    assert_eq!(sale.referral_depth, legacy.referral_depth);
+    assert_eq!(sale.upline_rewards, legacy.upline_rewards);
+    assert_eq!(sale.token_mint, legacy.token_mint);
+    assert_eq!(sale.claim_start, legacy.claim_start);
+    assert_eq!(sale.vesting_duration, legacy.vesting_duration);
+    assert_eq!(sale.bind_referrer, u8::from(legacy.bind_referrer));
+    assert_eq!(sale.min_referral_usd, legacy.min_referral_usd);
+    assert_eq!(sale.tier_count, legacy.tier_count);
+    for (index, tier) in legacy.reward_tiers.iter().enumerate() {
+      assert_eq!(sale.tier_min_usd[index], tier.min_usd);
+      assert_eq!(sale.tier_rewards[index], tier.reward);
+    }
+    assert_eq!(sale.referrer_cap_usd, legacy.referrer_cap_usd);
+    assert_eq!(sale.budget_usd, legacy.budget_usd);
+    assert_eq!(sale.budget_tokens, legacy.budget_tokens);
+    assert_eq!(sale.spent_usd, legacy.spent_usd);
+    assert_eq!(sale.spent_tokens, legacy.spent_tokens);
+    assert!(sale.get_reward_mode() == legacy.reward_mode);
+    assert_eq!(sale.cash_share, legacy.cash_share);
+    assert_eq!(sale.buyer_bonus, legacy.buyer_bonus);
+    assert_eq!(sale.referrals_disabled, u8::from(legacy.referrals_disabled));
+    assert_eq!(sale.reject_disabled_referral, u8::from(legacy.reject_disabled_referral));
+    assert_eq!(sale.version, SALE_VERSION);
+  }
 }
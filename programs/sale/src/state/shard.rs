@@ -0,0 +1,138 @@
+use anchor_lang::prelude::*;
+use crate::state::ACCOUNT_VERSION;
+use crate::errors;
+use crate::math;
+
+// A write-lock slice of a round. Sharded deposits only touch their shard,
+// selling out of the capacity `settle_shards` granted it; the `pending_*`
+// fields are what the next settle rolls up into the sale and round.
+#[account(zero_copy)]
+pub struct Shard {
+  pending_sold: u128,
+  pending_spent_usd: u128,
+  pending_spent_tokens: u128,
+  capacity: u128,
+  total_sold: u128,
+  total_usd: u128,
+  round: i16,
+  index: u8,
+  version: u8,
+  _reserved: [u8; 60],
+}
+
+impl Shard {
+  pub fn init(
+    &mut self,
+    round: i16,
+    index: u8,
+  ) -> Result<()> {
+    self.round = round;
+    self.index = index;
+    self.pending_sold = 0;
+    self.pending_spent_usd = 0;
+    self.pending_spent_tokens = 0;
+    self.capacity = 0;
+    self.total_sold = 0;
+    self.total_usd = 0;
+    self.version = ACCOUNT_VERSION;
+
+    Ok(())
+  }
+
+  pub fn set_sold(
+    &mut self,
+    token_amount: u128,
+    usd_amount: u128,
+  ) -> Result<()> {
+    if token_amount > self.get_available() {
+      return err!(errors::Sale::ShardCapacityExceeded);
+    }
+
+    self.pending_sold = math::add(self.pending_sold, token_amount)?;
+    self.total_sold = math::add(self.total_sold, token_amount)?;
+    self.total_usd = math::add(self.total_usd, usd_amount)?;
+
+    Ok(())
+  }
+
+  pub fn set_referral_spent(
+    &mut self,
+    usd_amount: u128,
+    token_amount: u128,
+  ) -> Result<()> {
+    self.pending_spent_usd = math::add(self.pending_spent_usd, usd_amount)?;
+    self.pending_spent_tokens = math::add(self.pending_spent_tokens, token_amount)?;
+
+    Ok(())
+  }
+
+  pub fn set_capacity(
+    &mut self,
+    capacity: u128,
+  ) -> Result<()> {
+    self.capacity = capacity;
+
+    Ok(())
+  }
+
+  // Clears the pending counters and the granted capacity, returning
+  // (sold, released capacity, spent usd, spent tokens).
+  pub fn settle(
+    &mut self,
+  ) -> Result<(u128, u128, u128, u128)> {
+    let settled = (self.pending_sold, self.capacity, self.pending_spent_usd, self.pending_spent_tokens);
+    self.pending_sold = 0;
+    self.pending_spent_usd = 0;
+    self.pending_spent_tokens = 0;
+    self.capacity = 0;
+
+    Ok(settled)
+  }
+
+  pub fn get_available(
+    &self,
+  ) -> u128 {
+    self.capacity.saturating_sub(self.pending_sold)
+  }
+
+  pub fn get_round(
+    &self,
+  ) -> i16 {
+    self.round
+  }
+
+  pub fn get_index(
+    &self,
+  ) -> u8 {
+    self.index
+  }
+
+  pub fn get_totals(
+    &self,
+  ) -> (u128, u128) {
+    (self.total_sold, self.total_usd)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bytemuck::Zeroable;
+
+  #[test]
+  fn sells_within_its_capacity() {
+    let mut shard = Shard::zeroed();
+    shard.init(1, 0).unwrap();
+    assert_eq!(shard.set_sold(1, 10).unwrap_err(), errors::Sale::ShardCapacityExceeded.into());
+
+    shard.set_capacity(100).unwrap();
+    shard.set_sold(60, 600).unwrap();
+    assert_eq!(shard.set_sold(41, 410).unwrap_err(), errors::Sale::ShardCapacityExceeded.into());
+    shard.set_sold(40, 400).unwrap();
+    assert_eq!(shard.get_available(), 0);
+
+    assert_eq!(shard.settle().unwrap(), (100, 100, 0, 0));
+    assert_eq!(shard.get_available(), 0);
+    assert_eq!(shard.get_totals(), (100, 1_000));
+  }
+}
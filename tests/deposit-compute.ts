@@ -0,0 +1,309 @@
+import { BN, Program } from "@coral-xyz/anchor";
+import { ACCOUNT_SIZE, TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import {
+  Keypair,
+  LAMPORTS_PER_SOL,
+  PublicKey,
+  SystemProgram,
+  Transaction,
+  TransactionInstruction,
+} from "@solana/web3.js";
+import { BankrunProvider, startAnchor } from "anchor-bankrun";
+import { assert } from "chai";
+import { readFileSync } from "fs";
+import { ProgramTestContext } from "solana-bankrun";
+import { Sale } from "../target/types/sale";
+
+const IDL = require("../target/idl/sale.json");
+
+// Run with `yarn bench`, which builds the program with the `bench` feature
+// so deposits accept a known treasury.
+//
+// Compute units a first deposit may use: it creates the beneficiary,
+// referral, purchase history and receipt accounts, which makes it the most
+// expensive path a buyer hits.
+const CU_CEILINGS = {
+  deposit: 120_000,
+  depositUsdc: 140_000,
+  depositUsdt: 140_000,
+};
+
+const ROUND_ID = 1;
+
+// The program compares against the constants in `auth.rs`, so the
+// benchmark reads them from there instead of duplicating them.
+const AUTH = readFileSync("programs/sale/src/auth.rs", "utf8");
+
+// The benchmark runs against a build with the `bench` feature, so a
+// constant gated on it takes precedence.
+function authConstant(name: string): string {
+  const match =
+    AUTH.match(
+      new RegExp(`feature = "bench"\\)\\]\\s*pub const ${name}: &str = "([^"]*)"`)
+    ) ?? AUTH.match(new RegExp(`const ${name}: &str = "([^"]*)"`));
+  if (!match) {
+    throw new Error(`${name} not found in auth.rs`);
+  }
+  return match[1];
+}
+
+// Seeds are joined with "_" the way the program's `seeds` constraints are.
+function pda(programId: PublicKey, ...parts: (string | Buffer)[]): PublicKey {
+  const seeds: Buffer[] = [];
+  parts.forEach((part, index) => {
+    if (index > 0) {
+      seeds.push(Buffer.from("_"));
+    }
+    seeds.push(Buffer.from(part));
+  });
+  return PublicKey.findProgramAddressSync(seeds, programId)[0];
+}
+
+function u16(value: number): Buffer {
+  const buffer = Buffer.alloc(2);
+  buffer.writeInt16LE(value);
+  return buffer;
+}
+
+function u32(value: number): Buffer {
+  const buffer = Buffer.alloc(4);
+  buffer.writeUInt32LE(value);
+  return buffer;
+}
+
+const normalize = (name: string) => name.replace(/_/g, "").toLowerCase();
+
+const INT_SIZES: Record<string, number> = {
+  u8: 1,
+  i8: 1,
+  u16: 2,
+  i16: 2,
+  u32: 4,
+  i32: 4,
+  u64: 8,
+  i64: 8,
+  u128: 16,
+  i128: 16,
+};
+
+function encodeValue(type: any, value: any): Buffer {
+  if (type === "pubkey") {
+    return (value ?? PublicKey.default).toBuffer();
+  }
+
+  if (type.array) {
+    const [inner, length] = type.array;
+    return Buffer.concat(
+      Array.from({ length }, (_, index) => encodeValue(inner, value?.[index]))
+    );
+  }
+
+  const size = INT_SIZES[type];
+  return new BN(value ?? 0).toTwos(size * 8).toArrayLike(Buffer, "le", size);
+}
+
+// `Sale` and `Round` are zero-copy, so their data is the discriminator
+// followed by every field in IDL order. Fields not in `values` are zeroed.
+function encodeZeroCopy(name: string, values: Record<string, any>): Buffer {
+  const account = IDL.accounts.find(
+    (a) => normalize(a.name) === normalize(name)
+  );
+  const typeDef = IDL.types.find(
+    (t) => normalize(t.name.split("::").pop()) === normalize(name)
+  );
+  const overrides: Record<string, any> = {};
+  for (const key of Object.keys(values)) {
+    overrides[normalize(key)] = values[key];
+  }
+
+  return Buffer.concat([
+    Buffer.from(account.discriminator),
+    ...typeDef.type.fields.map((field) =>
+      encodeValue(field.type, overrides[normalize(field.name)])
+    ),
+  ]);
+}
+
+// An initialized SPL token account with no delegate or close authority.
+function tokenAccount(mint: PublicKey, owner: PublicKey, amount: number) {
+  const data = Buffer.alloc(ACCOUNT_SIZE);
+  mint.toBuffer().copy(data, 0);
+  owner.toBuffer().copy(data, 32);
+  new BN(amount).toArrayLike(Buffer, "le", 8).copy(data, 64);
+  data[108] = 1;
+
+  return {
+    lamports: LAMPORTS_PER_SOL,
+    data,
+    owner: TOKEN_PROGRAM_ID,
+    executable: false,
+  };
+}
+
+describe("deposit compute units", () => {
+  const treasury = new PublicKey(authConstant("TREASURY"));
+  const priceFeed = new PublicKey(authConstant("SOL_USD_PRICEFEED"));
+  const mints = {
+    depositUsdc: new PublicKey(authConstant("USDC")),
+    depositUsdt: new PublicKey(authConstant("USDT")),
+  };
+
+  let context: ProgramTestContext;
+  let program: Program<Sale>;
+  let sale: PublicKey;
+  let round: PublicKey;
+
+  before(async () => {
+    context = await startAnchor(".", [], []);
+    program = new Program<Sale>(IDL, new BankrunProvider(context));
+    sale = pda(program.programId);
+    round = pda(program.programId, "ROUND", u16(ROUND_ID));
+
+    context.setAccount(sale, {
+      lamports: LAMPORTS_PER_SOL,
+      data: encodeZeroCopy("Sale", {
+        state: 1,
+        round: ROUND_ID,
+        maxInvestment: new BN("1000000000000000"),
+        minInvestment: 0,
+        mainReward: 50_000_000,
+        secondaryReward: 50_000_000,
+        referralDepth: 1,
+        version: 3,
+      }),
+      owner: program.programId,
+      executable: false,
+    });
+    context.setAccount(round, {
+      lamports: LAMPORTS_PER_SOL,
+      data: encodeZeroCopy("Round", {
+        id: ROUND_ID,
+        state: 1,
+        price: 1_000_000_000,
+        endPrice: 1_000_000_000,
+        totalSupply: new BN("1000000000000000000"),
+        version: 3,
+      }),
+      owner: program.programId,
+      executable: false,
+    });
+    context.setAccount(treasury, {
+      lamports: LAMPORTS_PER_SOL,
+      data: Buffer.alloc(0),
+      owner: SystemProgram.programId,
+      executable: false,
+    });
+    context.setAccount(priceFeed, {
+      lamports: LAMPORTS_PER_SOL,
+      data: Buffer.alloc(0),
+      owner: SystemProgram.programId,
+      executable: false,
+    });
+  });
+
+  // Each deposit comes from a fresh buyer through a fresh referrer, so
+  // every `init_if_needed` account is created.
+  async function measure(
+    build: (
+      buyer: PublicKey,
+      refKey: PublicKey,
+      accounts: any
+    ) => Promise<TransactionInstruction>
+  ): Promise<number> {
+    const buyer = Keypair.generate();
+    const refKey = Keypair.generate().publicKey;
+    context.setAccount(buyer.publicKey, {
+      lamports: 100 * LAMPORTS_PER_SOL,
+      data: Buffer.alloc(0),
+      owner: SystemProgram.programId,
+      executable: false,
+    });
+
+    const referral = pda(program.programId, "REFERRAL", refKey.toBuffer());
+    const purchaseHistory = pda(
+      program.programId,
+      "PURCHASE_HISTORY",
+      buyer.publicKey.toBuffer(),
+      round.toBuffer()
+    );
+    const accounts = {
+      sale,
+      payer: buyer.publicKey,
+      round,
+      beneficiary: pda(
+        program.programId,
+        "BENEFICIARY",
+        buyer.publicKey.toBuffer()
+      ),
+      referral,
+      purchaseHistory,
+      purchaseReceipt: pda(
+        program.programId,
+        "PURCHASE_RECEIPT",
+        purchaseHistory.toBuffer(),
+        u32(0)
+      ),
+      systemProgram: SystemProgram.programId,
+    };
+
+    const ix = await build(buyer.publicKey, refKey, accounts);
+    const tx = new Transaction().add(ix);
+    tx.recentBlockhash = context.lastBlockhash;
+    tx.feePayer = buyer.publicKey;
+    tx.sign(buyer);
+
+    const { result, meta } =
+      await context.banksClient.tryProcessTransaction(tx);
+    assert.isNull(result, meta?.logMessages.join("\n"));
+    return Number(meta.computeUnitsConsumed);
+  }
+
+  it("deposit", async () => {
+    const units = await measure((_, refKey, accounts) =>
+      program.methods
+        .deposit(refKey, new BN(LAMPORTS_PER_SOL))
+        .accountsStrict({
+          ...accounts,
+          priceInfo: priceFeed,
+          treasuryInfo: treasury,
+        })
+        .instruction()
+    );
+
+    console.log(`      deposit: ${units} CU`);
+    assert.isAtMost(units, CU_CEILINGS.deposit);
+  });
+
+  for (const method of ["depositUsdc", "depositUsdt"] as const) {
+    it(method, async () => {
+      const mint = mints[method];
+      const units = await measure((buyer, refKey, accounts) => {
+        const beneficiaryAta = Keypair.generate().publicKey;
+        const treasuryAta = Keypair.generate().publicKey;
+        const referralPdaAta = Keypair.generate().publicKey;
+        context.setAccount(
+          beneficiaryAta,
+          tokenAccount(mint, buyer, 1_000_000_000)
+        );
+        context.setAccount(treasuryAta, tokenAccount(mint, treasury, 0));
+        context.setAccount(
+          referralPdaAta,
+          tokenAccount(mint, accounts.referral, 0)
+        );
+
+        return program.methods[method](refKey, new BN(100_000_000))
+          .accountsStrict({
+            ...accounts,
+            beneficiaryAta,
+            treasuryAta,
+            referralPdaAta,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .instruction();
+      });
+
+      console.log(`      ${method}: ${units} CU`);
+      assert.isAtMost(units, CU_CEILINGS[method]);
+    });
+  }
+});